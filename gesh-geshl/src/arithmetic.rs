@@ -0,0 +1,167 @@
+//! Integer arithmetic expressions.
+//!
+//! Arithmetic is used in arithmetic expansions (`$(( ... ))`), arithmetic commands
+//! (`(( ... ))`), and the `let` builtin. The syntax and precedence of operators follow C, with
+//! the addition of `**` for exponentiation.
+//!
+use std::fmt;
+
+/// An integer arithmetic expression.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Arithmetic {
+    /// An integer literal.
+    ///
+    Number(i64),
+
+    /// The value of a variable, with or without a leading `$`.
+    ///
+    Variable(String),
+
+    /// An operator applied to a single operand, like `-x` or `!x`.
+    ///
+    Unary(UnaryOperator, Box<Arithmetic>),
+
+    /// An operator applied to two operands, like `x + 1`.
+    ///
+    Binary(Box<Arithmetic>, BinaryOperator, Box<Arithmetic>),
+
+    /// Assigns a value to a variable, optionally combining it with the variable's current value
+    /// first, as in `x += 1`.
+    ///
+    Assign(String, Option<BinaryOperator>, Box<Arithmetic>),
+
+    /// Adds to a variable before using its value (`++x`, `--x`).
+    ///
+    PreIncrement(String, i64),
+
+    /// Adds to a variable after using its value (`x++`, `x--`).
+    ///
+    PostIncrement(String, i64),
+
+    /// Chooses between two values (`condition ? a : b`).
+    ///
+    Conditional(Box<Arithmetic>, Box<Arithmetic>, Box<Arithmetic>),
+}
+
+/// An operator with a single operand.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Plus,
+    Not,
+    BitNot,
+}
+
+/// An operator with two operands.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+    ShiftLeft,
+    ShiftRight,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+impl UnaryOperator {
+    /// Returns the symbol for this operator in source code.
+    ///
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOperator::Negate => "-",
+            UnaryOperator::Plus => "+",
+            UnaryOperator::Not => "!",
+            UnaryOperator::BitNot => "~",
+        }
+    }
+}
+
+impl BinaryOperator {
+    /// Returns the symbol for this operator in source code.
+    ///
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Remainder => "%",
+            BinaryOperator::Power => "**",
+            BinaryOperator::ShiftLeft => "<<",
+            BinaryOperator::ShiftRight => ">>",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEqual => ">=",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::BitAnd => "&",
+            BinaryOperator::BitXor => "^",
+            BinaryOperator::BitOr => "|",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Or => "||",
+        }
+    }
+}
+
+impl fmt::Display for Arithmetic {
+    /// Formats this expression so that it would parse back into the same expression.
+    ///
+    /// Operands that are themselves operations are wrapped in parentheses, so precedence never
+    /// needs to be considered.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arithmetic::Number(n) => write!(f, "{}", n),
+            Arithmetic::Variable(name) => write!(f, "{}", name),
+            Arithmetic::Unary(operator, operand) => write!(f, "{}{}", operator.symbol(), Operand(operand)),
+            Arithmetic::Binary(lhs, operator, rhs) => {
+                write!(f, "{} {} {}", Operand(lhs), operator.symbol(), Operand(rhs))
+            },
+            Arithmetic::Assign(name, operator, value) => {
+                write!(f, "{} {}= {}", name, operator.map_or("", BinaryOperator::symbol), value)
+            },
+            Arithmetic::PreIncrement(name, delta) => write!(f, "{}{}", increment_symbol(*delta), name),
+            Arithmetic::PostIncrement(name, delta) => write!(f, "{}{}", name, increment_symbol(*delta)),
+            Arithmetic::Conditional(condition, then, otherwise) => {
+                write!(f, "{} ? {} : {}", Operand(condition), Operand(then), Operand(otherwise))
+            },
+        }
+    }
+}
+
+/// An operand in an expression, which is parenthesized if it's more than a single value.
+///
+struct Operand<'a>(&'a Arithmetic);
+
+impl<'a> fmt::Display for Operand<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Arithmetic::Number(n) if *n < 0 => write!(f, "({})", n),
+            Arithmetic::Number(_) | Arithmetic::Variable(_) => write!(f, "{}", self.0),
+            expression => write!(f, "({})", expression),
+        }
+    }
+}
+
+/// Returns the symbol for an increment by `delta`.
+///
+fn increment_symbol(delta: i64) -> &'static str {
+    if delta < 0 { "--" } else { "++" }
+}
@@ -0,0 +1,394 @@
+//! Here-documents, whose bodies follow the line that uses them.
+//!
+//! Since a body comes after the end of its statement, and can contain anything, bodies are
+//! taken out of the input before it's parsed. Once the input has been parsed, each body is
+//! given to the `<<` redirection it belongs to, in the order they appear.
+//!
+//! Here-documents can also be used inside command and process substitutions (`$( ... )`,
+//! `<( ... )`), with their bodies after the line holding the `<<` operator, like anywhere else.
+//! Within a command, the here-documents in its words get their bodies before those of its own
+//! redirections.
+//!
+use parser;
+
+use super::{
+    Brace,
+    Command,
+    Error,
+    ExpansionOperator,
+    ParsedLine,
+    Piece,
+    Redirect,
+    Result,
+    ShellString,
+};
+
+/// The body of a here-document.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Body {
+    text: String,
+    interpolate: bool,
+}
+
+/// What `extract` is currently reading inside of.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Nesting {
+    /// A double-quoted string.
+    DoubleQuote,
+    /// Arithmetic (`$(( ... ))`, `(( ... ))`), where `<<` is a shift.
+    Arithmetic,
+    /// A command or process substitution, which is a program of its own.
+    Substitution,
+    /// Parentheses inside any of the others.
+    Parens,
+}
+
+/// A here-document whose body hasn't been read yet.
+///
+struct Pending {
+    delimiter: String,
+    strip_tabs: bool,
+    interpolate: bool,
+}
+
+/// Takes the bodies of all here-documents out of `input`, returning what's left of the input
+/// along with the bodies, in order.
+///
+/// The body of a here-document starts on the line after its `<<` operator, and ends at a line
+/// that is just the delimiter. With `<<-`, leading tabs are removed from each line of the body,
+/// and from the line with the delimiter. `Error::Incomplete` is returned if the delimiter for a
+/// body hasn't been reached by the end of the input.
+///
+pub fn extract(input: &str) -> Result<(String, Vec<Body>)> {
+    let mut output = String::with_capacity(input.len());
+    let mut bodies = Vec::new();
+    let mut pending = Vec::new();
+    let mut nesting = Vec::new();
+    let mut rest = input;
+    while let Some(c) = rest.chars().next() {
+        let inside = nesting.last().cloned();
+        let arithmetic = nesting.iter()
+            .rev()
+            .take_while(|&&n| n != Nesting::Substitution)
+            .any(|&n| n == Nesting::Arithmetic);
+        let len = match c {
+            '\\' => 1 + rest[1..].chars().next().map_or(0, char::len_utf8),
+            '$' if rest.starts_with("$((") => {
+                nesting.push(Nesting::Arithmetic);
+                3
+            },
+            '$' if rest.starts_with("$(") => {
+                nesting.push(Nesting::Substitution);
+                2
+            },
+            '"' if inside == Some(Nesting::DoubleQuote) => {
+                nesting.pop();
+                1
+            },
+            _ if inside == Some(Nesting::DoubleQuote) => c.len_utf8(),
+            '\'' => rest[1..].find('\'').map_or(rest.len(), |end| end + 2),
+            '"' => {
+                nesting.push(Nesting::DoubleQuote);
+                1
+            },
+            '#' if output.is_empty() || output.ends_with(|c: char| c.is_whitespace() || ";&|(".contains(c)) => {
+                rest.find('\n').unwrap_or(rest.len())
+            },
+            ')' if inside == Some(Nesting::Arithmetic) && rest.starts_with("))") => {
+                nesting.pop();
+                2
+            },
+            ')' if inside.is_some() => {
+                nesting.pop();
+                1
+            },
+            '(' if inside.is_some() => {
+                nesting.push(Nesting::Parens);
+                1
+            },
+            '(' if rest.starts_with("((") => {
+                nesting.push(Nesting::Arithmetic);
+                2
+            },
+            // In arithmetic, `<<` is a shift, and `<(` is a comparison
+            _ if arithmetic => c.len_utf8(),
+            '<' | '>' if rest[1..].starts_with('(') => {
+                nesting.push(Nesting::Substitution);
+                2
+            },
+            '<' if rest.starts_with("<<<") => 3,
+            '<' if rest.starts_with("<<") => {
+                let (len, here_document) = operator(rest);
+                pending.push(here_document);
+                len
+            },
+            '\n' if !pending.is_empty() => {
+                output.push('\n');
+                rest = read_bodies(&rest[1..], pending.drain(..), &mut bodies)?;
+                continue;
+            },
+            c => c.len_utf8(),
+        };
+
+        output.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+
+    if !pending.is_empty() {
+        return Err(Error::Incomplete);
+    }
+    Ok((output, bodies))
+}
+
+/// Gives each here-document redirection in `lines` its body, in order.
+///
+/// Returns `Error::Invalid` if the number of bodies doesn't match the number of here-documents.
+///
+pub fn fill(lines: &mut [ParsedLine], bodies: Vec<Body>) -> Result<()> {
+    let mut bodies = bodies.into_iter();
+    for line in lines {
+        fill_line(line, &mut bodies)?;
+    }
+
+    match bodies.next() {
+        Some(_) => Err(Error::Invalid),
+        None => Ok(()),
+    }
+}
+
+/// Gives each here-document redirection in `line` the next body from `bodies`.
+///
+fn fill_line<I: Iterator<Item = Body>>(line: &mut ParsedLine, bodies: &mut I) -> Result<()> {
+    match line {
+        ParsedLine::Command(command) => fill_command(command, bodies),
+        ParsedLine::Pipeline(pipeline) => {
+            for command in &mut pipeline.commands {
+                fill_command(command, bodies)?;
+            }
+            Ok(())
+        },
+        ParsedLine::And(lhs, rhs) | ParsedLine::Or(lhs, rhs) => {
+            fill_line(lhs, bodies)?;
+            fill_line(rhs, bodies)
+        },
+        ParsedLine::List(lines) => {
+            for line in lines {
+                fill_line(line, bodies)?;
+            }
+            Ok(())
+        },
+        ParsedLine::Background(line) => fill_line(line, bodies),
+        ParsedLine::If(clause) => {
+            for (condition, body) in &mut clause.branches {
+                fill_line(condition, bodies)?;
+                fill_line(body, bodies)?;
+            }
+            match clause.otherwise {
+                Some(ref mut otherwise) => fill_line(otherwise, bodies),
+                None => Ok(()),
+            }
+        },
+        ParsedLine::While(clause) => {
+            fill_line(&mut clause.condition, bodies)?;
+            fill_line(&mut clause.body, bodies)
+        },
+        ParsedLine::For(clause) => {
+            for word in &mut clause.words {
+                fill_string(word, bodies)?;
+            }
+            fill_line(&mut clause.body, bodies)
+        },
+        ParsedLine::Function(function) => fill_line(&mut function.body, bodies),
+        ParsedLine::SetVariables(vars) => {
+            for var in vars {
+                fill_string(&mut var.value, bodies)?;
+            }
+            Ok(())
+        },
+        ParsedLine::Empty | ParsedLine::Arithmetic(_) => Ok(()),
+    }
+}
+
+/// Gives each here-document redirection of `command`, and of the substitutions in its words,
+/// the next body from `bodies`.
+///
+fn fill_command<I: Iterator<Item = Body>>(command: &mut Command, bodies: &mut I) -> Result<()> {
+    for var in &mut command.vars {
+        fill_string(&mut var.value, bodies)?;
+    }
+    for arg in &mut command.args {
+        fill_string(arg, bodies)?;
+    }
+
+    for redirect in &mut command.redirects {
+        match redirect {
+            Redirect::HereDocument(_, body) => {
+                let Body { text, interpolate } = bodies.next().ok_or(Error::Invalid)?;
+                *body = if interpolate {
+                    parser::here_document_body(&text)
+                } else {
+                    ShellString::from(text)
+                };
+            },
+            Redirect::Read(_, word)
+            | Redirect::Write(_, word)
+            | Redirect::Append(_, word)
+            | Redirect::HereString(_, word) => fill_string(word, bodies)?,
+            Redirect::Duplicate(..) => (),
+        }
+    }
+    Ok(())
+}
+
+/// Gives each here-document redirection in the command and process substitutions of `string`
+/// the next body from `bodies`.
+///
+fn fill_string<I: Iterator<Item = Body>>(string: &mut ShellString, bodies: &mut I) -> Result<()> {
+    for piece in string.iter_mut() {
+        match piece {
+            Piece::CommandSubstitution { program, .. } | Piece::ProcessSubstitution { program, .. } => {
+                for line in program {
+                    fill_line(line, bodies)?;
+                }
+            },
+            Piece::Brace(Brace::Alternatives(items)) => {
+                for item in items {
+                    fill_string(item, bodies)?;
+                }
+            },
+            Piece::Expansion { expansion, .. } => match &mut expansion.operator {
+                ExpansionOperator::Default { word, .. }
+                | ExpansionOperator::Assign { word, .. }
+                | ExpansionOperator::Error { word, .. } => fill_string(word, bodies)?,
+                ExpansionOperator::RemovePrefix { pattern, .. }
+                | ExpansionOperator::RemoveSuffix { pattern, .. } => fill_string(pattern, bodies)?,
+                ExpansionOperator::Replace { pattern, replacement, .. } => {
+                    fill_string(pattern, bodies)?;
+                    fill_string(replacement, bodies)?;
+                },
+                ExpansionOperator::Length => (),
+            },
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Reads the `<<` or `<<-` operator at the start of `input`, along with its delimiter.
+///
+/// Returns the length of the operator and delimiter, and the here-document they start. Quotes
+/// are removed from the delimiter, and if there were any, the body isn't interpolated.
+///
+fn operator(input: &str) -> (usize, Pending) {
+    let strip_tabs = input[2..].starts_with('-');
+    let mut start = if strip_tabs { 3 } else { 2 };
+    start += input[start..].len() - input[start..].trim_start_matches([' ', '\t']).len();
+
+    let word = &input[start..];
+    let mut delimiter = String::new();
+    let mut interpolate = true;
+    let mut quote = None;
+    let mut end = 0;
+    while let Some(c) = word[end..].chars().next() {
+        match c {
+            '\\' => {
+                interpolate = false;
+                if let Some(escaped) = word[end + 1..].chars().next() {
+                    delimiter.push(escaped);
+                    end += escaped.len_utf8();
+                }
+            },
+            c if quote == Some(c) => quote = None,
+            c if quote.is_some() => delimiter.push(c),
+            '\'' | '"' => {
+                interpolate = false;
+                quote = Some(c);
+            },
+            c if c.is_whitespace() || ";&|<>()".contains(c) => break,
+            c => delimiter.push(c),
+        }
+        end += c.len_utf8();
+    }
+
+    (start + end, Pending { delimiter, strip_tabs, interpolate })
+}
+
+/// Reads the bodies of the `pending` here-documents, one after the other, from the start of
+/// `input`.
+///
+/// Returns what's left of `input` after the last delimiter.
+///
+fn read_bodies<'a, I>(mut input: &'a str, pending: I, bodies: &mut Vec<Body>) -> Result<&'a str>
+    where I: Iterator<Item = Pending>
+{
+    for Pending { delimiter, strip_tabs, interpolate } in pending {
+        let mut text = String::new();
+        loop {
+            let end = input.find('\n').ok_or(Error::Incomplete)?;
+            let mut line = &input[..end];
+            input = &input[end + 1..];
+            if strip_tabs {
+                line = line.trim_start_matches('\t');
+            }
+
+            if line == delimiter {
+                break;
+            }
+            text.push_str(line);
+            text.push('\n');
+        }
+        bodies.push(Body { text, interpolate });
+    }
+
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(text: &str, interpolate: bool) -> Body {
+        Body { text: text.to_owned(), interpolate }
+    }
+
+    #[test]
+    fn test_extract_takes_bodies_out_of_input() {
+        assert_eq!(
+            Ok((
+                "cat <<EOF | wc -l <<'END'\necho done\n".to_owned(),
+                vec![body("a\n  b ${x}\n", true), body("$c\n", false)],
+            )),
+            extract("cat <<EOF | wc -l <<'END'\na\n  b ${x}\nEOF\n$c\nEND\necho done\n")
+        );
+    }
+
+    #[test]
+    fn test_extract_strips_tabs() {
+        assert_eq!(
+            Ok(("cat <<- \"EOF\"\n".to_owned(), vec![body("a\n b\n", false)])),
+            extract("cat <<- \"EOF\"\n\ta\n\t\t b\n\tEOF\n")
+        );
+    }
+
+    #[test]
+    fn test_extract_ignores_shifts_quotes_and_here_strings() {
+        let input = "echo $((1 << (2))) '<<' \"<<\" <<< x \"$((3 << 1))\" $(echo $((1<<2))) # <<\n";
+        assert_eq!(Ok((input.to_owned(), Vec::new())), extract(input));
+    }
+
+    #[test]
+    fn test_extract_reads_bodies_inside_substitutions() {
+        assert_eq!(
+            Ok(("echo \"$(cat <<EOF\n)\" <(cat <<'END'\n)\n".to_owned(), vec![body("subst\n", true), body("b\n", false)])),
+            extract("echo \"$(cat <<EOF\nsubst\nEOF\n)\" <(cat <<'END'\nb\nEND\n)\n")
+        );
+    }
+
+    #[test]
+    fn test_extract_is_incomplete_without_delimiter() {
+        assert_eq!(Err(Error::Incomplete), extract("cat <<EOF\nsome text\n"));
+        assert_eq!(Err(Error::Incomplete), extract("cat <<EOF\n"));
+    }
+}
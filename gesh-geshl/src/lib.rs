@@ -3,22 +3,49 @@
 //! Provides facilities for taking an input line and producing a structured result which
 //! can more easily be evaluated.
 //!
+// nom's `named!` doesn't attach doc comments to the functions it generates
+#![allow(unused_doc_comments)]
+
 #[macro_use] extern crate nom;
 
+mod arithmetic;
+mod here_document;
 mod parser;
 mod strings;
 
-use std::result;
+use std::{
+    fmt,
+    result,
+};
+
+pub use arithmetic::{
+    Arithmetic,
+    BinaryOperator,
+    UnaryOperator,
+};
 
 pub use strings::{
+    Brace,
+    Direction,
+    Expansion,
+    ExpansionOperator,
     Piece,
     ShellString,
 };
 
 /// An error during parsing
 ///
-#[derive(Clone, Debug)]
-pub struct Error;
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The input ends partway through a statement, such as inside an unclosed quote or after a
+    /// trailing `|`. More input could complete it.
+    ///
+    Incomplete,
+
+    /// The input isn't valid geshl.
+    ///
+    Invalid,
+}
 
 /// Used for results form all parser functions
 ///
@@ -32,12 +59,53 @@ pub struct SetVariable {
     pub value: ShellString,
 }
 
+/// A redirection of one of a command's file descriptors.
+///
+/// `&> file` and `&>> file` are represented as a redirection of stdout, followed by a
+/// duplication of stdout onto stderr.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Redirect {
+    /// Reads from a file (`< file`, `3< file`).
+    ///
+    Read(u32, ShellString),
+
+    /// Writes to a file, truncating it first (`> file`, `2> file`).
+    ///
+    Write(u32, ShellString),
+
+    /// Appends to a file (`>> file`, `2>> file`).
+    ///
+    Append(u32, ShellString),
+
+    /// Makes the first file descriptor a copy of the second (`2>&1`, `<&3`).
+    ///
+    Duplicate(u32, u32),
+
+    /// Reads from the body of a here-document (`<<EOF`, `<<-EOF`), which is the lines after
+    /// the command up to the delimiter.
+    ///
+    HereDocument(u32, ShellString),
+
+    /// Reads from a word, followed by a newline (`<<< word`).
+    ///
+    HereString(u32, ShellString),
+}
+
 /// A command and its context.
 ///
 #[derive(Clone, Debug, PartialEq)]
 pub struct Command {
     pub vars: Vec<SetVariable>,
     pub args: Vec<ShellString>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// A sequence of commands, where the output of each command is piped into the input of the next.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
 }
 
 /// A line that has been parsed
@@ -56,12 +124,114 @@ pub enum ParsedLine {
     /// the path.
     ///
     Command(Command),
+
+    /// Two or more commands connected by pipes (`|`).
+    ///
+    Pipeline(Pipeline),
+
+    /// Runs the second line only if the first one succeeds (`&&`).
+    ///
+    And(Box<ParsedLine>, Box<ParsedLine>),
+
+    /// Runs the second line only if the first one fails (`||`).
+    ///
+    Or(Box<ParsedLine>, Box<ParsedLine>),
+
+    /// Two or more lines to run one after the other (`;`).
+    ///
+    List(Vec<ParsedLine>),
+
+    /// A line to run in the background (`&`).
+    ///
+    Background(Box<ParsedLine>),
+
+    /// A conditional (`if ...; then ...; fi`).
+    ///
+    If(If),
+
+    /// A loop that runs while a condition succeeds (`while ...; do ...; done`).
+    ///
+    While(While),
+
+    /// A loop over a list of words (`for ... in ...; do ...; done`).
+    ///
+    For(For),
+
+    /// A function definition (`name() { ...; }`).
+    ///
+    Function(Function),
+
+    /// An arithmetic command (`(( ... ))`), which succeeds if the expression isn't zero.
+    ///
+    Arithmetic(Arithmetic),
+}
+
+/// A conditional, which runs the body of the first branch whose condition succeeds.
+///
+/// # Examples
+///
+/// - `if test -f foo; then cat foo; fi`
+/// - `if a; then b; elif c; then d; else e; fi`
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct If {
+    /// Conditions, along with the body to run if the condition succeeds, in order.
+    ///
+    pub branches: Vec<(ParsedLine, ParsedLine)>,
+
+    /// The body to run if no condition succeeds.
+    ///
+    pub otherwise: Option<Box<ParsedLine>>,
+}
+
+/// A loop that runs its body for as long as its condition succeeds.
+///
+/// # Examples
+///
+/// - `while read line; do echo "${line}"; done`
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct While {
+    pub condition: Box<ParsedLine>,
+    pub body: Box<ParsedLine>,
+}
+
+/// A loop that runs its body once for every word, with the word assigned to a variable.
+///
+/// # Examples
+///
+/// - `for file in *.txt; do wc -l "${file}"; done`
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct For {
+    pub variable: String,
+    pub words: Vec<ShellString>,
+    pub body: Box<ParsedLine>,
+}
+
+/// A function definition, whose body runs whenever the function is called like a command.
+///
+/// # Examples
+///
+/// - `greet() { echo "hello ${1}"; }`
+/// - `function greet { echo "hello ${1}"; }`
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub body: Box<ParsedLine>,
 }
 
 /// A parser for geshl.
 ///
 pub struct Parser;
 
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser::new()
+    }
+}
+
 impl Parser {
     /// Constructs a new `Parser`
     ///
@@ -69,17 +239,268 @@ impl Parser {
         Parser
     }
 
-    /// Parses `line` into a structured result that can be executed by a shell.
+    /// Parses `input`, which may span multiple lines, into the statements that make it up.
+    ///
+    /// Statements are separated by newlines, although a single statement can continue over
+    /// several lines. Empty lines and comments are skipped.
+    ///
+    /// If `input` ends partway through a statement, or before the end of a here-document,
+    /// `Error::Incomplete` is returned so that the caller can ask for more input, then try again
+    /// with everything.
+    ///
+    pub fn parse(&self, input: &str) -> Result<Vec<ParsedLine>> {
+        let mut input = input.to_owned();
+        if !input.ends_with('\n') {
+            input.push('\n');
+        }
+        let (input, bodies) = here_document::extract(&input)?;
+
+        let mut statements = Vec::new();
+        let mut rest = input.as_str();
+        while !rest.is_empty() {
+            match parser::statement(rest) {
+                Ok((remaining, ParsedLine::Empty)) => rest = remaining,
+                Ok((remaining, statement)) => {
+                    statements.push(statement);
+                    rest = remaining;
+                },
+                Err(nom::Err::Incomplete(_)) => return Err(Error::Incomplete),
+                Err(nom::Err::Error(_)) => return Err(Error::Invalid),
+                Err(nom::Err::Failure(_)) => return Err(Error::Invalid),
+            }
+        }
+
+        here_document::fill(&mut statements, bodies)?;
+        Ok(statements)
+    }
+
+    /// Returns whether or not `word` is a reserved word, like `if` or `done`, which has a special
+    /// meaning at the start of a command.
     ///
-    pub fn parse(&self, mut line: String) -> Result<ParsedLine> {
-        line.push('\n');
+    pub fn is_reserved_word(&self, word: &str) -> bool {
+        parser::RESERVED_WORDS.contains(&word)
+    }
 
-        let parse_result = parser::parse_line(&line);
-        match parse_result {
-            Ok((_, parsed_line)) => Ok(parsed_line),
-            Err(nom::Err::Incomplete(_)) => Err(Error),
-            Err(nom::Err::Error(_)) => Err(Error),
-            Err(nom::Err::Failure(_)) => Err(Error),
+    /// Parses `input` as a single arithmetic expression, such as an argument to `let`.
+    ///
+    pub fn parse_arithmetic(&self, input: &str) -> Result<Arithmetic> {
+        match parser::arithmetic(input) {
+            Ok((rest, expression)) if parser::arithmetic_space(rest).map(|(rest, _)| rest.is_empty()) == Ok(true) => {
+                Ok(expression)
+            },
+            Ok(_) => Err(Error::Invalid),
+            Err(nom::Err::Incomplete(_)) => Err(Error::Incomplete),
+            Err(_) => Err(Error::Invalid),
         }
     }
 }
+
+impl fmt::Display for SetVariable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Redirect::Read(0, file) => write!(f, "< {}", file),
+            Redirect::Read(fd, file) => write!(f, "{}< {}", fd, file),
+            Redirect::Write(1, file) => write!(f, "> {}", file),
+            Redirect::Write(fd, file) => write!(f, "{}> {}", fd, file),
+            Redirect::Append(1, file) => write!(f, ">> {}", file),
+            Redirect::Append(fd, file) => write!(f, "{}>> {}", fd, file),
+            Redirect::Duplicate(0, other) => write!(f, "<&{}", other),
+            Redirect::Duplicate(fd, other) => write!(f, "{}>&{}", fd, other),
+            Redirect::HereDocument(fd, body) => {
+                // The body can't follow on the same line, so write the equivalent here-string,
+                // which adds back the final newline
+                let mut word = body.clone();
+                if word.strip_newline() {
+                    write!(f, "{}", Redirect::HereString(*fd, word))
+                } else {
+                    write!(f, "{}", Redirect::Read(*fd, ShellString::from("/dev/null")))
+                }
+            },
+            Redirect::HereString(0, word) => write!(f, "<<< {}", word),
+            Redirect::HereString(fd, word) => write!(f, "{}<<< {}", fd, word),
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let vars = self.vars.iter().map(|var| var.to_string());
+        let args = self.args.iter().map(|arg| arg.to_string());
+        let redirects = self.redirects.iter().map(|redirect| redirect.to_string());
+        write!(f, "{}", vars.chain(args).chain(redirects).collect::<Vec<_>>().join(" "))
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let commands = self.commands.iter().map(|command| command.to_string()).collect::<Vec<_>>();
+        write!(f, "{}", commands.join(" | "))
+    }
+}
+
+impl fmt::Display for ParsedLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParsedLine::Empty => Ok(()),
+            ParsedLine::SetVariables(vars) => {
+                let vars = vars.iter().map(|var| var.to_string()).collect::<Vec<_>>();
+                write!(f, "{}", vars.join(" "))
+            },
+            ParsedLine::Command(command) => write!(f, "{}", command),
+            ParsedLine::Pipeline(pipeline) => write!(f, "{}", pipeline),
+            ParsedLine::And(lhs, rhs) => write!(f, "{} && {}", lhs, rhs),
+            ParsedLine::Or(lhs, rhs) => write!(f, "{} || {}", lhs, rhs),
+            ParsedLine::List(lines) => {
+                let lines = lines.iter().map(|line| line.to_string()).collect::<Vec<_>>();
+                write!(f, "{}", lines.join("; "))
+            },
+            ParsedLine::Background(line) => write!(f, "{} &", line),
+            ParsedLine::If(if_line) => write!(f, "{}", if_line),
+            ParsedLine::While(while_line) => write!(f, "{}", while_line),
+            ParsedLine::For(for_line) => write!(f, "{}", for_line),
+            ParsedLine::Function(function) => write!(f, "{}", function),
+            ParsedLine::Arithmetic(expression) => write!(f, "(({}))", expression),
+        }
+    }
+}
+
+impl fmt::Display for If {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, (condition, body)) in self.branches.iter().enumerate() {
+            let keyword = if index == 0 { "if" } else { "elif" };
+            write!(f, "{} {}; then {}; ", keyword, condition, body)?;
+        }
+        if let Some(otherwise) = &self.otherwise {
+            write!(f, "else {}; ", otherwise)?;
+        }
+        write!(f, "fi")
+    }
+}
+
+impl fmt::Display for While {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "while {}; do {}; done", self.condition, self.body)
+    }
+}
+
+impl fmt::Display for For {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "for {} in", self.variable)?;
+        for word in &self.words {
+            write!(f, " {}", word)?;
+        }
+        write!(f, "; do {}; done", self.body)
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}() {{ {}; }}", self.name, self.body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_renders_parsed_line_as_source() {
+        let source = "FOO=bar a 'b c' \"${HOME}\"/* 2>&1 | d >> out && e; f &";
+        let statements = Parser::new().parse(source).expect("should parse");
+
+        assert_eq!(1, statements.len());
+        assert_eq!(source, statements[0].to_string());
+    }
+
+    #[test]
+    fn test_display_renders_control_flow_as_source() {
+        let source = "if a; then b; elif c; then d; e; else f; fi && while g; do for x in y *; do h; done; done";
+        let statements = Parser::new().parse(source).expect("should parse");
+
+        assert_eq!(source, statements[0].to_string());
+    }
+
+    #[test]
+    fn test_display_renders_function_as_source() {
+        let source = "greet() { echo hello; return 1; }";
+        let statements = Parser::new().parse(source).expect("should parse");
+
+        assert_eq!(source, statements[0].to_string());
+    }
+
+    #[test]
+    fn test_display_renders_arithmetic_as_source() {
+        let statements = Parser::new().parse("((x += 2 ** -y % 3)) && echo $(( a < b ? ++i : (j--) ))").expect("should parse");
+
+        assert_eq!("((x += (2 ** (-y)) % 3)) && echo $(((a < b) ? (++i) : (j--)))", statements[0].to_string());
+    }
+
+    #[test]
+    fn test_parse_arithmetic_parses_whole_input() {
+        let parser = Parser::new();
+        assert_eq!(
+            Ok(Arithmetic::Assign("x".to_owned(), None, Box::new(Arithmetic::Number(1)))),
+            parser.parse_arithmetic(" x = 1 ")
+        );
+        assert_eq!(Err(Error::Invalid), parser.parse_arithmetic("1 2"));
+        assert_eq!(Err(Error::Incomplete), parser.parse_arithmetic("1 +"));
+    }
+
+    #[test]
+    fn test_parse_fills_in_here_documents() {
+        let source = "if true; then\n  cat <<EOF | tr <<-'END' a b\n${x}\nEOF\n\t$y\n\tEND\nfi\ncat <<EOF\nEOF\n";
+        let statements = Parser::new().parse(source).expect("should parse");
+
+        let rendered = statements.iter().map(|statement| statement.to_string()).collect::<Vec<_>>();
+        assert_eq!(vec!["if true; then cat <<< \"${x}\" | tr a b <<< '$y'; fi", "cat < /dev/null"], rendered);
+        assert_eq!(Err(Error::Incomplete), Parser::new().parse("cat <<EOF\nabc\n"));
+    }
+
+    #[test]
+    fn test_parse_fills_in_here_documents_inside_substitutions() {
+        let source = "echo \"$(cat <<EOF\nsubst\nEOF\n)\" $(cat <<A; cat <<B\na\nA\nb\nB\n)\n";
+        let statements = Parser::new().parse(source).expect("should parse");
+
+        let rendered = statements.iter().map(|statement| statement.to_string()).collect::<Vec<_>>();
+        assert_eq!(vec!["echo \"$(cat <<< subst)\" $(cat <<< a; cat <<< b)"], rendered);
+        assert_eq!(Err(Error::Incomplete), Parser::new().parse("echo \"$(cat <<EOF\n)\"\n"));
+    }
+
+    #[test]
+    fn test_parse_rejects_unparsed_trailing_input() {
+        assert_eq!(Err(Error::Invalid), Parser::new().parse("echo a; )("));
+    }
+
+    #[test]
+    fn test_parse_parses_each_line_of_a_program() {
+        let source = "#!/usr/bin/env gesh\n\nFOO=bar\n  a | b  # comment\nc &&\n  d\n";
+        let statements = Parser::new().parse(source).expect("should parse");
+
+        let rendered = statements.iter().map(|statement| statement.to_string()).collect::<Vec<_>>();
+        assert_eq!(vec!["FOO=bar", "a | b", "c && d"], rendered);
+    }
+
+    #[test]
+    fn test_parse_joins_lines_ending_in_a_backslash() {
+        let statements = Parser::new().parse("echo a \\\n  b \"c\\\nd\"").expect("should parse");
+        assert_eq!("echo a b cd", statements[0].to_string());
+    }
+
+    #[test]
+    fn test_parse_needs_more_input_for_unfinished_statements() {
+        let parser = Parser::new();
+        assert_eq!(Err(Error::Incomplete), parser.parse("echo 'abc"));
+        assert_eq!(Err(Error::Incomplete), parser.parse("echo \"abc"));
+        assert_eq!(Err(Error::Incomplete), parser.parse("echo abc \\"));
+        assert_eq!(Err(Error::Incomplete), parser.parse("ls |"));
+        assert_eq!(Err(Error::Incomplete), parser.parse("ls &&\n"));
+        assert_eq!(Err(Error::Incomplete), parser.parse("ls ||  # comment"));
+        assert_eq!(Err(Error::Incomplete), parser.parse("greet() {\n  echo hello\n"));
+    }
+}
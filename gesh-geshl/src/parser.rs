@@ -7,6 +7,7 @@
 //!
 use nom::{
     self,
+    digit,
     Context,
     InputTakeAtPosition,
     IResult,
@@ -14,29 +15,348 @@ use nom::{
 };
 
 use std::{
-    ffi::OsString,
-    path::{
-        self,
-        PathBuf,
-    },
+    str::FromStr,
+    path,
 };
 
 use super::{
+    Arithmetic,
+    BinaryOperator,
+    Brace,
     Command,
+    Direction,
+    Expansion,
+    ExpansionOperator,
+    For,
+    Function,
+    If,
     ParsedLine,
     Piece,
+    Pipeline,
+    Redirect,
     SetVariable,
     ShellString,
+    UnaryOperator,
+    While,
 };
 
+/// Words that have a special meaning at the start of a command.
+///
+pub const RESERVED_WORDS: [&str; 11] = [
+    "do", "done", "elif", "else", "fi", "for", "function", "if", "in", "then", "while",
+];
+
+/// Operators in arithmetic expressions, with longer operators before any of their prefixes.
+///
+const ARITHMETIC_OPERATORS: [&str; 38] = [
+    "<<=", ">>=", "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=", "*=", "/=", "%=",
+    "&=", "^=", "|=", "++", "--", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~", "=",
+    "?", ":", "(", ")",
+];
+
+/// Binary operators in arithmetic expressions, from lowest to highest precedence.
+///
+/// Exponentiation (`**`) binds more tightly than all of these, and is handled separately since
+/// it's right associative.
+///
+const ARITHMETIC_PRECEDENCE: [&[(&str, BinaryOperator)]; 10] = [
+    &[("||", BinaryOperator::Or)],
+    &[("&&", BinaryOperator::And)],
+    &[("|", BinaryOperator::BitOr)],
+    &[("^", BinaryOperator::BitXor)],
+    &[("&", BinaryOperator::BitAnd)],
+    &[("==", BinaryOperator::Equal), ("!=", BinaryOperator::NotEqual)],
+    &[
+        ("<", BinaryOperator::Less),
+        ("<=", BinaryOperator::LessEqual),
+        (">", BinaryOperator::Greater),
+        (">=", BinaryOperator::GreaterEqual),
+    ],
+    &[("<<", BinaryOperator::ShiftLeft), (">>", BinaryOperator::ShiftRight)],
+    &[("+", BinaryOperator::Add), ("-", BinaryOperator::Subtract)],
+    &[("*", BinaryOperator::Multiply), ("/", BinaryOperator::Divide), ("%", BinaryOperator::Remainder)],
+];
+
+/// A word in a command, which is either an argument or redirections.
+///
+enum Word {
+    Arg(ShellString),
+    Redirects(Vec<Redirect>),
+}
+
+/// Parses a single statement, including the newline that ends it.
+///
+named!(
+    pub statement(&str) -> ParsedLine,
+    terminated!(parse_line, char!('\n'))
+);
+
 /// Parses an arbitrary line.
 ///
+/// Lines may be indented, and may end with a comment. A line with nothing but a comment, like
+/// the `#!/usr/bin/env gesh` at the top of a script, is empty.
+///
 named!(
     pub parse_line(&str) -> ParsedLine,
+    delimited!(
+        space,
+        alt!(
+            list
+            | peek!(one_of!("#\n")) => { |_| ParsedLine::Empty }
+        ),
+        preceded!(space, opt!(comment))
+    )
+);
+
+/// Parses a comment, which runs until the end of the line.
+///
+named!(
+    comment(&str) -> &str,
+    preceded!(char!('#'), take_till!(|c| c == '\n'))
+);
+
+/// Parses one or more lines, each terminated by a semicolon or an ampersand. The terminator
+/// is optional for the last line.
+///
+/// Lines terminated by an ampersand are run in the background. A single line that runs in the
+/// foreground is returned as is.
+///
+/// # Examples
+///
+/// - `command1; command2`
+/// - `command1 && command2; command3 | command4;`
+/// - `command1 & command2 &`
+///
+named!(
+    list(&str) -> ParsedLine,
+    map!(
+        many1!(pair!(and_or, opt!(terminator))),
+        |items| {
+            let mut lines = items.into_iter()
+                .map(|(line, terminator)| {
+                    if terminator == Some('&') {
+                        ParsedLine::Background(Box::new(line))
+                    } else {
+                        line
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            if lines.len() == 1 {
+                lines.remove(0)
+            } else {
+                ParsedLine::List(lines)
+            }
+        }
+    )
+);
+
+/// Parses a semicolon or ampersand that terminates a line, along with any surrounding space.
+///
+named!(
+    terminator(&str) -> char,
+    delimited!(
+        space,
+        alt!(
+            char!(';')
+            | terminated!(char!('&'), not!(one_of!("&>")))
+        ),
+        space
+    )
+);
+
+/// Parses one or more pipelines joined by `&&` or `||`.
+///
+/// Both operators have the same precedence and are left associative, so `a || b && c` is
+/// equivalent to `(a || b) && c`.
+///
+named!(
+    and_or(&str) -> ParsedLine,
+    do_parse!(
+        first: simple_line
+        >> rest: many0!(pair!(logical_operator, simple_line))
+        >> (rest.into_iter().fold(first, |lhs, (operator, rhs)| {
+            if operator == "&&" {
+                ParsedLine::And(Box::new(lhs), Box::new(rhs))
+            } else {
+                ParsedLine::Or(Box::new(lhs), Box::new(rhs))
+            }
+        }))
+    )
+);
+
+/// Parses a `&&` or `||`, along with any surrounding space.
+///
+/// The next pipeline can start on a new line.
+///
+named!(
+    logical_operator(&str) -> &str,
+    delimited!(space, alt!(tag!("&&") | tag!("||")), linebreak)
+);
+
+/// Parses a control flow construct, a function definition, an arithmetic command, a pipeline, or
+/// variable assignments.
+///
+named!(
+    simple_line(&str) -> ParsedLine,
+    alt!(
+        arithmetic_command
+        | if_clause
+        | while_clause
+        | for_clause
+        | function_definition
+        | pipeline
+        | set_variables => { ParsedLine::SetVariables }
+    )
+);
+
+/// Parses a conditional, with any number of `elif` branches and an optional `else`.
+///
+/// # Examples
+///
+/// - `if test -f foo; then cat foo; fi`
+/// - `if a; then b; elif c; then d; else e; fi`
+///
+named!(
+    if_clause(&str) -> ParsedLine,
+    do_parse!(
+        call!(keyword, "if")
+        >> condition: compound_list
+        >> preceded!(linebreak, call!(keyword, "then"))
+        >> body: compound_list
+        >> elifs: many0!(do_parse!(
+            preceded!(linebreak, call!(keyword, "elif"))
+            >> condition: compound_list
+            >> preceded!(linebreak, call!(keyword, "then"))
+            >> body: compound_list
+            >> ((condition, body))
+        ))
+        >> otherwise: opt!(preceded!(preceded!(linebreak, call!(keyword, "else")), compound_list))
+        >> preceded!(linebreak, call!(keyword, "fi"))
+        >> ({
+            let mut branches = vec![(condition, body)];
+            branches.extend(elifs);
+            ParsedLine::If(If { branches, otherwise: otherwise.map(Box::new) })
+        })
+    )
+);
+
+/// Parses a loop that runs while its condition succeeds.
+///
+/// # Examples
+///
+/// - `while true; do echo y; done`
+///
+named!(
+    while_clause(&str) -> ParsedLine,
+    do_parse!(
+        call!(keyword, "while")
+        >> condition: compound_list
+        >> preceded!(linebreak, call!(keyword, "do"))
+        >> body: compound_list
+        >> preceded!(linebreak, call!(keyword, "done"))
+        >> (ParsedLine::While(While { condition: Box::new(condition), body: Box::new(body) }))
+    )
+);
+
+/// Parses a loop over a list of words.
+///
+/// # Examples
+///
+/// - `for file in *.txt; do wc -l "${file}"; done`
+///
+named!(
+    for_clause(&str) -> ParsedLine,
+    do_parse!(
+        call!(keyword, "for")
+        >> space
+        >> variable: env_var
+        >> call!(keyword, "in")
+        >> words: many0!(preceded!(space, piece))
+        >> separator
+        >> preceded!(linebreak, call!(keyword, "do"))
+        >> body: compound_list
+        >> preceded!(linebreak, call!(keyword, "done"))
+        >> (ParsedLine::For(For { variable: variable.to_owned(), words, body: Box::new(body) }))
+    )
+);
+
+/// Parses an arithmetic command, which succeeds if its expression isn't zero.
+///
+/// # Examples
+///
+/// - `(( i < 10 ))`
+/// - `(( total += size ))`
+///
+named!(
+    arithmetic_command(&str) -> ParsedLine,
+    map!(
+        delimited!(tag!("(("), arithmetic, preceded!(arithmetic_space, tag!("))"))),
+        ParsedLine::Arithmetic
+    )
+);
+
+/// Parses a function definition, with its body in braces.
+///
+/// # Examples
+///
+/// - `greet() { echo "hello ${1}"; }`
+/// - `function greet { echo "hello ${1}"; }`
+///
+named!(
+    function_definition(&str) -> ParsedLine,
+    do_parse!(
+        name: alt!(
+            preceded!(
+                call!(keyword, "function"),
+                delimited!(space, function_name, opt!(preceded!(space, tag!("()"))))
+            )
+            | terminated!(function_name, preceded!(space, tag!("()")))
+        )
+        >> preceded!(linebreak, char!('{'))
+        >> body: compound_list
+        >> preceded!(linebreak, char!('}'))
+        >> (ParsedLine::Function(Function { name: name.to_owned(), body: Box::new(body) }))
+    )
+);
+
+/// Parses the statements that make up the body of a control flow construct.
+///
+/// Every statement must be followed by a semicolon, an ampersand, or a newline. The body ends
+/// at the reserved word that follows it, like `then` or `done`.
+///
+named!(
+    compound_list(&str) -> ParsedLine,
+    map!(
+        many1!(preceded!(linebreak, pair!(and_or, separator))),
+        |items| {
+            let mut lines = items.into_iter()
+                .map(|(line, separator)| {
+                    if separator == '&' {
+                        ParsedLine::Background(Box::new(line))
+                    } else {
+                        line
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            if lines.len() == 1 {
+                lines.remove(0)
+            } else {
+                ParsedLine::List(lines)
+            }
+        }
+    )
+);
+
+/// Parses the end of a statement in the body of a control flow construct, which is either a
+/// terminator or the end of the line.
+///
+named!(
+    separator(&str) -> char,
     alt!(
-        command
-        | set_variables => { |vars| ParsedLine::SetVariables(vars) }
-        | char!('\n') => { |_| ParsedLine::Empty }
+        terminator
+        | delimited!(space, opt!(comment), char!('\n')) => { |_| '\n' }
     )
 );
 
@@ -74,25 +394,164 @@ named!(
     )
 );
 
+/// Parses one or more commands separated by pipes.
+///
+/// A single command is returned as `ParsedLine::Command`.
+///
+/// # Examples
+///
+/// - `command`
+/// - `command1 | command2 arg | command3`
+///
+named!(
+    pipeline(&str) -> ParsedLine,
+    map!(
+        separated_nonempty_list!(pipe, command),
+        |mut commands| {
+            if commands.len() == 1 {
+                ParsedLine::Command(commands.remove(0))
+            } else {
+                ParsedLine::Pipeline(Pipeline { commands })
+            }
+        }
+    )
+);
+
+/// Parses a pipe between two commands, along with any surrounding space.
+///
+/// The next command can start on a new line.
+///
+named!(
+    pipe(&str) -> char,
+    delimited!(space, terminated!(char!('|'), not!(char!('|'))), linebreak)
+);
+
 /// Parses a command prefixed with zero or more environment variables to set.
 ///
+/// Redirections can appear anywhere after the variables.
+///
 /// # Examples
 ///
 /// - `FOO=bar command`
 /// - `FOO=bar BAR=spam command arg1 "arg2 in quotes"
+/// - `command arg1 > out.txt 2>&1`
 ///
 named!(
-    command(&str) -> ParsedLine,
+    command(&str) -> Command,
     sep!(
         space,
         do_parse!(
-            vars: many0!(set_variable)
-            >> args: many1!(piece)
-            >> (ParsedLine::Command(Command { vars, args }))
+            not!(reserved_word)
+            >> vars: many0!(set_variable)
+            >> words: many1!(word)
+            >> ({
+                let mut args = Vec::new();
+                let mut redirects = Vec::new();
+                for word in words {
+                    match word {
+                        Word::Arg(arg) => args.push(arg),
+                        Word::Redirects(more) => redirects.extend(more),
+                    }
+                }
+                Command { vars, args, redirects }
+            })
+        )
+    )
+);
+
+/// Parses a single word of a command.
+///
+named!(
+    word(&str) -> Word,
+    alt!(
+        redirect => { Word::Redirects }
+        | piece => { Word::Arg }
+    )
+);
+
+/// Parses a file redirection.
+///
+/// The body of a here-document isn't part of its redirection, and is filled in separately.
+///
+/// # Examples
+///
+/// - `< in.txt`
+/// - `> out.txt`
+/// - `2>> errors.log`
+/// - `2>&1`
+/// - `&> all.txt`
+/// - `<<EOF`, `<<-'EOF'`
+/// - `<<< "${text}"`
+///
+named!(
+    redirect(&str) -> Vec<Redirect>,
+    alt!(
+        preceded!(tag!("&>>"), redirect_target) => {
+            |target| vec![Redirect::Append(1, target), Redirect::Duplicate(2, 1)]
+        }
+        | preceded!(tag!("&>"), redirect_target) => {
+            |target| vec![Redirect::Write(1, target), Redirect::Duplicate(2, 1)]
+        }
+        | do_parse!(
+            fd: opt!(file_descriptor)
+            >> tag!(">&")
+            >> other: file_descriptor
+            >> (vec![Redirect::Duplicate(fd.unwrap_or(1), other)])
+        )
+        | do_parse!(
+            fd: opt!(file_descriptor)
+            >> tag!("<<<")
+            >> word: redirect_target
+            >> (vec![Redirect::HereString(fd.unwrap_or(0), word)])
+        )
+        | do_parse!(
+            fd: opt!(file_descriptor)
+            >> tag!("<<")
+            >> opt!(char!('-'))
+            >> redirect_target
+            >> (vec![Redirect::HereDocument(fd.unwrap_or(0), ShellString::from(Vec::new()))])
+        )
+        | do_parse!(
+            fd: opt!(file_descriptor)
+            >> tag!("<&")
+            >> other: file_descriptor
+            >> (vec![Redirect::Duplicate(fd.unwrap_or(0), other)])
+        )
+        | do_parse!(
+            fd: opt!(file_descriptor)
+            >> tag!(">>")
+            >> target: redirect_target
+            >> (vec![Redirect::Append(fd.unwrap_or(1), target)])
+        )
+        | do_parse!(
+            fd: opt!(file_descriptor)
+            >> char!('>')
+            >> target: redirect_target
+            >> (vec![Redirect::Write(fd.unwrap_or(1), target)])
+        )
+        | do_parse!(
+            fd: opt!(file_descriptor)
+            >> char!('<')
+            >> target: redirect_target
+            >> (vec![Redirect::Read(fd.unwrap_or(0), target)])
         )
     )
 );
 
+/// Parses the file that is the target of a redirection.
+///
+named!(
+    redirect_target(&str) -> ShellString,
+    preceded!(space, piece)
+);
+
+/// Parses a file descriptor number.
+///
+named!(
+    file_descriptor(&str) -> u32,
+    map_res!(digit, u32::from_str)
+);
+
 /// Parses a string "piece".
 ///
 /// A piece could be a path, a glob, an unquoted string, an interpolated string, and so on.
@@ -100,17 +559,111 @@ named!(
 named!(
     piece(&str) -> ShellString,
     fold_many1!(
-        alt!(
-            path
-            | glob
-            | interpolated_string
-            | uninterpolated_string
+        piece_part,
+        ShellString::from(Vec::new()),
+        |acc, string| acc + string
+    )
+);
+
+/// Parses one of the parts that are joined together to make a piece.
+///
+named!(
+    piece_part(&str) -> ShellString,
+    alt!(
+        path
+        | brace => { ShellString::from }
+        | glob
+        | interpolated_string
+        | uninterpolated_string
+        | arithmetic_expansion => { ShellString::from }
+        | command_substitution => { ShellString::from }
+        | process_substitution => { ShellString::from }
+        | interpolated_env_var => { ShellString::from }
+        | tag!("$") => { ShellString::from }
+    )
+);
+
+/// Parses a brace expansion.
+///
+/// Braces need at least two comma-separated alternatives, or a sequence.
+///
+/// ## Examples
+///
+/// - `{rs,bak}`
+/// - `{a,b{1,2},}`
+/// - `{1..10..2}`
+/// - `{01..10}`
+/// - `{a..z}`
+///
+named!(
+    brace(&str) -> Piece,
+    map!(
+        delimited!(
+            char!('{'),
+            alt!(
+                brace_sequence
+                | do_parse!(
+                    first: brace_item
+                    >> rest: many1!(preceded!(char!(','), brace_item))
+                    >> ({
+                        let mut items = vec![first];
+                        items.extend(rest);
+                        Brace::Alternatives(items)
+                    })
+                )
+            ),
+            char!('}')
         ),
+        Piece::Brace
+    )
+);
+
+/// Parses one of the alternatives in a brace expansion, which may be empty.
+///
+named!(
+    brace_item(&str) -> ShellString,
+    fold_many0!(
+        piece_part,
         ShellString::from(Vec::new()),
         |acc, string| acc + string
     )
 );
 
+/// Parses the inside of a sequence brace expansion, whose endpoints are both integers or both
+/// letters.
+///
+named!(
+    brace_sequence(&str) -> Brace,
+    do_parse!(
+        endpoints: alt!(
+            separated_pair!(brace_integer, tag!(".."), brace_integer)
+            | separated_pair!(brace_letter, tag!(".."), brace_letter)
+        )
+        >> step: opt!(preceded!(tag!(".."), map_res!(brace_integer, i64::from_str)))
+        >> (Brace::Sequence { start: endpoints.0.to_owned(), end: endpoints.1.to_owned(), step })
+    )
+);
+
+/// Parses an optionally negative integer in a sequence brace expansion.
+///
+fn brace_integer(input: &str) -> IResult<&str, &str> {
+    let (rest, integer) = recognize!(input, pair!(opt!(char!('-')), digit))?;
+    match integer.parse::<i64>() {
+        Ok(_) => Ok((rest, integer)),
+        Err(_) => Err(nom::Err::Error(Context::Code(input, nom::ErrorKind::Digit))),
+    }
+}
+
+/// Parses a single letter in a sequence brace expansion.
+///
+fn brace_letter(input: &str) -> IResult<&str, &str> {
+    match input.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => Ok((&input[1..], &input[..1])),
+        Some(_) => Err(nom::Err::Error(Context::Code(input, nom::ErrorKind::Alpha))),
+        None => Err(nom::Err::Incomplete(Needed::Size(1))),
+    }
+}
+
 /// Parses a glob
 ///
 /// A piece could be a path, a glob, an unquoted string, an interpolated string, and so on.
@@ -119,17 +672,44 @@ named!(
     glob(&str) -> ShellString,
     map!(
         alt!(
-            tag!("?") => { |v| String::from(v) }
-            | tag!("**") => { |v| String::from(v) }
-            | tag!("*") => { |v| String::from(v) }
-            | tag!("[]]") => { |v| String::from(v) }
-            | tag!("[!]]") => { |v| String::from(v) }
+            pattern_group => { String::from }
+            | tag!("?") => { String::from }
+            | tag!("**") => { String::from }
+            | tag!("*") => { String::from }
+            | tag!("[]]") => { String::from }
+            | tag!("[!]]") => { String::from }
             | delimited!(char!('['), is_not!("]"), char!(']')) => { |v| format!("[{}]", v) }
         ),
         |v| ShellString::from(Piece::Glob(v))
     )
 );
 
+/// Parses an extended glob pattern group, like `!(*.o)` or `@(foo|bar)`.
+///
+/// Groups may be nested, but can't contain whitespace or other characters that end a word.
+///
+fn pattern_group(input: &str) -> IResult<&str, &str> {
+    let mut chars = input.char_indices();
+    match (chars.next(), chars.next()) {
+        (Some((_, c)), Some((_, '('))) if "?*+@!".contains(c) => (),
+        (Some((_, c)), None) if "?*+@!".contains(c) => return Err(nom::Err::Incomplete(Needed::Size(2))),
+        _ => return Err(nom::Err::Error(Context::Code(input, nom::ErrorKind::Tag))),
+    }
+
+    let mut depth = 1;
+    for (index, c) in chars {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 1 => return Ok((&input[index + 1..], &input[..index + 1])),
+            ')' => depth -= 1,
+            c if c.is_whitespace() || ";&<>\"'$".contains(c) => break,
+            _ => (),
+        }
+    }
+
+    Err(nom::Err::Error(Context::Code(input, nom::ErrorKind::Tag)))
+}
+
 /// Parses a path-like component.
 ///
 /// ## Examples
@@ -140,30 +720,51 @@ named!(
 /// - `./current/path`
 /// - `../parent/`
 /// - `~/home/directory`
+/// - `~user/directory`
 ///
 named!(
     path(&str) -> ShellString,
-    map!(
-        take_while1!(is_path_character),
-        |path| {
-            if path.starts_with('~') {
-                // TODO ~foo should reference the home dir of "foo"
-                let full_buf = PathBuf::from(path);
-                let mut components = full_buf.components();
-                components.next();
-                let buf = components.as_path().to_path_buf();
-
-                ShellString::from(vec![
-                    Piece::Variable("HOME".to_owned()),
-                    Piece::from(format!("/{}", OsString::from(buf).to_string_lossy().into_owned())),
-                ])
-            } else {
-                ShellString::from(path)
-            }
-        }
+    alt!(
+        do_parse!(
+            char!('~')
+            >> prefix: take_while!(is_tilde_prefix_character)
+            >> rest: take_while!(is_path_character)
+            >> ({
+                let tilde = ShellString::from(Piece::Tilde(prefix.to_owned()));
+                if rest.is_empty() {
+                    tilde
+                } else {
+                    tilde + ShellString::from(rest)
+                }
+            })
+        )
+        | map!(path_characters, ShellString::from)
     )
 );
 
+/// Takes the characters of a path, along with any `+` that doesn't start a pattern group like
+/// `+(a|b)`, so that words like `+2` and `%+` can be used as arguments.
+///
+fn path_characters(input: &str) -> IResult<&str, &str> {
+    let mut chars = input.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let is_plus = c == '+' && match chars.peek() {
+            Some(&(_, next)) => next != '(',
+            None => return Err(nom::Err::Incomplete(Needed::Size(1))),
+        };
+
+        if !is_path_character(c) && !is_plus {
+            return if index == 0 {
+                Err(nom::Err::Error(Context::Code(input, nom::ErrorKind::TakeWhile1)))
+            } else {
+                Ok((&input[index..], &input[..index]))
+            };
+        }
+    }
+
+    Err(nom::Err::Incomplete(Needed::Size(1)))
+}
+
 /// Parses an interpolated string from the command line.
 ///
 /// ## Examples
@@ -171,37 +772,538 @@ named!(
 /// - `"just some text"`
 /// - `"some text with an ${ENVVAR} interpolated"`
 ///
+/// An empty string still produces an empty piece, so that it becomes an empty word.
+///
 named!(
     interpolated_string(&str) -> ShellString,
     map!(
         delimited!(
             char!('"'),
-            many_till!(alt!(interpolated_env_var | fixed_string), peek!(char!('"'))),
+            many_till!(
+                alt!(
+                    arithmetic_expansion
+                    | command_substitution => { quoted }
+                    | interpolated_env_var => { quoted }
+                    | tag!("$") => { Piece::from }
+                    | fixed_string
+                ),
+                peek!(char!('"'))
+            ),
             char!('"')
         ),
-        |v| ShellString::from(v.0)
+        |v| if v.0.is_empty() { ShellString::from("") } else { ShellString::from(v.0) }
+    )
+);
+
+/// Parses an arithmetic expansion, which is replaced by the value of the expression inside it.
+///
+/// ## Examples
+///
+/// - `$((1 + 2))`
+/// - `$(( count * 2 ))`
+///
+named!(
+    arithmetic_expansion(&str) -> Piece,
+    map!(
+        delimited!(tag!("$(("), arithmetic, preceded!(arithmetic_space, tag!("))"))),
+        Piece::Arithmetic
     )
 );
 
+/// Parses an arithmetic expression.
+///
+/// The expression ends at the first token that can't continue it, such as the `))` that closes
+/// an arithmetic expansion. Whitespace, including newlines, can appear between tokens.
+///
+pub fn arithmetic(input: &str) -> IResult<&str, Arithmetic> {
+    arithmetic_assignment(input)
+}
+
+/// Parses an assignment, which is right associative, or any expression with higher precedence.
+///
+fn arithmetic_assignment(input: &str) -> IResult<&str, Arithmetic> {
+    let (start, _) = arithmetic_space(input)?;
+    if let Ok((rest, name)) = arithmetic_name(start) {
+        let operator = match arithmetic_operator(rest) {
+            Ok((after, "=")) => Some((after, None)),
+            Ok((after, operator)) if operator.len() > 1 && operator.ends_with('=') => {
+                let operator = &operator[..operator.len() - 1];
+                ARITHMETIC_PRECEDENCE.iter()
+                    .flat_map(|level| level.iter())
+                    .find(|(symbol, _)| *symbol == operator)
+                    .map(|(_, operator)| (after, Some(*operator)))
+            },
+            _ => None,
+        };
+
+        if let Some((after, operator)) = operator {
+            let (rest, value) = arithmetic_assignment(after)?;
+            return Ok((rest, Arithmetic::Assign(name.to_owned(), operator, Box::new(value))));
+        }
+    }
+
+    arithmetic_conditional(input)
+}
+
+/// Parses a conditional expression (`a ? b : c`), or any expression with higher precedence.
+///
+fn arithmetic_conditional(input: &str) -> IResult<&str, Arithmetic> {
+    let (rest, condition) = arithmetic_binary(input, 0)?;
+    match arithmetic_operator(rest) {
+        Ok((after, "?")) => {
+            let (rest, then) = arithmetic_assignment(after)?;
+            let rest = match arithmetic_operator(rest)? {
+                (rest, ":") => rest,
+                _ => return Err(arithmetic_error(rest)),
+            };
+            let (rest, otherwise) = arithmetic_conditional(rest)?;
+            Ok((rest, Arithmetic::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise))))
+        },
+        _ => Ok((rest, condition)),
+    }
+}
+
+/// Parses a left associative binary operation at the given precedence `level`, or any expression
+/// with higher precedence.
+///
+fn arithmetic_binary(input: &str, level: usize) -> IResult<&str, Arithmetic> {
+    if level == ARITHMETIC_PRECEDENCE.len() {
+        return arithmetic_power(input);
+    }
+
+    let (mut rest, mut lhs) = arithmetic_binary(input, level + 1)?;
+    loop {
+        let (after, operator) = match arithmetic_operator(rest) {
+            Ok((after, symbol)) => match ARITHMETIC_PRECEDENCE[level].iter().find(|(s, _)| *s == symbol) {
+                Some((_, operator)) => (after, *operator),
+                None => return Ok((rest, lhs)),
+            },
+            Err(_) => return Ok((rest, lhs)),
+        };
+
+        let (remaining, rhs) = arithmetic_binary(after, level + 1)?;
+        lhs = Arithmetic::Binary(Box::new(lhs), operator, Box::new(rhs));
+        rest = remaining;
+    }
+}
+
+/// Parses an exponentiation, which is right associative, or any expression with higher
+/// precedence.
+///
+fn arithmetic_power(input: &str) -> IResult<&str, Arithmetic> {
+    let (rest, base) = arithmetic_unary(input)?;
+    match arithmetic_operator(rest) {
+        Ok((after, "**")) => {
+            let (rest, exponent) = arithmetic_power(after)?;
+            Ok((rest, Arithmetic::Binary(Box::new(base), BinaryOperator::Power, Box::new(exponent))))
+        },
+        _ => Ok((rest, base)),
+    }
+}
+
+/// Parses a unary operation, or a value.
+///
+fn arithmetic_unary(input: &str) -> IResult<&str, Arithmetic> {
+    let unary = |operator, rest| {
+        arithmetic_unary(rest).map(|(rest, operand)| (rest, Arithmetic::Unary(operator, Box::new(operand))))
+    };
+
+    match arithmetic_operator(input) {
+        Ok((rest, "-")) => unary(UnaryOperator::Negate, rest),
+        Ok((rest, "+")) => unary(UnaryOperator::Plus, rest),
+        Ok((rest, "!")) => unary(UnaryOperator::Not, rest),
+        Ok((rest, "~")) => unary(UnaryOperator::BitNot, rest),
+        Ok((rest, operator)) if operator == "++" || operator == "--" => {
+            let (rest, _) = arithmetic_space(rest)?;
+            let (rest, name) = arithmetic_name(rest)?;
+            let delta = if operator == "++" { 1 } else { -1 };
+            Ok((rest, Arithmetic::PreIncrement(name.to_owned(), delta)))
+        },
+        Err(nom::Err::Incomplete(needed)) => Err(nom::Err::Incomplete(needed)),
+        _ => arithmetic_value(input),
+    }
+}
+
+/// Parses a number, a variable, or a parenthesized expression.
+///
+/// Numbers starting with `0x` are hexadecimal, and other numbers starting with `0` are octal.
+/// Variables can be followed by `++` or `--`.
+///
+fn arithmetic_value(input: &str) -> IResult<&str, Arithmetic> {
+    let (input, _) = arithmetic_space(input)?;
+    match input.chars().next() {
+        None => Err(nom::Err::Incomplete(Needed::Unknown)),
+        Some('(') => {
+            let (rest, expression) = arithmetic_assignment(&input[1..])?;
+            match arithmetic_operator(rest)? {
+                (rest, ")") => Ok((rest, expression)),
+                _ => Err(arithmetic_error(rest)),
+            }
+        },
+        Some(c) if c.is_ascii_digit() => {
+            let end = input.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(input.len());
+            let literal = &input[..end];
+            let number = if literal.starts_with("0x") || literal.starts_with("0X") {
+                i64::from_str_radix(&literal[2..], 16)
+            } else if literal.len() > 1 && literal.starts_with('0') {
+                i64::from_str_radix(&literal[1..], 8)
+            } else {
+                literal.parse()
+            };
+
+            match number {
+                Ok(number) => Ok((&input[end..], Arithmetic::Number(number))),
+                Err(_) => Err(arithmetic_error(input)),
+            }
+        },
+        Some('$') => {
+            let (rest, name) = alt!(
+                &input[1..],
+                delimited!(char!('{'), parameter_name, char!('}'))
+                | recognize!(one_of!("0123456789"))
+                | arithmetic_name
+            )?;
+            Ok((rest, Arithmetic::Variable(name.to_owned())))
+        },
+        Some(_) => {
+            let (rest, name) = arithmetic_name(input)?;
+            match arithmetic_operator(rest) {
+                Ok((after, "++")) => Ok((after, Arithmetic::PostIncrement(name.to_owned(), 1))),
+                Ok((after, "--")) => Ok((after, Arithmetic::PostIncrement(name.to_owned(), -1))),
+                _ => Ok((rest, Arithmetic::Variable(name.to_owned()))),
+            }
+        },
+    }
+}
+
+/// Parses the next operator in an arithmetic expression, along with any space before it.
+///
+fn arithmetic_operator(input: &str) -> IResult<&str, &str> {
+    let (input, _) = arithmetic_space(input)?;
+    if input.is_empty() {
+        return Err(nom::Err::Incomplete(Needed::Unknown));
+    }
+
+    match ARITHMETIC_OPERATORS.iter().find(|operator| input.starts_with(*operator)) {
+        Some(operator) => Ok((&input[operator.len()..], &input[..operator.len()])),
+        None => Err(arithmetic_error(input)),
+    }
+}
+
+/// Parses the name of a variable in an arithmetic expression, which may end the input.
+///
+fn arithmetic_name(input: &str) -> IResult<&str, &str> {
+    match env_var(input) {
+        Err(nom::Err::Incomplete(_)) => Ok(("", input)),
+        result => result,
+    }
+}
+
+/// Parses the space between tokens in an arithmetic expression, which may include newlines.
+///
+pub fn arithmetic_space(input: &str) -> IResult<&str, &str> {
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', '\n']);
+        match rest.strip_prefix("\\\n") {
+            Some(after) => rest = after,
+            None => return Ok((rest, &input[..input.len() - rest.len()])),
+        }
+    }
+}
+
+/// Error for input that isn't a valid arithmetic expression.
+///
+fn arithmetic_error(input: &str) -> nom::Err<&str> {
+    nom::Err::Error(Context::Code(input, nom::ErrorKind::Tag))
+}
+
+/// Parses a command substitution, which is replaced by the output of the program inside it.
+///
+/// The program can span several lines, and is parsed like any other.
+///
+/// ## Examples
+///
+/// - `$(date)`
+/// - `$(cd "${dir}"; ls | wc -l)`
+///
+named!(
+    command_substitution(&str) -> Piece,
+    map!(
+        preceded!(tag!("$("), nested_program),
+        |program| Piece::CommandSubstitution { program, quoted: false }
+    )
+);
+
+/// Parses a process substitution, which is replaced by a path to a pipe that's connected to
+/// the program inside it.
+///
+/// ## Examples
+///
+/// - `<(sort a.txt)`
+/// - `>(gzip > out.gz)`
+///
+named!(
+    process_substitution(&str) -> Piece,
+    do_parse!(
+        direction: alt!(
+            tag!("<(") => { |_| Direction::Input }
+            | tag!(">(") => { |_| Direction::Output }
+        )
+        >> program: nested_program
+        >> (Piece::ProcessSubstitution { program, direction })
+    )
+);
+
+/// Parses the program inside a command or process substitution, up to and including the closing
+/// parenthesis.
+///
+named!(
+    nested_program(&str) -> Vec<ParsedLine>,
+    do_parse!(
+        statements: many0!(preceded!(linebreak, pair!(and_or, opt!(separator))))
+        >> preceded!(linebreak, char!(')'))
+        >> (statements.into_iter()
+            .map(|(line, separator)| {
+                if separator == Some('&') {
+                    ParsedLine::Background(Box::new(line))
+                } else {
+                    line
+                }
+            })
+            .collect())
+    )
+);
+
+/// Marks a variable, parameter expansion, or command substitution as appearing within double
+/// quotes.
+///
+fn quoted(piece: Piece) -> Piece {
+    match piece {
+        Piece::Variable { name, .. } => Piece::Variable { name, quoted: true },
+        Piece::Expansion { expansion, .. } => Piece::Expansion { expansion, quoted: true },
+        Piece::CommandSubstitution { program, .. } => Piece::CommandSubstitution { program, quoted: true },
+        piece => piece,
+    }
+}
+
+/// Creates an unquoted variable piece.
+///
+fn variable(name: &str) -> Piece {
+    Piece::Variable { name: name.to_owned(), quoted: false }
+}
+
+/// Creates an unquoted parameter expansion piece.
+///
+fn unquoted_expansion(expansion: Expansion) -> Piece {
+    Piece::Expansion { expansion, quoted: false }
+}
+
 /// Parses an environment variable interpolation.
 ///
+/// Braces are optional, but are needed for positional parameters beyond `9`, or to apply an
+/// operator to the variable.
+///
 /// ## Examples
 ///
-/// - `${HOME}`
+/// - `$HOME`, `${HOME}`
 /// - `${SOME_DIR}`
+/// - `$1`, `${10}`
+/// - `$#`, `$?`
 ///
 named!(
     interpolated_env_var(&str) -> Piece,
-    map!(
-        do_parse!(
-            tag!("$")
-            >> var: delimited!(tag!("{"), env_var, tag!("}"))
-            >> (var)
-        ),
-        |v| Piece::Variable(v.to_owned())
+    preceded!(
+        tag!("$"),
+        alt!(
+            delimited!(tag!("{"), parameter_expansion, tag!("}"))
+            | recognize!(one_of!("0123456789")) => { variable }
+            | special_parameter => { variable }
+            | env_var => { variable }
+        )
+    )
+);
+
+/// Parses what's inside the braces of a parameter expansion, which is a variable name that may
+/// be followed by an operator.
+///
+/// ## Examples
+///
+/// - `HOME`
+/// - `#PATH`
+/// - `name:-anonymous`
+/// - `file%.*`
+/// - `path//:/ `
+///
+named!(
+    parameter_expansion(&str) -> Piece,
+    alt!(
+        preceded!(char!('#'), parameter_name) => { |name: &str| unquoted_expansion(Expansion {
+            name: name.to_owned(),
+            operator: ExpansionOperator::Length,
+        }) }
+        | do_parse!(
+            name: parameter_name
+            >> operator: opt!(expansion_operator)
+            >> (match operator {
+                Some(operator) => unquoted_expansion(Expansion { name: name.to_owned(), operator }),
+                None => variable(name),
+            })
+        )
+    )
+);
+
+/// Parses the name of a parameter, which is a variable, a positional parameter, or a special
+/// parameter.
+///
+named!(
+    parameter_name(&str) -> &str,
+    alt!(env_var | digit | special_parameter)
+);
+
+/// Parses the operator in a parameter expansion, along with its words.
+///
+named!(
+    expansion_operator(&str) -> ExpansionOperator,
+    alt!(
+        preceded!(tag!(":-"), call!(expansion_word, "}")) => { |word| ExpansionOperator::Default { word, if_null: true } }
+        | preceded!(tag!(":="), call!(expansion_word, "}")) => { |word| ExpansionOperator::Assign { word, if_null: true } }
+        | preceded!(tag!(":?"), call!(expansion_word, "}")) => { |word| ExpansionOperator::Error { word, if_null: true } }
+        | preceded!(tag!("-"), call!(expansion_word, "}")) => { |word| ExpansionOperator::Default { word, if_null: false } }
+        | preceded!(tag!("="), call!(expansion_word, "}")) => { |word| ExpansionOperator::Assign { word, if_null: false } }
+        | preceded!(tag!("?"), call!(expansion_word, "}")) => { |word| ExpansionOperator::Error { word, if_null: false } }
+        | preceded!(tag!("##"), call!(expansion_word, "}")) => { |pattern| ExpansionOperator::RemovePrefix { pattern, longest: true } }
+        | preceded!(tag!("#"), call!(expansion_word, "}")) => { |pattern| ExpansionOperator::RemovePrefix { pattern, longest: false } }
+        | preceded!(tag!("%%"), call!(expansion_word, "}")) => { |pattern| ExpansionOperator::RemoveSuffix { pattern, longest: true } }
+        | preceded!(tag!("%"), call!(expansion_word, "}")) => { |pattern| ExpansionOperator::RemoveSuffix { pattern, longest: false } }
+        | do_parse!(
+            all: alt!(tag!("//") => { |_| true } | tag!("/") => { |_| false })
+            >> pattern: call!(expansion_word, "/}")
+            >> replacement: opt!(preceded!(char!('/'), call!(expansion_word, "}")))
+            >> (ExpansionOperator::Replace {
+                pattern,
+                replacement: replacement.unwrap_or_else(|| ShellString::from(Vec::new())),
+                all,
+            })
+        )
     )
 );
 
+/// Parses a word within a parameter expansion, which ends at any of the characters in `stop`.
+///
+/// Words can contain quoted strings, variables, and command substitutions. Other characters,
+/// including spaces, are taken as is, unless escaped with a backslash.
+///
+fn expansion_word<'a>(input: &'a str, stop: &'static str) -> IResult<&'a str, ShellString> {
+    let mut word = ShellString::from(Vec::new());
+    let mut text = String::new();
+    let mut rest = input;
+    loop {
+        let c = match rest.chars().next() {
+            Some(c) if stop.contains(c) => break,
+            Some(c) => c,
+            None => return Err(nom::Err::Incomplete(Needed::Unknown)),
+        };
+
+        let parsed = match c {
+            '$' => alt!(rest, arithmetic_expansion | command_substitution | interpolated_env_var).map(|(rest, piece)| (rest, ShellString::from(piece))),
+            '"' => interpolated_string(rest),
+            '\'' => uninterpolated_string(rest),
+            _ => Err(nom::Err::Error(Context::Code(rest, nom::ErrorKind::Char))),
+        };
+
+        match parsed {
+            Ok((remaining, string)) => {
+                if !text.is_empty() {
+                    word = word + ShellString::from(text.split_off(0));
+                }
+                word = word + string;
+                rest = remaining;
+            },
+            Err(nom::Err::Incomplete(needed)) => return Err(nom::Err::Incomplete(needed)),
+            Err(_) if c == '\\' => {
+                let escaped = rest[1..].chars().next().ok_or(nom::Err::Incomplete(Needed::Size(1)))?;
+                text.push(escaped);
+                rest = &rest[1 + escaped.len_utf8()..];
+            },
+            Err(_) => {
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+            },
+        }
+    }
+
+    if !text.is_empty() {
+        word = word + ShellString::from(text);
+    }
+    Ok((rest, word))
+}
+
+/// Parses the body of a here-document whose delimiter isn't quoted.
+///
+/// Variables, parameter expansions, command substitutions, and arithmetic expansions are
+/// interpolated as if the body were in double quotes. A backslash only escapes `$`, `` ` ``,
+/// another backslash, or a newline, and is kept as is anywhere else.
+///
+pub fn here_document_body(input: &str) -> ShellString {
+    let mut body = ShellString::from(Vec::new());
+    let mut text = String::new();
+    let mut rest = input;
+    while let Some(c) = rest.chars().next() {
+        if c == '$' {
+            if let Ok((remaining, piece)) = alt!(rest, arithmetic_expansion | command_substitution | interpolated_env_var) {
+                if !text.is_empty() {
+                    body = body + ShellString::from(text.split_off(0));
+                }
+                body = body + ShellString::from(quoted(piece));
+                rest = remaining;
+                continue;
+            }
+        } else if c == '\\' {
+            match rest[1..].chars().next() {
+                Some(escaped @ '$') | Some(escaped @ '`') | Some(escaped @ '\\') => {
+                    text.push(escaped);
+                    rest = &rest[2..];
+                    continue;
+                },
+                Some('\n') => {
+                    rest = &rest[2..];
+                    continue;
+                },
+                _ => (),
+            }
+        }
+
+        text.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    if !text.is_empty() {
+        body = body + ShellString::from(text);
+    }
+    body
+}
+
+/// Parses the name of a special parameter:
+///
+/// - `#`, the number of positional parameters,
+/// - `@` and `*`, all positional parameters,
+/// - `?`, the exit status of the last command,
+/// - `$`, the pid of the shell, or
+/// - `!`, the pid of the last background job.
+///
+named!(
+    special_parameter(&str) -> &str,
+    recognize!(one_of!("#@*?$!"))
+);
+
+/// Returns whether or not `chr` can be part of the prefix after a `~`, which ends at the first
+/// path separator.
+///
+fn is_tilde_prefix_character(chr: char) -> bool {
+    chr == '+' || (is_path_character(chr) && !path::is_separator(chr))
+}
+
 /// Returns whether or not `chr` is valid as a character in a variable name.
 ///
 /// A variable name is composed of alphanumeric characters, and an underscore. If `is_not_first` is
@@ -220,13 +1322,7 @@ fn env_var(input: &str) -> IResult<&str, &str> {
 /// Returns whether or not `chr` is valid as a character in a variable name.
 ///
 fn is_var_character(chr: char) -> bool {
-    match chr {
-        'a'...'z' => true,
-        'A'...'Z' => true,
-        '0'...'9' => true,
-        '_' => true,
-        _ => false,
-    }
+    matches!(chr, 'a'..='z' | 'A'..='Z' | '0'..='9' | '_')
 }
 
 /// Parses regular text inside of an interpolated string.
@@ -238,6 +1334,9 @@ fn is_var_character(chr: char) -> bool {
 /// - `\\` becomes a backslash
 /// - `\"` becomes a double quote
 /// - `\'` becomes a single quote
+/// - `\$` becomes a dollar sign, instead of starting a variable
+/// - ``\` `` becomes a backquote
+/// - a backslash at the end of a line joins it to the next line
 ///
 /// ## Examples
 ///
@@ -256,9 +1355,12 @@ named!(
                 tag!("\\") => { |_| "\\" }
                 | tag!("\"") => { |_| "\"" }
                 | tag!("\'") => { |_| "'" }
+                | tag!("$") => { |_| "$" }
+                | tag!("`") => { |_| "`" }
                 | tag!("n") => { |_| "\n" }
                 | tag!("r") => { |_| "\r" }
                 | tag!("t") => { |_| "\t" }
+                | tag!("\n") => { |_| "" }
             )
         ),
         Piece::from
@@ -296,55 +1398,322 @@ named!(
     )
 );
 
-/// Split input at space characters, not including newlines / carriage returns
-///
-fn space(input: &str) -> IResult<&str, &str> {
-    input.split_at_position(|c| {
-        !c.is_whitespace() || c == '\n' || c == '\r'
-    })
-}
+/// Split input at space characters, not including newlines / carriage returns
+///
+/// A backslash at the end of a line counts as space, so that a line can be continued on the
+/// next one. Like `split_at_position`, more input is needed if there's nothing but space.
+///
+fn space(input: &str) -> IResult<&str, &str> {
+    let mut end = 0;
+    loop {
+        let rest = &input[end..];
+        if rest.starts_with("\\\n") {
+            end += 2;
+            continue;
+        }
+
+        match rest.chars().next() {
+            Some(c) if c.is_whitespace() && c != '\n' && c != '\r' => end += c.len_utf8(),
+            Some(_) => return Ok((rest, &input[..end])),
+            None => return Err(nom::Err::Incomplete(Needed::Size(1))),
+        }
+    }
+}
+
+/// Parses the given reserved word, after any space.
+///
+fn keyword<'a>(input: &'a str, word: &'static str) -> IResult<&'a str, &'a str> {
+    let (rest, _) = space(input)?;
+    match reserved_word(rest) {
+        Ok((remaining, found)) if found == word => Ok((remaining, found)),
+        Ok(_) => Err(nom::Err::Error(Context::Code(rest, nom::ErrorKind::Tag))),
+        Err(err) => Err(err),
+    }
+}
+
+/// Parses the name of a function, which may contain dashes along with the characters allowed in
+/// variable names.
+///
+fn function_name(input: &str) -> IResult<&str, &str> {
+    let is_name_character = |c| is_var_character(c) || c == '-';
+    let (rest, name) = input.split_at_position(|c| !is_name_character(c))?;
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) || RESERVED_WORDS.contains(&name) {
+        return Err(nom::Err::Error(Context::Code(input, nom::ErrorKind::IsA)));
+    }
+    Ok((rest, name))
+}
+
+/// Parses a whole word that is one of `RESERVED_WORDS`.
+///
+fn reserved_word(input: &str) -> IResult<&str, &str> {
+    let end = input.find(|c| !is_path_character(c)).unwrap_or(input.len());
+    let word = &input[..end];
+    if RESERVED_WORDS.contains(&word) {
+        Ok((&input[end..], word))
+    } else {
+        Err(nom::Err::Error(Context::Code(input, nom::ErrorKind::Tag)))
+    }
+}
+
+/// Parses space that may include newlines and comments, such as after a `|` or `&&`.
+///
+fn linebreak(input: &str) -> IResult<&str, &str> {
+    let mut rest = input;
+    loop {
+        let (after_space, _) = space(rest)?;
+        rest = match after_space.chars().next() {
+            Some('\n') => &after_space[1..],
+            Some('#') => comment(after_space)?.0,
+            _ => return Ok((after_space, &input[..input.len() - after_space.len()])),
+        };
+    }
+}
+
+/// Returns whether or not `chr` is valid as a character in a command name.
+///
+fn is_path_character(chr: char) -> bool {
+    if path::is_separator(chr) {
+        return true
+    }
+
+    matches!(chr, 'a'..='z' | 'A'..='Z' | '0'..='9' | '~' | '%' | '-' | '_' | '.' | '=')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+     * Tests for `parse_line`
+     */
+    #[test]
+    fn test_parse_line_parses_current_directory() {
+        assert_eq!(
+            ("\n", ParsedLine::Command(Command {
+                vars: Vec::new(),
+                args: vec![ShellString::from("./foo.sh")],
+                redirects: Vec::new(),
+            })),
+            parse_line("./foo.sh\n").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_parse_line_parses_indented_line_with_comment() {
+        assert_eq!(
+            ("\n", ParsedLine::Command(Command {
+                vars: Vec::new(),
+                args: vec![ShellString::from("ls"), ShellString::from("-l")],
+                redirects: Vec::new(),
+            })),
+            parse_line("  ls -l  # list files\n").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_parse_line_parses_blank_and_comment_lines_as_empty() {
+        assert_eq!(("\n", ParsedLine::Empty), parse_line("\n").expect("should parse"));
+        assert_eq!(("\n", ParsedLine::Empty), parse_line("   \n").expect("should parse"));
+        assert_eq!(("\n", ParsedLine::Empty), parse_line("#!/usr/bin/env gesh\n").expect("should parse"));
+    }
+
+    #[test]
+    fn test_parse_line_parses_pipeline() {
+        assert_eq!(
+            ("\n", ParsedLine::Pipeline(Pipeline {
+                commands: vec![
+                    Command {
+                        vars: Vec::new(),
+                        args: vec![ShellString::from("ls"), ShellString::from("-l")],
+                        redirects: Vec::new(),
+                    },
+                    Command {
+                        vars: vec![
+                            SetVariable { name: "FOO".to_owned(), value: ShellString::from("bar") }
+                        ],
+                        args: vec![ShellString::from("grep"), ShellString::from("foo")],
+                        redirects: Vec::new(),
+                    },
+                    Command {
+                        vars: Vec::new(),
+                        args: vec![ShellString::from("wc")],
+                        redirects: Vec::new(),
+                    },
+                ],
+            })),
+            parse_line("ls -l | FOO=bar grep foo|wc\n").expect("should parse")
+        );
+    }
 
-/// Returns whether or not `chr` is valid as a character in a command name.
-///
-fn is_path_character(chr: char) -> bool {
-    if path::is_separator(chr) {
-        return true
+    #[test]
+    fn test_parse_line_parses_list() {
+        assert_eq!(
+            ("\n", ParsedLine::List(vec![
+                ParsedLine::SetVariables(vec![
+                    SetVariable { name: "FOO".to_owned(), value: ShellString::from("bar") },
+                ]),
+                ParsedLine::Command(Command {
+                    vars: Vec::new(),
+                    args: vec![ShellString::from("a")],
+                    redirects: Vec::new(),
+                }),
+            ])),
+            parse_line("FOO=bar; a;\n").expect("should parse")
+        );
     }
 
-    match chr {
-        'a'...'z' => true,
-        'A'...'Z' => true,
-        '0'...'9' => true,
-        '~' | '-' | '_' | '.' | '=' => true,
-        _ => false,
+    #[test]
+    fn test_parse_line_parses_background_lines() {
+        let command = |name: &str| ParsedLine::Command(Command {
+            vars: Vec::new(),
+            args: vec![ShellString::from(name)],
+            redirects: Vec::new(),
+        });
+
+        assert_eq!(
+            ("\n", ParsedLine::List(vec![
+                ParsedLine::Background(Box::new(
+                    ParsedLine::And(Box::new(command("a")), Box::new(command("b")))
+                )),
+                command("c"),
+                ParsedLine::Background(Box::new(command("d"))),
+            ])),
+            parse_line("a && b & c; d&\n").expect("should parse")
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_parse_line_parses_and_or_left_associatively() {
+        let command = |name: &str| ParsedLine::Command(Command {
+            vars: Vec::new(),
+            args: vec![ShellString::from(name)],
+            redirects: Vec::new(),
+        });
+
+        assert_eq!(
+            ("\n", ParsedLine::List(vec![
+                ParsedLine::Or(
+                    Box::new(ParsedLine::And(Box::new(command("a")), Box::new(command("b")))),
+                    Box::new(command("c")),
+                ),
+                command("d"),
+            ])),
+            parse_line("a && b||c ; d\n").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_parse_line_parses_pipelines_with_higher_precedence_than_and_or() {
+        let command = |name: &str| Command {
+            vars: Vec::new(),
+            args: vec![ShellString::from(name)],
+            redirects: Vec::new(),
+        };
+
+        assert_eq!(
+            ("\n", ParsedLine::Or(
+                Box::new(ParsedLine::Pipeline(Pipeline { commands: vec![command("a"), command("b")] })),
+                Box::new(ParsedLine::Pipeline(Pipeline { commands: vec![command("c"), command("d")] })),
+            )),
+            parse_line("a | b || c | d\n").expect("should parse")
+        );
+    }
 
     /*
-     * Tests for `parse_line`
+     * Tests for control flow
      */
     #[test]
-    fn test_parse_line_parses_current_directory() {
+    fn test_if_clause_parses_branches_over_multiple_lines() {
+        let command = |name: &str| ParsedLine::Command(Command {
+            vars: Vec::new(),
+            args: vec![ShellString::from(name)],
+            redirects: Vec::new(),
+        });
+
         assert_eq!(
-            ("\n", ParsedLine::Command(Command {
-                vars: Vec::new(),
-                args: vec![ShellString::from("./foo.sh")],
+            ("\n", ParsedLine::If(If {
+                branches: vec![
+                    (command("a"), command("b")),
+                    (command("c"), ParsedLine::List(vec![command("d"), command("e")])),
+                ],
+                otherwise: Some(Box::new(command("f"))),
             })),
-            parse_line("./foo.sh\n").expect("should parse")
+            if_clause("if a; then\n  b\nelif c\nthen\n  # comment\n  d; e\nelse f; fi\n").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_while_clause_parses() {
+        assert_eq!(
+            ("\n", ParsedLine::While(While {
+                condition: Box::new(ParsedLine::Command(Command {
+                    vars: Vec::new(),
+                    args: vec![ShellString::from("true")],
+                    redirects: Vec::new(),
+                })),
+                body: Box::new(ParsedLine::Background(Box::new(ParsedLine::Command(Command {
+                    vars: Vec::new(),
+                    args: vec![ShellString::from("echo"), ShellString::from("done")],
+                    redirects: Vec::new(),
+                })))),
+            })),
+            while_clause("while true; do echo done & done\n").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_for_clause_parses_words() {
+        assert_eq!(
+            ("\n", ParsedLine::For(For {
+                variable: "x".to_owned(),
+                words: vec![ShellString::from("a"), ShellString::from(Piece::Glob("*".to_owned()))],
+                body: Box::new(ParsedLine::Command(Command {
+                    vars: Vec::new(),
+                    args: vec![ShellString::from("b")],
+                    redirects: Vec::new(),
+                })),
+            })),
+            for_clause("for x in a *\ndo b; done\n").expect("should parse")
         );
     }
 
+    #[test]
+    fn test_function_definition_parses_both_forms() {
+        let expected = ParsedLine::Function(Function {
+            name: "say-hi".to_owned(),
+            body: Box::new(ParsedLine::Command(Command {
+                vars: Vec::new(),
+                args: vec![ShellString::from("echo"), ShellString::from("hi")],
+                redirects: Vec::new(),
+            })),
+        });
+
+        assert_eq!(("\n", expected.clone()), function_definition("say-hi() { echo hi; }\n").expect("should parse"));
+        assert_eq!(("\n", expected.clone()), function_definition("say-hi ()\n{\n  echo hi\n}\n").expect("should parse"));
+        assert_eq!(("\n", expected), function_definition("function say-hi { echo hi; }\n").expect("should parse"));
+        assert!(function_definition("say-hi arg\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_line_needs_more_input_for_open_blocks() {
+        assert!(parse_line("if a; then\n").unwrap_err().is_incomplete());
+        assert!(parse_line("while a; do b; done; for x in y; do\n").unwrap_err().is_incomplete());
+    }
+
+    #[test]
+    fn test_command_doesnt_parse_reserved_words() {
+        assert!(command("fi\n").is_err());
+        assert!(command("done;\n").is_err());
+        assert!(command("fire\n").is_ok());
+    }
+
     /*
      * Tests for `command`
      */
     #[test]
     fn test_command_parses() {
         assert_eq!(
-            ("\n", ParsedLine::Command(Command {
+            ("\n", Command {
                 vars: Vec::new(),
                 args: vec![
                     ShellString::from("/bin/echo"),
@@ -352,18 +1721,39 @@ mod tests {
                     ShellString::from("home"),
                     ShellString::from("dir is"),
                     ShellString::from(vec![
-                        Piece::Variable("HOME".to_owned())
+                        Piece::Variable { name: "HOME".to_owned(), quoted: true }
                     ])
                 ],
-            })),
+                redirects: Vec::new(),
+            }),
             command("/bin/echo My home 'dir is' \"${HOME}\"\n").expect("should parse")
         );
     }
 
+    #[test]
+    fn test_command_parses_job_specifications() {
+        assert_eq!(
+            ("\n", Command {
+                vars: Vec::new(),
+                args: vec![ShellString::from("fg"), ShellString::from("%1")],
+                redirects: Vec::new(),
+            }),
+            command("fg %1\n").expect("should parse")
+        );
+        assert_eq!(
+            ("\n", Command {
+                vars: Vec::new(),
+                args: vec![ShellString::from("kill"), ShellString::from("%+"), ShellString::from("%%")],
+                redirects: Vec::new(),
+            }),
+            command("kill %+ %%\n").expect("should parse")
+        );
+    }
+
     #[test]
     fn test_command_parses_with_env_vars() {
         assert_eq!(
-            ("\n", ParsedLine::Command(Command {
+            ("\n", Command {
                 vars: vec![
                     SetVariable { name: "FOO".to_owned(), value: ShellString::from("bar") }
                 ],
@@ -371,11 +1761,97 @@ mod tests {
                     ShellString::from("export"),
                     ShellString::from("BAR=baz"),
                 ],
-            })),
+                redirects: Vec::new(),
+            }),
             command("FOO=bar export BAR=baz\n").expect("should parse")
         );
     }
 
+    #[test]
+    fn test_command_parses_with_redirects() {
+        assert_eq!(
+            ("\n", Command {
+                vars: Vec::new(),
+                args: vec![
+                    ShellString::from("sort"),
+                    ShellString::from("-r"),
+                ],
+                redirects: vec![
+                    Redirect::Read(0, ShellString::from("in.txt")),
+                    Redirect::Write(1, ShellString::from("out.txt")),
+                    Redirect::Duplicate(2, 1),
+                    Redirect::Append(2, ShellString::from("err.log")),
+                ],
+            }),
+            command("sort < in.txt -r >out.txt 2>&1 2>> err.log\n").expect("should parse")
+        );
+    }
+
+    /*
+     * Tests for `redirect`
+     */
+    #[test]
+    fn test_redirect_parses_all_output_redirect() {
+        assert_eq!(
+            ("\n", vec![
+                Redirect::Write(1, ShellString::from("all.txt")),
+                Redirect::Duplicate(2, 1),
+            ]),
+            redirect("&> all.txt\n").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_redirect_parses_all_output_append() {
+        assert_eq!(
+            ("\n", vec![
+                Redirect::Append(1, ShellString::from("all.txt")),
+                Redirect::Duplicate(2, 1),
+            ]),
+            redirect("&>>all.txt\n").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_redirect_parses_input_duplication() {
+        assert_eq!(
+            ("\n", vec![Redirect::Duplicate(0, 3)]),
+            redirect("<&3\n").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_redirect_parses_here_documents_and_here_strings() {
+        assert_eq!(
+            ("\n", vec![Redirect::HereDocument(0, ShellString::from(Vec::new()))]),
+            redirect("<<- 'EOF'\n").expect("should parse")
+        );
+        assert_eq!(
+            ("\n", vec![Redirect::HereString(3, ShellString::from(vec![
+                Piece::from("a "),
+                Piece::Variable { name: "b".to_owned(), quoted: true },
+            ]))]),
+            redirect("3<<< \"a $b\"\n").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_here_document_body_interpolates_and_unescapes() {
+        assert_eq!(
+            ShellString::from(vec![
+                Piece::from("a "),
+                Piece::Variable { name: "b".to_owned(), quoted: true },
+                Piece::from(" $c \\d \\ \"e\"\n"),
+            ]),
+            here_document_body("a $b \\$c \\d \\\\ \"e\"\n")
+        );
+    }
+
+    #[test]
+    fn test_redirect_doesnt_parse_number_separated_from_operator() {
+        assert!(redirect("2 > out.txt\n").is_err());
+    }
+
     /*
      * Tests for `set_variable` and `set_variables`
      */
@@ -407,6 +1883,10 @@ mod tests {
             ("\n", ShellString::from("/bin/echo")),
             piece("/bin/echo\n").expect("should parse")
         );
+        assert_eq!(
+            ("\n", ShellString::from("+2")),
+            piece("+2\n").expect("should parse")
+        );
     }
 
     #[test]
@@ -434,7 +1914,7 @@ mod tests {
                 Piece::from("foo/"),
                 Piece::from("bar"),
                 Piece::from("/"),
-                Piece::Variable("HOME".to_owned()),
+                Piece::Variable { name: "HOME".to_owned(), quoted: true },
                 Piece::from(" x"),
                 Piece::from("/"),
                 Piece::from("more"),
@@ -444,6 +1924,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_piece_parses_unbraced_variables_and_special_parameters() {
+        assert_eq!(
+            ("\n", ShellString::from(vec![
+                Piece::Variable { name: "HOME".to_owned(), quoted: false },
+                Piece::from("/"),
+                Piece::Variable { name: "USER_1".to_owned(), quoted: false },
+                Piece::from("."),
+                Piece::Variable { name: "?".to_owned(), quoted: false },
+                Piece::Variable { name: "$".to_owned(), quoted: false },
+                Piece::from(" "),
+                Piece::Variable { name: "!".to_owned(), quoted: true },
+                Piece::Variable { name: "*".to_owned(), quoted: true },
+                Piece::Variable { name: "0".to_owned(), quoted: false },
+                Piece::from("$"),
+            ])),
+            piece("$HOME/$USER_1.$?$$\" $!$*\"$0$\n").expect("should parse")
+        );
+        assert_eq!(
+            ("\n", ShellString::from(vec![Piece::from("a"), Piece::from("$"), Piece::from(" "), Piece::from("$")])),
+            piece("\"a$ $\"\n").expect("should parse")
+        );
+    }
+
     #[test]
     fn test_piece_parses_glob() {
         assert_eq!(
@@ -451,7 +1955,7 @@ mod tests {
                 Piece::from("foo/"),
                 Piece::from("bar"),
                 Piece::from("/"),
-                Piece::Variable("HOME".to_owned()),
+                Piece::Variable { name: "HOME".to_owned(), quoted: true },
                 Piece::from("/"),
                 Piece::Glob("**".to_owned()),
                 Piece::from("/"),
@@ -462,6 +1966,46 @@ mod tests {
         );
     }
 
+    /*
+     * Tests for `brace`
+     */
+    #[test]
+    fn test_brace_parses_nested_alternatives() {
+        assert_eq!(
+            ("\n", ShellString::from(vec![
+                Piece::from("file."),
+                Piece::Brace(Brace::Alternatives(vec![
+                    ShellString::from("rs"),
+                    ShellString::from(vec![
+                        Piece::from("b"),
+                        Piece::Brace(Brace::Alternatives(vec![ShellString::from("ak"), ShellString::from("in")])),
+                    ]),
+                    ShellString::from(Vec::new()),
+                ])),
+            ])),
+            piece("file.{rs,b{ak,in},}\n").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_brace_parses_sequences() {
+        assert_eq!(
+            ("", Piece::Brace(Brace::Sequence { start: "01".to_owned(), end: "-10".to_owned(), step: Some(3) })),
+            brace("{01..-10..3}").expect("should parse")
+        );
+        assert_eq!(
+            ("", Piece::Brace(Brace::Sequence { start: "a".to_owned(), end: "e".to_owned(), step: None })),
+            brace("{a..e}").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_brace_needs_alternatives_or_sequence() {
+        assert!(brace("{a}").is_err());
+        assert!(brace("{a..5}").is_err());
+        assert!(brace("{}").is_err());
+    }
+
     /*
      * Tests for `glob`
      */
@@ -513,6 +2057,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_glob_parses_pattern_groups() {
+        assert_eq!(
+            ("\n", ShellString::from(Piece::Glob("!(*.o|@(a|b))".to_owned()))),
+            glob("!(*.o|@(a|b))\n").expect("should parse")
+        );
+        assert_eq!(
+            ("(\n", ShellString::from(Piece::Glob("*".to_owned()))),
+            glob("*(\n").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_piece_parses_plus_pattern_groups() {
+        assert_eq!(
+            ("\n", ShellString::from(Piece::Glob("+(a|b)".to_owned()))),
+            piece("+(a|b)\n").expect("should parse")
+        );
+        assert_eq!(
+            ("\n", ShellString::from(vec![
+                Piece::Glob("*".to_owned()),
+                Piece::Glob("+(a|B)".to_owned()),
+                Piece::from(".txt"),
+            ])),
+            piece("*+(a|B).txt\n").expect("should parse")
+        );
+    }
+
     #[test]
     fn test_glob_parses_character_negation_glob() {
         assert_eq!(
@@ -544,13 +2116,25 @@ mod tests {
     fn test_path_parses_tilde_paths() {
         assert_eq!(
             ("\n", ShellString::from(vec![
-                Piece::Variable("HOME".to_owned()),
+                Piece::Tilde("".to_owned()),
                 Piece::from("/bin/echo")
             ])),
             path("~/bin/echo\n").expect("should parse")
         );
     }
 
+    #[test]
+    fn test_path_parses_tilde_prefixes() {
+        assert_eq!(
+            (" ", ShellString::from(vec![Piece::Tilde("root".to_owned()), Piece::from("/x")])),
+            path("~root/x ").expect("should parse")
+        );
+        assert_eq!(
+            ("\n", ShellString::from(Piece::Tilde("+2".to_owned()))),
+            path("~+2\n").expect("should parse")
+        );
+    }
+
     #[test]
     fn test_path_parses_separator_at_end() {
         assert_eq!(
@@ -585,9 +2169,9 @@ mod tests {
         assert_eq!(
             ("", ShellString::from(vec![
                 Piece::from("home dir:\n\t"),
-                Piece::Variable("HOME".to_owned()),
+                Piece::Variable { name: "HOME".to_owned(), quoted: true },
                 Piece::from("\n\ncode dir:\n\t"),
-                Piece::Variable("CODE_DIR".to_owned()),
+                Piece::Variable { name: "CODE_DIR".to_owned(), quoted: true },
             ])),
             interpolated_string("\"home dir:\\n\\t${HOME}\\n\\ncode dir:\\n\\t${CODE_DIR}\"").expect("should parse")
         );
@@ -603,6 +2187,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_interpolated_string_parses_escaped_dollars_and_backquotes() {
+        assert_eq!(
+            ("", ShellString::from("cost: $HOME `date` \\")),
+            interpolated_string("\"cost: \\$HOME \\`date\\` \\\\\"").expect("should parse")
+        );
+    }
+
     #[test]
     fn test_interpolated_string_parses_string_with_vars() {
         assert_eq!(
@@ -613,6 +2205,146 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_interpolated_string_parses_positional_and_special_parameters() {
+        assert_eq!(
+            ("", ShellString::from(vec![
+                Piece::Variable { name: "1".to_owned(), quoted: true },
+                Piece::from(" "),
+                Piece::Variable { name: "12".to_owned(), quoted: true },
+                Piece::from(" "),
+                Piece::Variable { name: "#".to_owned(), quoted: true },
+                Piece::from(" "),
+                Piece::Variable { name: "@".to_owned(), quoted: true },
+            ])),
+            interpolated_string("\"$1 ${12} $# ${@}\"").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_interpolated_string_parses_parameter_expansions() {
+        let expansion = |name: &str, operator| Piece::Expansion {
+            expansion: Expansion { name: name.to_owned(), operator },
+            quoted: true,
+        };
+        assert_eq!(
+            ("", ShellString::from(vec![
+                expansion("A", ExpansionOperator::Default {
+                    word: ShellString::from(vec![Piece::from("x "), Piece::Variable { name: "B".to_owned(), quoted: false }]),
+                    if_null: true,
+                }),
+                expansion("1", ExpansionOperator::Assign { word: ShellString::from(Vec::new()), if_null: false }),
+                expansion("C", ExpansionOperator::Length),
+                expansion("D", ExpansionOperator::RemoveSuffix { pattern: ShellString::from(".*"), longest: true }),
+                expansion("E", ExpansionOperator::Replace {
+                    pattern: ShellString::from("/"),
+                    replacement: ShellString::from("}"),
+                    all: true,
+                }),
+                Piece::Variable { name: "#".to_owned(), quoted: true },
+            ])),
+            interpolated_string("\"${A:-x ${B}}${1=}${#C}${D%%.*}${E//\\//'}'}${#}\"").expect("should parse")
+        );
+    }
+
+    #[test]
+    fn test_interpolated_string_parses_quoted_command_substitution() {
+        assert_eq!(
+            ("", ShellString::from(vec![
+                Piece::from("today is "),
+                Piece::CommandSubstitution {
+                    program: vec![ParsedLine::Command(Command {
+                        vars: Vec::new(),
+                        args: vec![ShellString::from("date"), ShellString::from(")")],
+                        redirects: Vec::new(),
+                    })],
+                    quoted: true,
+                },
+            ])),
+            interpolated_string("\"today is $(date ')')\"").expect("should parse")
+        );
+    }
+
+    /*
+     * Tests for `command_substitution`
+     */
+    #[test]
+    fn test_command_substitution_parses_nested_program() {
+        let (rest, parsed) = command_substitution("$(\n  a | b; c $(d)\n)x").expect("should parse");
+
+        assert_eq!("x", rest);
+        assert_eq!("$(a | b; c $(d))", parsed.to_string());
+    }
+
+    #[test]
+    fn test_command_substitution_needs_more_input_when_unclosed() {
+        assert!(command_substitution("$(echo a\n").unwrap_err().is_incomplete());
+    }
+
+    /*
+     * Tests for `process_substitution`
+     */
+    #[test]
+    fn test_process_substitution_parses_both_directions() {
+        let (rest, parsed) = process_substitution("<(sort a.txt)x").expect("should parse");
+        assert_eq!("x", rest);
+        assert_eq!("<(sort a.txt)", parsed.to_string());
+
+        let (_, parsed) = process_substitution(">(\n  gzip > out.gz\n)\n").expect("should parse");
+        assert_eq!(">(gzip > out.gz)", parsed.to_string());
+    }
+
+    #[test]
+    fn test_command_parses_process_substitutions_as_arguments() {
+        let (_, parsed) = command("diff <(a) <(b) > >(c)\n").expect("should parse");
+
+        assert_eq!(3, parsed.args.len());
+        assert_eq!(1, parsed.redirects.len());
+        assert_eq!("diff <(a) <(b) > >(c)", parsed.to_string());
+    }
+
+    /*
+     * Tests for `arithmetic`
+     */
+    #[test]
+    fn test_arithmetic_follows_operator_precedence() {
+        let (rest, parsed) = arithmetic("1 + 2 * 3 ** 2 ** 2 - 4 << 1 == 2 || !x && y)").expect("should parse");
+
+        assert_eq!(")", rest);
+        assert_eq!("((((1 + (2 * (3 ** (2 ** 2)))) - 4) << 1) == 2) || ((!x) && y)", parsed.to_string());
+    }
+
+    #[test]
+    fn test_arithmetic_parses_assignments_and_increments() {
+        let (_, parsed) = arithmetic("a = b <<= c-- + ++d ? $e : ${f} - $1)").expect("should parse");
+
+        assert_eq!("a = b <<= ((c--) + (++d)) ? e : (f - 1)", parsed.to_string());
+    }
+
+    #[test]
+    fn test_arithmetic_parses_number_bases() {
+        assert_eq!(("", Arithmetic::Number(255)), arithmetic("0xff").expect("should parse"));
+        assert_eq!(("", Arithmetic::Number(8)), arithmetic("010").expect("should parse"));
+        assert!(arithmetic("09").is_err());
+    }
+
+    #[test]
+    fn test_arithmetic_needs_more_input_for_missing_operand() {
+        assert!(arithmetic("(1 + 2").unwrap_err().is_incomplete());
+        assert!(arithmetic("x *\n").unwrap_err().is_incomplete());
+    }
+
+    /*
+     * Tests for `arithmetic_expansion`
+     */
+    #[test]
+    fn test_arithmetic_expansion_parses_nested_parentheses() {
+        let (rest, parsed) = arithmetic_expansion("$(( (1 + 2) * (x) ))y").expect("should parse");
+
+        assert_eq!("y", rest);
+        assert_eq!("$(((1 + 2) * x))", parsed.to_string());
+    }
+
     /*
      * Tests for `uninterpolated_string`
      */
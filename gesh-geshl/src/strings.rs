@@ -3,10 +3,20 @@
 //! Strings in a shell can have many components consisting of:
 //!
 //! - fixed string components,
-//! - variable interpolations, and
-//! - path components (for example, `~` is the user's home directory).
+//! - variable interpolations, optionally with an operator (like `${VAR:-default}`),
+//! - brace expansions (like `{a,b}` or `{1..10}`),
+//! - command substitutions, and
+//! - tilde prefixes (for example, `~` is the user's home directory).
 //!
-use std::ops;
+use std::{
+    fmt,
+    ops,
+};
+
+use super::{
+    Arithmetic,
+    ParsedLine,
+};
 
 /// A string in a shell.
 ///
@@ -34,31 +44,259 @@ pub enum Piece {
     ///
     Glob(String),
 
+    /// A tilde prefix, which is replaced by a directory when the string is expanded.
+    ///
+    /// The prefix is what comes between the `~` and the first path separator:
+    /// - an empty prefix is the user's home directory,
+    /// - a user name is that user's home directory,
+    /// - `+` and `-` are the current and previous working directory, and
+    /// - a number, optionally preceded by `+` or `-`, is an entry in the directory stack.
+    ///
+    Tilde(String),
+
+    /// A brace expansion, which produces a separate word for each of its alternatives.
+    ///
+    Brace(Brace),
+
     /// A shell variable.
     ///
-    Variable(String),
+    /// Unless `quoted`, the value is split into separate words.
+    ///
+    Variable {
+        name: String,
+        quoted: bool,
+    },
+
+    /// A shell variable with an operator applied to it.
+    ///
+    /// Unless `quoted`, the result is split into separate words.
+    ///
+    Expansion {
+        expansion: Expansion,
+        quoted: bool,
+    },
+
+    /// The value of an arithmetic expression (`$(( ... ))`).
+    ///
+    Arithmetic(Arithmetic),
+
+    /// The output of a nested program (`$(...)`).
+    ///
+    /// Unless `quoted`, the output is split into separate words.
+    ///
+    CommandSubstitution {
+        program: Vec<ParsedLine>,
+        quoted: bool,
+    },
+
+    /// A nested program connected to a pipe (`<(...)`, `>(...)`), which is replaced by a path
+    /// to the other end of the pipe.
+    ///
+    ProcessSubstitution {
+        program: Vec<ParsedLine>,
+        direction: Direction,
+    },
+}
+
+/// Which way data flows through a process substitution.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// The command reads the output of the program (`<(...)`).
+    ///
+    Input,
+
+    /// The command writes to the input of the program (`>(...)`).
+    ///
+    Output,
+}
+
+/// A brace expansion.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Brace {
+    /// A list of alternatives (`{a,b,c}`), each of which may contain further braces.
+    ///
+    Alternatives(Vec<ShellString>),
+
+    /// A sequence of integers or letters (`{1..10}`, `{a..z..2}`).
+    ///
+    /// The endpoints are kept as written, since leading zeros in an integer pad every value in
+    /// the sequence to the same width.
+    ///
+    Sequence { start: String, end: String, step: Option<i64> },
+}
+
+/// A parameter expansion, which applies an operator to the value of a variable.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expansion {
+    pub name: String,
+    pub operator: ExpansionOperator,
+}
+
+/// An operator in a parameter expansion.
+///
+/// For the operators that come in two forms, `if_null` is set by the form with a colon, which
+/// treats a variable set to an empty string as if it were unset.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExpansionOperator {
+    /// The length of the value (`${#VAR}`).
+    ///
+    Length,
+
+    /// Use `word` if the variable is unset (`${VAR-word}`, `${VAR:-word}`).
+    ///
+    Default { word: ShellString, if_null: bool },
+
+    /// Set the variable to `word` if it's unset (`${VAR=word}`, `${VAR:=word}`).
+    ///
+    Assign { word: ShellString, if_null: bool },
+
+    /// Fail with `word` as the message if the variable is unset (`${VAR?word}`, `${VAR:?word}`).
+    ///
+    Error { word: ShellString, if_null: bool },
+
+    /// Remove the shortest or longest prefix matching a pattern (`${VAR#pat}`, `${VAR##pat}`).
+    ///
+    RemovePrefix { pattern: ShellString, longest: bool },
+
+    /// Remove the shortest or longest suffix matching a pattern (`${VAR%pat}`, `${VAR%%pat}`).
+    ///
+    RemoveSuffix { pattern: ShellString, longest: bool },
+
+    /// Replace the first or every match of a pattern (`${VAR/pat/rep}`, `${VAR//pat/rep}`).
+    ///
+    Replace { pattern: ShellString, replacement: ShellString, all: bool },
 }
 
 impl ShellString {
     /// Returns an iterator over the pieces of this `ShellString`.
     ///
     pub fn iter(&self) -> impl Iterator<Item = &Piece> {
-        return self.pieces.iter();
+        self.pieces.iter()
+    }
+
+    /// Returns an iterator over the pieces of this `ShellString` that allows modifying them.
+    ///
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut Piece> {
+        self.pieces.iter_mut()
+    }
+
+    /// Removes the newline at the end of this string, if it ends with fixed text ending in one.
+    ///
+    /// Returns whether or not there was a newline to remove.
+    ///
+    pub(crate) fn strip_newline(&mut self) -> bool {
+        match self.pieces.last_mut() {
+            Some(Piece::Fixed(text)) if text.ends_with('\n') => text.pop(),
+            _ => return false,
+        };
+
+        if self.pieces.len() > 1 && self.pieces.last() == Some(&Piece::Fixed(String::new())) {
+            self.pieces.pop();
+        }
+        true
     }
 }
 
+impl fmt::Display for ShellString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for piece in &self.pieces {
+            write!(f, "{}", piece)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Piece {
+    /// Formats this piece so that it would parse back into the same piece.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Piece::Fixed(s) if !s.is_empty() && s.chars().all(is_unquoted_character) => write!(f, "{}", s),
+            Piece::Fixed(s) => write!(f, "'{}'", s.replace('\\', "\\\\").replace('\'', "\\'")),
+            Piece::Glob(s) => write!(f, "{}", s),
+            Piece::Tilde(prefix) => write!(f, "~{}", prefix),
+            Piece::Brace(brace) => write!(f, "{}", brace),
+            Piece::Variable { name, quoted: true } => write!(f, "\"${{{}}}\"", name),
+            Piece::Variable { name, quoted: false } => write!(f, "${{{}}}", name),
+            Piece::Expansion { expansion, quoted: true } => write!(f, "\"{}\"", expansion),
+            Piece::Expansion { expansion, quoted: false } => write!(f, "{}", expansion),
+            Piece::Arithmetic(expression) => write!(f, "$(({}))", expression),
+            Piece::CommandSubstitution { program, quoted } => {
+                let program = program.iter().map(|line| line.to_string()).collect::<Vec<_>>();
+                if *quoted {
+                    write!(f, "\"$({})\"", program.join("; "))
+                } else {
+                    write!(f, "$({})", program.join("; "))
+                }
+            },
+            Piece::ProcessSubstitution { program, direction } => {
+                let program = program.iter().map(|line| line.to_string()).collect::<Vec<_>>();
+                match direction {
+                    Direction::Input => write!(f, "<({})", program.join("; ")),
+                    Direction::Output => write!(f, ">({})", program.join("; ")),
+                }
+            },
+        }
+    }
+}
+
+impl fmt::Display for Brace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Brace::Alternatives(items) => {
+                let items = items.iter().map(|item| item.to_string()).collect::<Vec<_>>();
+                write!(f, "{{{}}}", items.join(","))
+            },
+            Brace::Sequence { start, end, step: Some(step) } => write!(f, "{{{}..{}..{}}}", start, end, step),
+            Brace::Sequence { start, end, step: None } => write!(f, "{{{}..{}}}", start, end),
+        }
+    }
+}
+
+impl fmt::Display for Expansion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let colon = |if_null: &bool| if *if_null { ":" } else { "" };
+        let twice = |operator: &'static str, twice: &bool| if *twice { operator.repeat(2) } else { operator.to_owned() };
+        match &self.operator {
+            ExpansionOperator::Length => write!(f, "${{#{}}}", self.name),
+            ExpansionOperator::Default { word, if_null } => write!(f, "${{{}{}-{}}}", self.name, colon(if_null), word),
+            ExpansionOperator::Assign { word, if_null } => write!(f, "${{{}{}={}}}", self.name, colon(if_null), word),
+            ExpansionOperator::Error { word, if_null } => write!(f, "${{{}{}?{}}}", self.name, colon(if_null), word),
+            ExpansionOperator::RemovePrefix { pattern, longest } => {
+                write!(f, "${{{}{}{}}}", self.name, twice("#", longest), pattern)
+            },
+            ExpansionOperator::RemoveSuffix { pattern, longest } => {
+                write!(f, "${{{}{}{}}}", self.name, twice("%", longest), pattern)
+            },
+            ExpansionOperator::Replace { pattern, replacement, all } => {
+                write!(f, "${{{}{}{}/{}}}", self.name, twice("/", all), pattern, replacement)
+            },
+        }
+    }
+}
+
+/// Returns whether or not `chr` can be displayed without quotes.
+///
+fn is_unquoted_character(chr: char) -> bool {
+    matches!(chr, 'a'..='z' | 'A'..='Z' | '0'..='9' | '/' | '~' | '-' | '_' | '.' | '=')
+}
+
 impl ops::Add<ShellString> for ShellString {
     type Output = ShellString;
 
     fn add(mut self, rhs: ShellString) -> Self::Output {
-        self.pieces.extend(rhs.pieces.into_iter());
+        self.pieces.extend(rhs.pieces);
         ShellString {
             pieces: self.pieces
         }
     }
 }
 
-impl <'a> From<&'a str> for ShellString {
+impl From<&str> for ShellString {
     fn from(value: &str) -> Self {
         ShellString { pieces: vec![Piece::Fixed(value.to_owned())] }
     }
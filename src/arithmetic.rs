@@ -0,0 +1,200 @@
+//! Evaluates integer arithmetic expressions within an environment.
+//!
+use geshl::{
+    Arithmetic,
+    BinaryOperator,
+    UnaryOperator,
+};
+
+use environment::Environment;
+
+use std::{
+    fmt,
+    result,
+};
+
+/// Result type for evaluating arithmetic.
+///
+pub type Result<T> = result::Result<T, Error>;
+
+/// The error type for evaluating arithmetic.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// Division, or a remainder, by zero.
+    ///
+    DivisionByZero,
+
+    /// Exponentiation with a negative exponent.
+    ///
+    NegativeExponent,
+
+    /// A variable holds something other than an integer.
+    ///
+    InvalidNumber { name: String, value: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::DivisionByZero => write!(f, "division by 0"),
+            Error::NegativeExponent => write!(f, "exponent less than 0"),
+            Error::InvalidNumber { name, value } => write!(f, "{}: invalid number: {}", name, value),
+        }
+    }
+}
+
+/// Evaluates `expression`, reading and assigning variables in `env`.
+///
+/// Arithmetic is done on 64-bit integers, wrapping on overflow. Unset and empty variables are
+/// treated as zero, and `&&`, `||` and `?:` only evaluate the operands they need.
+///
+pub fn evaluate(expression: &Arithmetic, env: &mut Environment) -> Result<i64> {
+    match expression {
+        Arithmetic::Number(n) => Ok(*n),
+        Arithmetic::Variable(name) => variable(name, env),
+        Arithmetic::Unary(operator, operand) => {
+            let operand = evaluate(operand, env)?;
+            Ok(match operator {
+                UnaryOperator::Negate => operand.wrapping_neg(),
+                UnaryOperator::Plus => operand,
+                UnaryOperator::Not => (operand == 0) as i64,
+                UnaryOperator::BitNot => !operand,
+            })
+        },
+        Arithmetic::Binary(lhs, BinaryOperator::And, rhs) => {
+            Ok((evaluate(lhs, env)? != 0 && evaluate(rhs, env)? != 0) as i64)
+        },
+        Arithmetic::Binary(lhs, BinaryOperator::Or, rhs) => {
+            Ok((evaluate(lhs, env)? != 0 || evaluate(rhs, env)? != 0) as i64)
+        },
+        Arithmetic::Binary(lhs, operator, rhs) => {
+            let lhs = evaluate(lhs, env)?;
+            let rhs = evaluate(rhs, env)?;
+            apply(*operator, lhs, rhs)
+        },
+        Arithmetic::Assign(name, operator, value) => {
+            let value = evaluate(value, env)?;
+            let value = match operator {
+                Some(operator) => apply(*operator, variable(name, env)?, value)?,
+                None => value,
+            };
+            env.set(name.clone(), value.to_string());
+            Ok(value)
+        },
+        Arithmetic::PreIncrement(name, delta) => {
+            let value = variable(name, env)?.wrapping_add(*delta);
+            env.set(name.clone(), value.to_string());
+            Ok(value)
+        },
+        Arithmetic::PostIncrement(name, delta) => {
+            let value = variable(name, env)?;
+            env.set(name.clone(), value.wrapping_add(*delta).to_string());
+            Ok(value)
+        },
+        Arithmetic::Conditional(condition, then, otherwise) => {
+            if evaluate(condition, env)? != 0 {
+                evaluate(then, env)
+            } else {
+                evaluate(otherwise, env)
+            }
+        },
+    }
+}
+
+/// Applies a binary operator to two values.
+///
+/// `&&` and `||` are also handled here, for compound assignments that use them.
+///
+fn apply(operator: BinaryOperator, lhs: i64, rhs: i64) -> Result<i64> {
+    Ok(match operator {
+        BinaryOperator::Add => lhs.wrapping_add(rhs),
+        BinaryOperator::Subtract => lhs.wrapping_sub(rhs),
+        BinaryOperator::Multiply => lhs.wrapping_mul(rhs),
+        BinaryOperator::Divide if rhs == 0 => return Err(Error::DivisionByZero),
+        BinaryOperator::Divide => lhs.wrapping_div(rhs),
+        BinaryOperator::Remainder if rhs == 0 => return Err(Error::DivisionByZero),
+        BinaryOperator::Remainder => lhs.wrapping_rem(rhs),
+        BinaryOperator::Power if rhs < 0 => return Err(Error::NegativeExponent),
+        BinaryOperator::Power => lhs.wrapping_pow(rhs.min(u32::MAX as i64) as u32),
+        BinaryOperator::ShiftLeft => lhs.wrapping_shl(rhs as u32),
+        BinaryOperator::ShiftRight => lhs.wrapping_shr(rhs as u32),
+        BinaryOperator::Less => (lhs < rhs) as i64,
+        BinaryOperator::LessEqual => (lhs <= rhs) as i64,
+        BinaryOperator::Greater => (lhs > rhs) as i64,
+        BinaryOperator::GreaterEqual => (lhs >= rhs) as i64,
+        BinaryOperator::Equal => (lhs == rhs) as i64,
+        BinaryOperator::NotEqual => (lhs != rhs) as i64,
+        BinaryOperator::BitAnd => lhs & rhs,
+        BinaryOperator::BitXor => lhs ^ rhs,
+        BinaryOperator::BitOr => lhs | rhs,
+        BinaryOperator::And => (lhs != 0 && rhs != 0) as i64,
+        BinaryOperator::Or => (lhs != 0 || rhs != 0) as i64,
+    })
+}
+
+/// Returns the value of a variable as an integer.
+///
+fn variable(name: &str, env: &Environment) -> Result<i64> {
+    let value = env.get(&name.to_owned()).unwrap_or_default();
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Ok(0);
+    }
+
+    trimmed.parse().map_err(|_| Error::InvalidNumber { name: name.to_owned(), value })
+}
+
+#[cfg(test)]
+mod tests {
+    use geshl::Parser;
+
+    use super::*;
+
+    /// Evaluates `source` as an arithmetic expression.
+    ///
+    fn eval(source: &str, env: &mut Environment) -> Result<i64> {
+        evaluate(&Parser::new().parse_arithmetic(source).expect("should parse"), env)
+    }
+
+    #[test]
+    fn test_evaluate_follows_precedence() {
+        let env = &mut Environment::empty();
+
+        assert_eq!(Ok(23), eval("5 + 2 * 3 ** 2", env));
+        assert_eq!(Ok(1), eval("7 / 2 - 7 % 2 * 2 == 1 || 1 < 0", env));
+        assert_eq!(Ok(-6), eval("~5 & -2 | 4 ^ 4", env));
+        assert_eq!(Ok(2), eval("1 ? 0 ? 1 : 2 : 3", env));
+    }
+
+    #[test]
+    fn test_evaluate_reads_and_assigns_variables() {
+        let env = &mut Environment::empty();
+        env.set("x".to_owned(), "4".to_owned());
+
+        assert_eq!(Ok(4), eval("y = x++", env));
+        assert_eq!(Ok(24), eval("y *= ++x", env));
+        assert_eq!(Some("6".to_owned()), env.get(&"x".to_owned()));
+        assert_eq!(Some("24".to_owned()), env.get(&"y".to_owned()));
+        assert_eq!(Ok(0), eval("unset + $empty", env));
+    }
+
+    #[test]
+    fn test_evaluate_short_circuits_logical_operators() {
+        let env = &mut Environment::empty();
+
+        assert_eq!(Ok(0), eval("0 && (x = 1)", env));
+        assert_eq!(Ok(1), eval("1 || x++", env));
+        assert_eq!(None, env.get(&"x".to_owned()));
+    }
+
+    #[test]
+    fn test_evaluate_fails_on_invalid_operations() {
+        let env = &mut Environment::empty();
+        env.set("x".to_owned(), "abc".to_owned());
+
+        assert_eq!(Err(Error::DivisionByZero), eval("1 / 0", env));
+        assert_eq!(Err(Error::NegativeExponent), eval("2 ** -1", env));
+        assert_eq!(Err(Error::InvalidNumber { name: "x".to_owned(), value: "abc".to_owned() }), eval("x + 1", env));
+    }
+}
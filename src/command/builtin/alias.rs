@@ -0,0 +1,123 @@
+use std::io::Write;
+
+use command::{
+    Context,
+    ExitStatus,
+    Result,
+};
+
+pub fn alias(Context { args, registry, mut io, .. }: Context) -> Result {
+    if args.is_empty() {
+        for (name, value) in registry.aliases() {
+            writeln!(io.stdout, "alias {}={}", name, quote(&value))?;
+        }
+        return Ok(ExitStatus::Success(0));
+    }
+
+    let mut code = 0;
+    for arg in args {
+        let mut split = arg.splitn(2, '=');
+        let name = split.next().unwrap_or_default();
+        match (split.next(), registry.alias(name)) {
+            (Some(_), _) if name.is_empty() || name.contains('/') => {
+                writeln!(io.stderr, "gesh: alias: {}: invalid alias name", name)?;
+                code = 1;
+            },
+            (Some(value), _) => registry.define_alias(name.to_owned(), value.to_owned()),
+            (None, Some(value)) => writeln!(io.stdout, "alias {}={}", name, quote(&value))?,
+            (None, None) => {
+                writeln!(io.stderr, "gesh: alias: {}: not found", name)?;
+                code = 1;
+            },
+        }
+    }
+
+    Ok(ExitStatus::Success(code))
+}
+
+pub fn unalias(Context { args, registry, mut io, .. }: Context) -> Result {
+    if args.is_empty() {
+        writeln!(io.stderr, "gesh: unalias: usage: unalias [-a] name [name ...]")?;
+        return Ok(ExitStatus::Success(2));
+    }
+
+    if args[0] == "-a" {
+        registry.remove_all_aliases();
+        return Ok(ExitStatus::Success(0));
+    }
+
+    let mut code = 0;
+    for name in args {
+        if !registry.remove_alias(&name) {
+            writeln!(io.stderr, "gesh: unalias: {}: not found", name)?;
+            code = 1;
+        }
+    }
+
+    Ok(ExitStatus::Success(code))
+}
+
+/// Quotes an alias value so that it can be given back to `alias`.
+///
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        Io,
+        Registry,
+    };
+    use environment::Environment;
+    use jobs::JobTable;
+    use super::*;
+
+    #[test]
+    fn test_alias_defines_aliases() {
+        let env = &mut Environment::empty();
+        let args = vec!["ll=ls -l".to_owned(), "say=echo 'hi'".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
+
+        let result = alias(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!(Some("ls -l".to_owned()), registry.alias("ll"));
+        assert_eq!(Some("echo 'hi'".to_owned()), registry.alias("say"));
+    }
+
+    #[test]
+    fn test_alias_fails_for_unknown_alias() {
+        let env = &mut Environment::empty();
+        let args = vec!["nope".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
+
+        let result = alias(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+    }
+
+    #[test]
+    fn test_unalias_removes_aliases() {
+        let env = &mut Environment::empty();
+        let args = vec!["ll".to_owned(), "nope".to_owned()];
+        let registry = &Registry::new();
+        registry.define_alias("ll".to_owned(), "ls -l".to_owned());
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
+
+        let result = unalias(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+        assert_eq!(None, registry.alias("ll"));
+    }
+
+    #[test]
+    fn test_quote_escapes_single_quotes() {
+        assert_eq!("'echo '\\''hi'\\'''", quote("echo 'hi'"));
+    }
+}
@@ -0,0 +1,65 @@
+use geshl::Parser;
+
+use std::io::Write;
+
+use arithmetic;
+
+use command::{
+    Context,
+    ExitStatus,
+    Result,
+};
+
+pub fn let_arithmetic(Context { env, args, mut io, .. }: Context) -> Result {
+    if args.is_empty() {
+        writeln!(io.stderr, "gesh: let: expression expected")?;
+        return Ok(ExitStatus::Success(1));
+    }
+
+    let parser = Parser::new();
+    let mut value = 0;
+    for arg in args {
+        let expression = match parser.parse_arithmetic(&arg) {
+            Ok(expression) => expression,
+            Err(_) => {
+                writeln!(io.stderr, "gesh: let: {}: syntax error in expression", arg)?;
+                return Ok(ExitStatus::Success(1));
+            },
+        };
+
+        value = match arithmetic::evaluate(&expression, env) {
+            Ok(value) => value,
+            Err(err) => {
+                writeln!(io.stderr, "gesh: let: {}", err)?;
+                return Ok(ExitStatus::Success(1));
+            },
+        };
+    }
+
+    Ok(ExitStatus::Success(if value == 0 { 1 } else { 0 }))
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        Io,
+        Registry,
+    };
+    use environment::Environment;
+    use jobs::JobTable;
+    use super::*;
+
+    #[test]
+    fn test_let_assigns_and_uses_last_value_for_status() {
+        let env = &mut Environment::empty();
+        let args = vec!["x = 3".to_owned(), "y = x * 2".to_owned(), "x - 3".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
+
+        let result = let_arithmetic(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+        assert_eq!(Some("6".to_owned()), env.get(&"y".to_owned()));
+    }
+}
@@ -0,0 +1,47 @@
+use std::io::Write;
+
+use command::{
+    Context,
+    ExitStatus,
+    Result,
+};
+
+use super::jobs::find_job;
+
+pub fn bg(Context { args, jobs, mut io, .. }: Context) -> Result {
+    let id = match find_job("bg", &args, jobs, &mut io.stderr) {
+        Some(id) => id,
+        None => return Ok(ExitStatus::Success(1)),
+    };
+
+    jobs.resume(id)?;
+    if let Some(job) = jobs.get(id) {
+        writeln!(io.stdout, "[{}] {} &", job.id, job.command)?;
+    }
+
+    Ok(ExitStatus::Success(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        Io,
+        Registry,
+    };
+    use environment::Environment;
+    use jobs::JobTable;
+    use super::*;
+
+    #[test]
+    fn test_bg_returns_error_when_no_such_job() {
+        let env = &mut Environment::empty();
+        let args = vec!["%2".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
+
+        let result = bg(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+    }
+}
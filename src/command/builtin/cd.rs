@@ -1,6 +1,15 @@
 use std::{
-    env,
-    path::PathBuf,
+    ffi::CStr,
+    fs,
+    io::{
+        self,
+        Write,
+    },
+    path::{
+        Component,
+        Path,
+        PathBuf,
+    },
 };
 
 use command::{
@@ -9,36 +18,199 @@ use command::{
     Result,
 };
 
-pub fn cd(Context { env, args, .. }: Context) -> Result {
-    let new_dir = match args.len() {
-        0 => env::home_dir(),
-        1 => PathBuf::from(&args[0]).canonicalize().ok(),
-        _ => return Ok(ExitStatus::Success(2)),
+use environment::Environment;
+
+use libc;
+
+pub fn cd(Context { env, args, mut io, .. }: Context) -> Result {
+    let mut physical = false;
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        if arg == "--" {
+            index += 1;
+            break;
+        } else if arg == "-" || !arg.starts_with('-') {
+            break;
+        }
+
+        for flag in arg[1..].chars() {
+            match flag {
+                'L' => physical = false,
+                'P' => physical = true,
+                _ => {
+                    writeln!(io.stderr, "gesh: cd: -{}: invalid option", flag)?;
+                    writeln!(io.stderr, "gesh: cd: usage: cd [-L|-P] [dir]")?;
+                    return Ok(ExitStatus::Success(2));
+                },
+            }
+        }
+        index += 1;
+    }
+
+    let (target, print) = match &args[index..] {
+        [] => match env.get(&"HOME".to_owned()) {
+            Some(home) => (home, false),
+            None => {
+                writeln!(io.stderr, "gesh: cd: HOME not set")?;
+                return Ok(ExitStatus::Success(1));
+            },
+        },
+        [dir] if dir == "-" => match env.get(&"OLDPWD".to_owned()) {
+            Some(previous) => (previous, true),
+            None => {
+                writeln!(io.stderr, "gesh: cd: OLDPWD not set")?;
+                return Ok(ExitStatus::Success(1));
+            },
+        },
+        [dir] => (dir.clone(), false),
+        _ => {
+            writeln!(io.stderr, "gesh: cd: too many arguments")?;
+            return Ok(ExitStatus::Success(2));
+        },
     };
 
-    match new_dir {
-        Some(dir) => {
+    match find_directory(env, &target, physical) {
+        Ok((dir, searched)) => {
+            if print || searched {
+                writeln!(io.stdout, "{}", dir.display())?;
+            }
             env.set_working_directory(dir);
             Ok(ExitStatus::Success(0))
         },
-        None => Ok(ExitStatus::Success(1))
+        Err(err) => {
+            writeln!(io.stderr, "gesh: cd: {}: {}", target, describe(&err))?;
+            Ok(ExitStatus::Success(1))
+        },
+    }
+}
+
+/// Finds the directory that `target` refers to, searching `CDPATH` for it if it's relative.
+///
+/// Returns the directory, along with whether or not it was found with `CDPATH`.
+///
+pub(super) fn find_directory(env: &Environment, target: &str, physical: bool) -> io::Result<(PathBuf, bool)> {
+    let (path, searched) = match search_cdpath(target, env) {
+        Some(path) => (path, true),
+        None => (env.resolve_path(target), false),
+    };
+
+    // Like other shells, fall back to the physical directory if the logical one doesn't exist,
+    // which happens when `..` follows a symbolic link to a directory elsewhere
+    let dir = match directory(env, &path, physical) {
+        Err(_) if !physical => directory(env, &path, true)?,
+        result => result?,
+    };
+    Ok((dir, searched))
+}
+
+/// Searches the directories in `CDPATH` for `dir`, returning the first match.
+///
+/// Only relative paths that don't start with `.` or `..` are searched for. An empty entry in
+/// `CDPATH` is the working directory, but since a match there is the same as not searching at
+/// all, `None` is returned for it.
+///
+fn search_cdpath(dir: &str, env: &Environment) -> Option<PathBuf> {
+    match Path::new(dir).components().next() {
+        Some(Component::Normal(_)) => {},
+        _ => return None,
+    }
+
+    let cdpath = env.get(&"CDPATH".to_owned())?;
+    for entry in cdpath.split(':') {
+        let candidate = env.resolve_path(if entry.is_empty() { "." } else { entry }).join(dir);
+        if candidate.is_dir() {
+            return if entry.is_empty() { None } else { Some(candidate) };
+        }
+    }
+    None
+}
+
+/// Returns the directory that `path` refers to.
+///
+/// With `physical`, all symbolic links are resolved. Otherwise, they're kept, and a `..`
+/// component removes the component before it.
+///
+pub(super) fn directory(env: &Environment, path: &Path, physical: bool) -> io::Result<PathBuf> {
+    let dir = if physical {
+        env.canonicalize_path(path)?
+    } else {
+        normalize(path)
+    };
+
+    if fs::metadata(&dir)?.is_dir() {
+        Ok(dir)
+    } else {
+        Err(io::Error::from_raw_os_error(libc::ENOTDIR))
+    }
+}
+
+/// Removes all `.` and `..` components from `path`, without looking at the file system.
+///
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            component => normalized.push(component.as_os_str()),
+        }
+    }
+    normalized
+}
+
+/// Describes an error the way other shells do, without the error code.
+///
+pub(super) fn describe(err: &io::Error) -> String {
+    match err.raw_os_error() {
+        Some(code) => unsafe { CStr::from_ptr(libc::strerror(code)) }.to_string_lossy().into_owned(),
+        None => err.to_string(),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use command::Registry;
+    use command::{
+        Io,
+        Registry,
+    };
     use environment::Environment;
-    use std::env;
+    use jobs::JobTable;
+    use command::pipe;
+    use std::{
+        env,
+        io::Read,
+        os::unix::fs::symlink,
+        process,
+    };
     use super::*;
 
+    /// Runs `cd` with `args`, returning its result and what it printed.
+    ///
+    fn run(env: &mut Environment, args: &[&str]) -> (Result, String) {
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let (mut reader, writer) = pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::null().expect("io") };
+
+        let result = cd(Context { env, args, registry, jobs, io });
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+        (result, output)
+    }
+
     #[test]
     fn test_cd_switches_to_home_dir_with_no_arguments() {
         let env = &mut Environment::from_existing_env();
         let args = vec![];
-        let registry = &Registry::for_env(&env);
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
 
-        let result = cd(Context { env, args, registry });
+        let result = cd(Context { env, args, registry, jobs, io });
 
         assert_eq!(Ok(ExitStatus::Success(0)), result);
         assert_eq!(env::home_dir().unwrap(), *env.working_directory());
@@ -47,23 +219,101 @@ mod tests {
     #[test]
     fn test_cd_switches_to_canonical_form_of_given_directory() {
         let env = &mut Environment::from_existing_env();
-        let args = vec![String::from(env::temp_dir().to_string_lossy())];
-        let registry = &Registry::for_env(&env);
+        let args = vec!["-P".to_owned(), String::from(env::temp_dir().to_string_lossy())];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
 
-        let result = cd(Context { env, args, registry });
+        let result = cd(Context { env, args, registry, jobs, io });
 
         assert_eq!(Ok(ExitStatus::Success(0)), result);
         assert_eq!(env::temp_dir().canonicalize().unwrap(), *env.working_directory());
     }
 
+    #[test]
+    fn test_cd_resolves_relative_directory_against_working_directory() {
+        let env = &mut Environment::from_existing_env();
+        env.set_working_directory(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src"));
+        let args = vec!["command/builtin".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
+
+        let result = cd(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert!(env.working_directory().ends_with("src/command/builtin"));
+    }
+
+    #[test]
+    fn test_cd_keeps_symbolic_links_unless_physical() {
+        let base = env::temp_dir().canonicalize().unwrap().join(format!("gesh-cd-{}", process::id()));
+        fs::create_dir_all(base.join("real")).expect("create directory");
+        let _ = symlink(base.join("real"), base.join("link"));
+        let env = &mut Environment::from_existing_env();
+        env.set_working_directory(base.clone());
+
+        assert_eq!(Ok(ExitStatus::Success(0)), run(env, &["link"]).0);
+        assert_eq!(base.join("link"), *env.working_directory());
+        assert_eq!(Ok(ExitStatus::Success(0)), run(env, &[".."]).0);
+        assert_eq!(base, *env.working_directory());
+        assert_eq!(Ok(ExitStatus::Success(0)), run(env, &["-P", "link"]).0);
+        assert_eq!(base.join("real"), *env.working_directory());
+
+        fs::remove_dir_all(&base).expect("remove directory");
+    }
+
+    #[test]
+    fn test_cd_switches_to_previous_directory_with_dash() {
+        let env = &mut Environment::from_existing_env();
+        env.set_working_directory(PathBuf::from("/"));
+        assert_eq!(Ok(ExitStatus::Success(0)), run(env, &["/usr"]).0);
+
+        let (result, output) = run(env, &["-"]);
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!("/\n", output);
+        assert_eq!(PathBuf::from("/"), *env.working_directory());
+        assert_eq!(Some("/usr".to_owned()), env.get(&"OLDPWD".to_owned()));
+    }
+
+    #[test]
+    fn test_cd_passes_pwd_and_oldpwd_to_commands() {
+        let vars = vec![("PWD".to_owned(), "/".to_owned()), ("OLDPWD".to_owned(), "/".to_owned())];
+        let env = &mut Environment::new(vars.into_iter().collect());
+        env.set_working_directory(PathBuf::from("/"));
+
+        assert_eq!(Ok(ExitStatus::Success(0)), run(env, &["/usr"]).0);
+
+        let exported = env.exported_vars();
+        assert_eq!(Some(&"/usr".to_owned()), exported.get("PWD"));
+        assert_eq!(Some(&"/".to_owned()), exported.get("OLDPWD"));
+    }
+
+    #[test]
+    fn test_cd_searches_cdpath() {
+        let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let env = &mut Environment::from_existing_env();
+        env.set_working_directory(PathBuf::from("/"));
+        env.set("CDPATH".to_owned(), format!("/nonexistent:{}", manifest_dir.display()));
+
+        let (result, output) = run(env, &["src"]);
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!(format!("{}\n", manifest_dir.join("src").display()), output);
+        assert_eq!(manifest_dir.join("src"), *env.working_directory());
+    }
+
     #[test]
     fn test_cd_returns_error_when_too_many_arguments() {
         let env = &mut Environment::from_existing_env();
         let args = vec!["too".to_owned(), "many".to_owned(), "arguments".to_owned()];
         let original_working_directory = env.working_directory().clone();
-        let registry = &Registry::for_env(&env);
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
 
-        let result = cd(Context { env, args, registry });
+        let result = cd(Context { env, args, registry, jobs, io });
 
         assert_eq!(Ok(ExitStatus::Success(2)), result);
         assert_eq!(original_working_directory, *env.working_directory());
@@ -74,9 +324,11 @@ mod tests {
         let env = &mut Environment::from_existing_env();
         let args = vec!["not/a/directory/that/exists".to_owned()];
         let original_working_directory = env.working_directory().clone();
-        let registry = &Registry::for_env(&env);
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
 
-        let result = cd(Context { env, args, registry });
+        let result = cd(Context { env, args, registry, jobs, io });
 
         assert_eq!(Ok(ExitStatus::Success(1)), result);
         assert_eq!(original_working_directory, *env.working_directory());
@@ -1,27 +1,213 @@
+use std::{
+    io::{
+        self,
+        Write,
+    },
+    path::PathBuf,
+};
+
 use command::{
     Context,
-    Error,
     ExitStatus,
     Result,
 };
 
-pub fn dirs(Context { env, .. }: Context) -> Result {
-    let stack = env.directory_stack();
-    if !stack.is_empty() {
-        let mut iter = stack.iter();
-        match iter.next().unwrap().to_str() {
-            Some(string) => print!("{}", string),
-            None => return Err(Error::Unknown),
-        };
-
-        for dir in iter {
-            match dir.to_str() {
-                Some(string) => print!(" {}", string),
-                None => return Err(Error::Unknown),
-            };
+use environment::Environment;
+
+use super::cd;
+
+/// How the directory stack is listed.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(super) struct Listing {
+    /// Lists full paths, instead of replacing the home directory with `~`.
+    ///
+    long: bool,
+
+    /// Lists one entry per line.
+    ///
+    vertical: bool,
+
+    /// Lists one entry per line, along with its index in the stack.
+    ///
+    numbered: bool,
+}
+
+pub fn dirs(Context { env, args, mut io, .. }: Context) -> Result {
+    let mut listing = Listing::default();
+    let mut clear = false;
+    let mut index = None;
+    for arg in &args {
+        if let Some(entry) = stack_index(arg) {
+            if index.is_some() {
+                writeln!(io.stderr, "gesh: dirs: too many arguments")?;
+                return Ok(ExitStatus::Success(1));
+            }
+            index = Some((arg, entry));
+            continue;
+        }
+
+        if !arg.starts_with('-') || arg.len() == 1 {
+            writeln!(io.stderr, "gesh: dirs: {}: invalid argument", arg)?;
+            writeln!(io.stderr, "gesh: dirs: usage: dirs [-clpv] [+N] [-N]")?;
+            return Ok(ExitStatus::Success(2));
+        }
+
+        for flag in arg[1..].chars() {
+            match flag {
+                'c' => clear = true,
+                'l' => listing.long = true,
+                'p' => listing.vertical = true,
+                'v' => listing.numbered = true,
+                _ => {
+                    writeln!(io.stderr, "gesh: dirs: -{}: invalid option", flag)?;
+                    writeln!(io.stderr, "gesh: dirs: usage: dirs [-clpv] [+N] [-N]")?;
+                    return Ok(ExitStatus::Success(2));
+                },
+            }
         }
     }
-    println!();
 
-    Ok(ExitStatus::Success(0))
+    if clear {
+        env.set_directory_stack(Vec::new());
+        return Ok(ExitStatus::Success(0));
+    }
+
+    match index {
+        Some((arg, (n, from_end))) => match env.directory_stack_entry(n, from_end) {
+            Some(dir) => {
+                let dir = if listing.long { String::from(dir.to_string_lossy()) } else { env.abbreviate_home(dir) };
+                writeln!(io.stdout, "{}", dir)?;
+                Ok(ExitStatus::Success(0))
+            },
+            None => {
+                writeln!(io.stderr, "gesh: dirs: {}: directory stack index out of range", arg)?;
+                Ok(ExitStatus::Success(1))
+            },
+        },
+        None => {
+            list(env, listing, &mut io.stdout)?;
+            Ok(ExitStatus::Success(0))
+        },
+    }
+}
+
+/// Writes the entries of the directory stack to `out`, starting with the working directory.
+///
+pub(super) fn list<W: Write>(env: &Environment, listing: Listing, out: &mut W) -> io::Result<()> {
+    let entries = env.directory_stack_entries().map(|dir| {
+        if listing.long {
+            String::from(dir.to_string_lossy())
+        } else {
+            env.abbreviate_home(dir)
+        }
+    });
+
+    if listing.numbered {
+        for (index, entry) in entries.enumerate() {
+            writeln!(out, "{:>2}  {}", index, entry)?;
+        }
+    } else if listing.vertical {
+        for entry in entries {
+            writeln!(out, "{}", entry)?;
+        }
+    } else {
+        writeln!(out, "{}", entries.collect::<Vec<_>>().join(" "))?;
+    }
+    Ok(())
+}
+
+/// Replaces the directory stack with `entries`, in the order `dirs` lists them, changing to
+/// the first of them.
+///
+/// If the first entry isn't a directory, the directory stack is left as it was.
+///
+pub(super) fn replace_stack(env: &mut Environment, mut entries: Vec<PathBuf>) -> io::Result<()> {
+    let first = entries.remove(0);
+    if first != *env.working_directory() {
+        let dir = cd::directory(env, &env.resolve_path(&first), false)?;
+        env.set_working_directory(dir);
+    }
+    env.set_directory_stack(entries);
+    Ok(())
+}
+
+/// Parses a `+N` or `-N` argument, which refers to the `N`th entry in the directory stack,
+/// counting from the start or the end of the list that `dirs` shows.
+///
+/// Returns `N`, and whether or not to count from the end.
+///
+pub(super) fn stack_index(arg: &str) -> Option<(usize, bool)> {
+    let from_end = match arg.chars().next()? {
+        '+' => false,
+        '-' => true,
+        _ => return None,
+    };
+
+    let digits = &arg[1..];
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok().map(|n| (n, from_end))
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        pipe,
+        Io,
+        Registry,
+    };
+    use jobs::JobTable;
+    use std::{
+        collections::HashMap,
+        io::Read,
+    };
+    use super::*;
+
+    /// Runs `dirs` with `args`, returning its result and what it printed.
+    ///
+    fn run(env: &mut Environment, args: &[&str]) -> (Result, String) {
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let (mut reader, writer) = pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let result = dirs(Context { env, args, registry, jobs, io });
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+        (result, output)
+    }
+
+    #[test]
+    fn test_dirs_lists_directory_stack() {
+        let env = &mut Environment::new(HashMap::new());
+        env.set("HOME".to_owned(), "/home/me".to_owned());
+        env.set_working_directory(PathBuf::from("/home/me/src"));
+        env.set_directory_stack(vec![PathBuf::from("/usr"), PathBuf::from("/home/me")]);
+
+        assert_eq!((Ok(ExitStatus::Success(0)), "~/src /usr ~\n".to_owned()), run(env, &[]));
+        assert_eq!((Ok(ExitStatus::Success(0)), "/home/me/src\n/usr\n/home/me\n".to_owned()), run(env, &["-lp"]));
+        assert_eq!((Ok(ExitStatus::Success(0)), " 0  ~/src\n 1  /usr\n 2  ~\n".to_owned()), run(env, &["-v"]));
+        assert_eq!((Ok(ExitStatus::Success(0)), "/usr\n".to_owned()), run(env, &["-1"]));
+    }
+
+    #[test]
+    fn test_dirs_clears_directory_stack() {
+        let env = &mut Environment::new(HashMap::new());
+        env.set_directory_stack(vec![PathBuf::from("/usr")]);
+
+        assert_eq!(Ok(ExitStatus::Success(0)), run(env, &["-c"]).0);
+        assert!(env.directory_stack().is_empty());
+    }
+
+    #[test]
+    fn test_stack_index_parses_counts_from_either_end() {
+        assert_eq!(Some((2, false)), stack_index("+2"));
+        assert_eq!(Some((0, true)), stack_index("-0"));
+        assert_eq!(None, stack_index("-n"));
+        assert_eq!(None, stack_index("+"));
+    }
 }
@@ -7,26 +7,43 @@ use std::{
 };
 
 use command::{
+    self,
     Context,
     Error,
     ExitStatus,
     Result,
 };
 
-pub fn exec(Context { args, env, registry, .. }: Context) -> Result {
+pub fn exec(Context { args, env, registry, io, .. }: Context) -> Result {
     if args.is_empty() {
-        // TODO e.g., exec 2>&1 should make all stderr go to stdout in the shell
+        // Any redirections, like `exec 2>&1`, apply to the shell itself from now on
+        io.replace_shell_streams()?;
         Ok(ExitStatus::Success(0))
     } else {
-        let absolute_command = registry.find_executable(&PathBuf::from(&args[0]));
+        let absolute_command = registry.find_executable(&PathBuf::from(&args[0]), env);
         if let Some(path) = absolute_command {
-            process::Command::new(path)
+            let mut command = process::Command::new(path);
+            let files = env.inherited_files().to_vec();
+            unsafe {
+                command.pre_exec(move || {
+                    command::reset_job_control_signals();
+                    for &fd in &files {
+                        command::keep_open_on_exec(fd)?;
+                    }
+                    Ok(())
+                });
+            }
+
+            let err = command
                 .args(args.iter().skip(1))
                 .envs(env.exported_vars())
                 .current_dir(env.working_directory())
+                .stdin(io.stdin)
+                .stdout(io.stdout)
+                .stderr(io.stderr)
                 .exec();
 
-            Err(Error::Unknown)
+            Err(Error::from(err))
         } else {
             Err(Error::UnknownCommand)
         }
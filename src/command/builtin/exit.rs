@@ -4,9 +4,9 @@ use command::{
     Result,
 };
 
-pub fn exit(Context { args, .. }: Context) -> Result {
+pub fn exit(Context { env, args, .. }: Context) -> Result {
     let status = match args.len() {
-        0 => 0,
+        0 => env.last_status(),
         1 => args[0].parse().unwrap_or(255),
         _ => return Ok(ExitStatus::Success(1)),
     };
@@ -15,17 +15,23 @@ pub fn exit(Context { args, .. }: Context) -> Result {
 
 #[cfg(test)]
 mod tests {
-    use command::Registry;
+    use command::{
+        Io,
+        Registry,
+    };
     use environment::Environment;
+    use jobs::JobTable;
     use super::*;
 
     #[test]
     fn test_exit_returns_exit_with_given_status() {
         let env = &mut Environment::from_existing_env();
         let args = vec!["77".to_owned()];
-        let registry = &Registry::for_env(&env);
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
 
-        let result = exit(Context { env, args, registry });
+        let result = exit(Context { env, args, registry, jobs, io });
 
         assert_eq!(Ok(ExitStatus::ExitWith(77)), result);
     }
@@ -34,9 +40,11 @@ mod tests {
     fn test_exit_returns_zero_exit_status_with_no_arguments() {
         let env = &mut Environment::from_existing_env();
         let args = vec![];
-        let registry = &Registry::for_env(&env);
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
 
-        let result = exit(Context { env, args, registry });
+        let result = exit(Context { env, args, registry, jobs, io });
 
         assert_eq!(Ok(ExitStatus::ExitWith(0)), result);
     }
@@ -45,9 +53,11 @@ mod tests {
     fn test_exit_returns_nonzero_exit_status_with_non_integral_argument() {
         let env = &mut Environment::from_existing_env();
         let args = vec!["abc".to_owned()];
-        let registry = &Registry::for_env(&env);
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
 
-        let result = exit(Context { env, args, registry });
+        let result = exit(Context { env, args, registry, jobs, io });
 
         assert_eq!(Ok(ExitStatus::ExitWith(255)), result);
     }
@@ -56,9 +66,11 @@ mod tests {
     fn test_exit_returns_error_with_too_many_arguments() {
         let env = &mut Environment::from_existing_env();
         let args = vec!["a".to_owned(), "b".to_owned()];
-        let registry = &Registry::for_env(&env);
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
 
-        let result = exit(Context { env, args, registry });
+        let result = exit(Context { env, args, registry, jobs, io });
 
         assert_eq!(Ok(ExitStatus::Success(1)), result);
     }
@@ -1,13 +1,15 @@
+use std::io::Write;
+
 use command::{
     Context,
     ExitStatus,
     Result,
 };
 
-pub fn export(Context { env, args, .. }: Context) -> Result {
+pub fn export(Context { env, args, mut io, .. }: Context) -> Result {
     if args.is_empty() {
         for (key, value) in env.exported_vars() {
-            println!("{}={}", key, value);
+            writeln!(io.stdout, "{}={}", key, value)?;
         }
     } else {
         for arg in args {
@@ -32,17 +34,23 @@ pub fn export(Context { env, args, .. }: Context) -> Result {
 
 #[cfg(test)]
 mod tests {
-    use command::Registry;
+    use command::{
+        Io,
+        Registry,
+    };
     use environment::Environment;
+    use jobs::JobTable;
     use super::*;
 
     #[test]
     fn test_export_prints_all_exported_vars_with_no_arguments() {
         let env = &mut Environment::empty();
         let args = vec![];
-        let registry = &Registry::for_env(&env);
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
 
-        let result = export(Context { env, args, registry });
+        let result = export(Context { env, args, registry, jobs, io });
 
         assert_eq!(Ok(ExitStatus::Success(0)), result);
     }
@@ -58,9 +66,11 @@ mod tests {
             "FOO".to_owned(),
             "SPAM=11 eggs".to_owned(),
         ];
-        let registry = &Registry::for_env(&env);
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
 
-        let result = export(Context { env, args, registry });
+        let result = export(Context { env, args, registry, jobs, io });
 
         assert_eq!(Ok(ExitStatus::Success(0)), result);
         assert_eq!(Some(&"bar".to_owned()), env.exported_vars().get("FOO"));
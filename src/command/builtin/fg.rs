@@ -0,0 +1,77 @@
+use libc;
+
+use std::io::Write;
+
+use command::{
+    Context,
+    ExitStatus,
+    Result,
+};
+
+use jobs::State;
+
+use super::jobs::find_job;
+
+pub fn fg(Context { args, jobs, mut io, .. }: Context) -> Result {
+    let id = match find_job("fg", &args, jobs, &mut io.stderr) {
+        Some(id) => id,
+        None => return Ok(ExitStatus::Success(1)),
+    };
+
+    if let Some(job) = jobs.get(id) {
+        writeln!(io.stdout, "{}", job.command)?;
+    }
+
+    match jobs.foreground(id)? {
+        Some(State::Done(code)) => Ok(ExitStatus::Success(code)),
+        Some(State::Stopped) => {
+            if let Some(job) = jobs.get(id) {
+                writeln!(io.stderr, "\n{}", jobs.describe(job))?;
+            }
+            Ok(ExitStatus::Success(128 + libc::SIGTSTP as u32))
+        },
+        _ => Ok(ExitStatus::Success(1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        self,
+        Group,
+        Io,
+        Registry,
+    };
+    use environment::Environment;
+    use jobs::JobTable;
+    use super::*;
+
+    #[test]
+    fn test_fg_waits_for_job_and_returns_its_status() {
+        let env = &mut Environment::empty();
+        let args = vec!["%1".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let pid = command::fork(Group::New(None), || Ok(ExitStatus::Success(3))).expect("fork");
+        jobs.add(vec![pid], "exit 3".to_owned());
+        let io = Io::null().expect("io");
+
+        let result = fg(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(3)), result);
+        assert!(jobs.is_empty());
+    }
+
+    #[test]
+    fn test_fg_returns_error_when_no_such_job() {
+        let env = &mut Environment::empty();
+        let args = vec![];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
+
+        let result = fg(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+    }
+}
@@ -0,0 +1,100 @@
+use std::io::Write;
+
+use command::{
+    Context,
+    ExitStatus,
+    Result,
+};
+
+pub fn local(Context { env, args, mut io, .. }: Context) -> Result {
+    if !env.in_local_scope() {
+        writeln!(io.stderr, "gesh: local: can only be used in a function")?;
+        return Ok(ExitStatus::Success(1));
+    }
+
+    for arg in args {
+        let mut split = arg.splitn(2, '=');
+        let name = split.next().unwrap_or_default().to_owned();
+        let value = split.next().map(|value| value.to_owned());
+        env.declare_local(name, value);
+    }
+
+    Ok(ExitStatus::Success(0))
+}
+
+pub fn return_from_function(Context { env, args, mut io, .. }: Context) -> Result {
+    if !env.in_local_scope() {
+        writeln!(io.stderr, "gesh: return: can only `return' from a function")?;
+        return Ok(ExitStatus::Success(1));
+    }
+
+    match args.first() {
+        None => Ok(ExitStatus::Return(env.last_status())),
+        Some(arg) => match arg.parse::<u32>() {
+            Ok(code) => Ok(ExitStatus::Return(code & 0xff)),
+            Err(_) => {
+                writeln!(io.stderr, "gesh: return: {}: numeric argument required", arg)?;
+                Ok(ExitStatus::Return(2))
+            },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        Io,
+        Registry,
+    };
+    use environment::Environment;
+    use jobs::JobTable;
+    use super::*;
+
+    #[test]
+    fn test_local_sets_variables_until_scope_ends() {
+        let env = &mut Environment::empty();
+        env.set("FOO".to_owned(), "outer".to_owned());
+        env.push_scope();
+
+        let args = vec!["FOO=inner".to_owned(), "BAR".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
+
+        let result = local(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!(Some("inner".to_owned()), env.get(&"FOO".to_owned()));
+        env.pop_scope();
+        assert_eq!(Some("outer".to_owned()), env.get(&"FOO".to_owned()));
+    }
+
+    #[test]
+    fn test_local_fails_outside_of_function() {
+        let env = &mut Environment::empty();
+        let args = vec!["FOO=bar".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
+
+        let result = local(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+        assert_eq!(None, env.get(&"FOO".to_owned()));
+    }
+
+    #[test]
+    fn test_return_gives_status_to_function() {
+        let env = &mut Environment::empty();
+        env.push_scope();
+
+        let args = vec!["3".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
+
+        let result = return_from_function(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Return(3)), result);
+    }
+}
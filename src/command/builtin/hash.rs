@@ -0,0 +1,170 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+};
+
+use command::{
+    Context,
+    ExitStatus,
+    Result,
+};
+
+pub fn hash(Context { env, args, registry, mut io, .. }: Context) -> Result {
+    let mut reset = false;
+    let mut delete = false;
+    let mut print = false;
+    let mut path = None;
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        index += 1;
+        if arg == "--" {
+            break;
+        } else if !arg.starts_with('-') || arg.len() == 1 {
+            index -= 1;
+            break;
+        }
+
+        for flag in arg[1..].chars() {
+            match flag {
+                'd' => delete = true,
+                'r' => reset = true,
+                't' => print = true,
+                'p' => match args.get(index) {
+                    Some(value) => {
+                        path = Some(PathBuf::from(value));
+                        index += 1;
+                    },
+                    None => {
+                        writeln!(io.stderr, "gesh: hash: -p: option requires an argument")?;
+                        return Ok(ExitStatus::Success(2));
+                    },
+                },
+                _ => {
+                    writeln!(io.stderr, "gesh: hash: -{}: invalid option", flag)?;
+                    writeln!(io.stderr, "gesh: hash: usage: hash [-rdt] [-p pathname] [name ...]")?;
+                    return Ok(ExitStatus::Success(2));
+                },
+            }
+        }
+    }
+
+    let names = &args[index..];
+    if reset {
+        registry.forget_all_hashed();
+    }
+
+    if names.is_empty() {
+        if reset || path.is_some() {
+            return Ok(ExitStatus::Success(0));
+        }
+
+        let hashed = registry.hashed_commands(env);
+        if hashed.is_empty() {
+            writeln!(io.stderr, "gesh: hash: hash table empty")?;
+        } else {
+            writeln!(io.stdout, "hits\tcommand")?;
+            for (_, path, hits) in hashed {
+                writeln!(io.stdout, "{:>4}\t{}", hits, path.display())?;
+            }
+        }
+        return Ok(ExitStatus::Success(0));
+    }
+
+    let mut code = 0;
+    for name in names {
+        let found = if let Some(ref path) = path {
+            registry.hash_command(name.clone(), path.clone(), env);
+            true
+        } else if delete {
+            registry.forget_hashed(name, env)
+        } else if print {
+            match registry.hashed(name, env) {
+                Some(path) => {
+                    if names.len() > 1 {
+                        write!(io.stdout, "{}\t", name)?;
+                    }
+                    writeln!(io.stdout, "{}", path.display())?;
+                    true
+                },
+                None => false,
+            }
+        } else if name.contains('/') || registry.is_internal(name) {
+            // Like other shells, quietly ignore commands that are never looked up on the path
+            true
+        } else {
+            match registry.search_path(name, env).next() {
+                Some(path) => {
+                    registry.hash_command(name.clone(), path, env);
+                    true
+                },
+                None => false,
+            }
+        };
+
+        if !found {
+            writeln!(io.stderr, "gesh: hash: {}: not found", name)?;
+            code = 1;
+        }
+    }
+
+    Ok(ExitStatus::Success(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        pipe,
+        Io,
+        Registry,
+    };
+    use environment::Environment;
+    use jobs::JobTable;
+    use std::{
+        collections::HashMap,
+        io::Read,
+    };
+    use super::*;
+
+    /// Returns variables where `PATH` is `paths`.
+    ///
+    fn path(paths: &str) -> HashMap<String, String> {
+        vec![("PATH".to_owned(), paths.to_owned())].into_iter().collect()
+    }
+
+    /// Runs `hash` with `args`, returning its result and what it printed.
+    ///
+    fn run(env: &mut Environment, registry: &Registry, args: &[&str]) -> (Result, String) {
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        let jobs = &mut JobTable::new();
+        let (mut reader, writer) = pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::null().expect("io") };
+
+        let result = hash(Context { env, args, registry, jobs, io });
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+        (result, output)
+    }
+
+    #[test]
+    fn test_hash_remembers_and_forgets_commands() {
+        let env = &mut Environment::new(path("/nonexistent:/bin"));
+        let registry = &Registry::new();
+
+        assert_eq!((Ok(ExitStatus::Success(0)), String::new()), run(env, registry, &["sh", "cd"]));
+        assert_eq!((Ok(ExitStatus::Success(0)), "/bin/sh\n".to_owned()), run(env, registry, &["-t", "sh"]));
+        assert_eq!((Ok(ExitStatus::Success(0)), "hits\tcommand\n   0\t/bin/sh\n".to_owned()), run(env, registry, &[]));
+
+        assert_eq!(Ok(ExitStatus::Success(0)), run(env, registry, &["-r"]).0);
+        assert_eq!(None, registry.hashed("sh", env));
+    }
+
+    #[test]
+    fn test_hash_fails_for_commands_not_on_path() {
+        let env = &mut Environment::new(path("/nonexistent"));
+        let registry = &Registry::new();
+
+        assert_eq!(Ok(ExitStatus::Success(1)), run(env, registry, &["sh"]).0);
+        assert_eq!(Ok(ExitStatus::Success(1)), run(env, registry, &["-d", "sh"]).0);
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    fs::File,
+    io::Write,
+};
+
+use command::{
+    Context,
+    ExitStatus,
+    Result,
+};
+
+use jobs::JobTable;
+
+pub fn jobs(Context { jobs, mut io, .. }: Context) -> Result {
+    jobs.update()?;
+    for job in jobs.iter() {
+        writeln!(io.stdout, "{}", jobs.describe(job))?;
+    }
+
+    // Finished jobs are only reported once
+    jobs.remove_finished();
+
+    Ok(ExitStatus::Success(0))
+}
+
+/// Finds the job referenced by the arguments of `builtin`, defaulting to the current job.
+///
+/// If there isn't exactly one such job, an error is written to `stderr`.
+///
+pub fn find_job(builtin: &str, args: &[String], jobs: &JobTable, stderr: &mut File) -> Option<usize> {
+    let spec = match args.len() {
+        0 => "%+",
+        1 => &args[0],
+        _ => {
+            let _ = writeln!(stderr, "gesh: {}: too many arguments", builtin);
+            return None;
+        },
+    };
+
+    let id = jobs.find(spec);
+    if id.is_none() {
+        let _ = writeln!(stderr, "gesh: {}: {}: no such job", builtin, spec);
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        self,
+        Group,
+        Io,
+        Registry,
+        pipe,
+    };
+    use environment::Environment;
+    use libc;
+    use std::{
+        io::Read,
+        thread,
+        time::Duration,
+    };
+    use super::*;
+
+    #[test]
+    fn test_jobs_lists_jobs() {
+        let env = &mut Environment::empty();
+        let args = vec![];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let pid = command::fork(Group::New(None), || {
+            thread::sleep(Duration::from_secs(10));
+            Ok(ExitStatus::Success(0))
+        }).expect("fork");
+        jobs.add(vec![pid], "sleep 10".to_owned());
+        let (mut reader, writer) = pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let result = super::jobs(Context { env, args, registry, jobs, io });
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+        unsafe { libc::kill(pid, libc::SIGKILL) };
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!("[1]+  Running                 sleep 10 &\n", output);
+    }
+
+    #[test]
+    fn test_find_job_defaults_to_current_job() {
+        let jobs = &mut JobTable::new();
+        jobs.add(vec![100], "a".to_owned());
+        jobs.add(vec![200], "b".to_owned());
+        let stderr = &mut File::create("/dev/null").expect("null");
+
+        assert_eq!(Some(2), find_job("fg", &[], jobs, stderr));
+        assert_eq!(Some(1), find_job("fg", &["%1".to_owned()], jobs, stderr));
+        assert_eq!(None, find_job("fg", &["%3".to_owned()], jobs, stderr));
+        assert_eq!(None, find_job("fg", &["%1".to_owned(), "%2".to_owned()], jobs, stderr));
+    }
+}
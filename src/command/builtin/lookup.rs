@@ -0,0 +1,320 @@
+use std::{
+    io::{
+        self,
+        Write,
+    },
+    path::PathBuf,
+};
+
+use command::{
+    Context,
+    Error,
+    Executable,
+    ExitStatus,
+    Registry,
+    Result,
+};
+
+use environment::Environment;
+
+use geshl::{
+    Function,
+    ParsedLine,
+    Parser,
+};
+
+/// Something a name can refer to when it's used as a command.
+///
+#[derive(Clone, Debug, PartialEq)]
+enum Kind {
+    Alias(String),
+    Keyword,
+    Function(ParsedLine),
+    Builtin,
+    Hashed(PathBuf),
+    File(PathBuf),
+}
+
+pub fn type_of_command(Context { env, args, registry, mut io, .. }: Context) -> Result {
+    let (flags, names) = split_flags(&args);
+    let all = flags.contains('a');
+    let path_only = flags.contains('p');
+    let terse = flags.contains('t');
+    if let Some(flag) = flags.chars().find(|flag| !"apt".contains(*flag)) {
+        writeln!(io.stderr, "gesh: type: -{}: invalid option", flag)?;
+        writeln!(io.stderr, "gesh: type: usage: type [-apt] name [name ...]")?;
+        return Ok(ExitStatus::Success(2));
+    }
+
+    let mut code = 0;
+    for name in names {
+        let kinds = kinds(name, env, registry, all);
+        if kinds.is_empty() {
+            if !terse && !path_only {
+                writeln!(io.stderr, "gesh: type: {}: not found", name)?;
+            }
+            code = 1;
+        }
+
+        for kind in kinds {
+            match kind {
+                Kind::Hashed(path) | Kind::File(path) if path_only || terse => {
+                    writeln!(io.stdout, "{}", if terse { "file".into() } else { path.display().to_string() })?;
+                },
+                _ if path_only => {},
+                kind if terse => writeln!(io.stdout, "{}", match kind {
+                    Kind::Alias(_) => "alias",
+                    Kind::Keyword => "keyword",
+                    Kind::Function(_) => "function",
+                    _ => "builtin",
+                })?,
+                kind => describe(name, kind, &mut io.stdout)?,
+            }
+        }
+    }
+
+    Ok(ExitStatus::Success(code))
+}
+
+pub fn command(Context { env, args, registry, jobs, mut io }: Context) -> Result {
+    let (flags, names) = split_flags(&args);
+    if let Some(flag) = flags.chars().find(|flag| !"vV".contains(*flag)) {
+        writeln!(io.stderr, "gesh: command: -{}: invalid option", flag)?;
+        writeln!(io.stderr, "gesh: command: usage: command [-vV] command [arg ...]")?;
+        return Ok(ExitStatus::Success(2));
+    }
+
+    if !flags.is_empty() {
+        let mut code = 0;
+        for name in names {
+            match kinds(name, env, registry, false).pop() {
+                Some(kind) if flags.contains('V') => describe(name, kind, &mut io.stdout)?,
+                Some(Kind::Alias(value)) => writeln!(io.stdout, "alias {}='{}'", name, value)?,
+                Some(Kind::Hashed(path)) | Some(Kind::File(path)) => writeln!(io.stdout, "{}", path.display())?,
+                Some(_) => writeln!(io.stdout, "{}", name)?,
+                None => {
+                    if flags.contains('V') {
+                        writeln!(io.stderr, "gesh: command: {}: not found", name)?;
+                    }
+                    code = 1;
+                },
+            }
+        }
+        return Ok(ExitStatus::Success(code));
+    }
+
+    // Runs the command without looking for a function with its name
+    let (name, args) = match names.split_first() {
+        Some((name, args)) => (name, args.to_vec()),
+        None => return Ok(ExitStatus::Success(0)),
+    };
+
+    if let Some(builtin) = registry.builtin(name) {
+        return builtin(Context { env, args, registry, jobs, io });
+    }
+
+    match registry.find_executable(&PathBuf::from(name), env) {
+        Some(path) => Executable::new(path).execute(Context { env, args, registry, jobs, io }),
+        None => {
+            writeln!(io.stderr, "gesh: {}: command not found", name)?;
+            Err(Error::UnknownCommand)
+        },
+    }
+}
+
+pub fn which(Context { env, args, registry, mut io, .. }: Context) -> Result {
+    let (flags, names) = split_flags(&args);
+    if let Some(flag) = flags.chars().find(|flag| *flag != 'a') {
+        writeln!(io.stderr, "gesh: which: -{}: invalid option", flag)?;
+        writeln!(io.stderr, "gesh: which: usage: which [-a] name [name ...]")?;
+        return Ok(ExitStatus::Success(2));
+    }
+
+    let mut code = 0;
+    for name in names {
+        let mut paths: Vec<_> = if name.contains('/') {
+            registry.find_executable(&PathBuf::from(name), env).into_iter().collect()
+        } else {
+            registry.search_path(name, env).collect()
+        };
+        if !flags.contains('a') {
+            paths.truncate(1);
+        }
+
+        if paths.is_empty() {
+            code = 1;
+        }
+        for path in paths {
+            writeln!(io.stdout, "{}", path.display())?;
+        }
+    }
+
+    Ok(ExitStatus::Success(code))
+}
+
+/// Splits leading `-abc` style arguments from the rest, returning all of their flags together.
+///
+/// A `--` argument ends the flags, and isn't included in the rest.
+///
+fn split_flags(args: &[String]) -> (String, &[String]) {
+    let mut flags = String::new();
+    for (index, arg) in args.iter().enumerate() {
+        if arg == "--" {
+            return (flags, &args[index + 1..]);
+        } else if !arg.starts_with('-') || arg.len() == 1 {
+            return (flags, &args[index..]);
+        }
+        flags.push_str(&arg[1..]);
+    }
+    (flags, &[])
+}
+
+/// Finds what `name` refers to when it's used as a command, in the order the shell looks for
+/// them.
+///
+/// Only the first is returned, unless `all` is set, in which case every executable on the path
+/// with that name is also included.
+///
+fn kinds(name: &str, env: &Environment, registry: &Registry, all: bool) -> Vec<Kind> {
+    let mut kinds = Vec::new();
+    if let Some(value) = registry.alias(name) {
+        kinds.push(Kind::Alias(value));
+    }
+    if Parser::new().is_reserved_word(name) {
+        kinds.push(Kind::Keyword);
+    }
+    if let Some(body) = registry.function(name) {
+        kinds.push(Kind::Function(body));
+    }
+    if registry.builtin(name).is_some() {
+        kinds.push(Kind::Builtin);
+    }
+
+    if name.contains('/') {
+        kinds.extend(registry.find_executable(&PathBuf::from(name), env).map(Kind::File));
+    } else if all {
+        kinds.extend(registry.search_path(name, env).map(Kind::File));
+    } else if let Some(path) = registry.hashed(name, env) {
+        kinds.push(Kind::Hashed(path));
+    } else {
+        kinds.extend(registry.search_path(name, env).next().map(Kind::File));
+    }
+
+    if !all {
+        kinds.truncate(1);
+    }
+    kinds
+}
+
+/// Writes a sentence describing what `name` refers to.
+///
+fn describe<W: Write>(name: &str, kind: Kind, out: &mut W) -> io::Result<()> {
+    match kind {
+        Kind::Alias(value) => writeln!(out, "{} is aliased to `{}'", name, value)?,
+        Kind::Keyword => writeln!(out, "{} is a shell keyword", name)?,
+        Kind::Function(body) => {
+            writeln!(out, "{} is a function", name)?;
+            writeln!(out, "{}", Function { name: name.to_owned(), body: Box::new(body) })?;
+        },
+        Kind::Builtin => writeln!(out, "{} is a shell builtin", name)?,
+        Kind::Hashed(path) => writeln!(out, "{} is hashed ({})", name, path.display())?,
+        Kind::File(path) => writeln!(out, "{} is {}", name, path.display())?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        pipe,
+        Io,
+    };
+    use jobs::JobTable;
+    use std::{
+        io::Read,
+    };
+    use super::*;
+
+    /// Runs `builtin` with `args`, returning its result and what it printed.
+    ///
+    fn run(builtin: fn(Context) -> Result, env: &mut Environment, registry: &Registry, args: &[&str]) -> (Result, String) {
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        let jobs = &mut JobTable::new();
+        let (mut reader, writer) = pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::null().expect("io") };
+
+        let result = builtin(Context { env, args, registry, jobs, io });
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+        (result, output)
+    }
+
+    /// Returns an environment that only finds commands in `/bin`, and a registry for it with an
+    /// alias and a function.
+    ///
+    fn setup() -> (Environment, Registry) {
+        let env = Environment::new(vec![("PATH".to_owned(), "/nonexistent:/bin".to_owned())].into_iter().collect());
+        let registry = Registry::new();
+        registry.define_alias("ll".to_owned(), "ls -l".to_owned());
+        let body = Parser::new().parse("echo hi").expect("should parse").remove(0);
+        registry.define_function("greet".to_owned(), body);
+        (env, registry)
+    }
+
+    #[test]
+    fn test_type_describes_each_kind_of_command() {
+        let (ref mut env, ref registry) = setup();
+
+        assert_eq!(
+            (
+                Ok(ExitStatus::Success(0)),
+                "ll is aliased to `ls -l'\nif is a shell keyword\ngreet is a function\ngreet() { echo hi; }\n\
+                 cd is a shell builtin\nsh is /bin/sh\n".to_owned(),
+            ),
+            run(type_of_command, env, registry, &["ll", "if", "greet", "cd", "sh"])
+        );
+        assert_eq!(
+            (Ok(ExitStatus::Success(1)), "builtin\nfile\n".to_owned()),
+            run(type_of_command, env, registry, &["-t", "cd", "sh", "nonexistent"])
+        );
+    }
+
+    #[test]
+    fn test_type_reports_hashed_commands() {
+        let (ref mut env, ref registry) = setup();
+        registry.find_executable(&PathBuf::from("sh"), env);
+
+        assert_eq!(
+            (Ok(ExitStatus::Success(0)), "sh is hashed (/bin/sh)\n".to_owned()),
+            run(type_of_command, env, registry, &["sh"])
+        );
+    }
+
+    #[test]
+    fn test_command_prints_how_names_are_found() {
+        let (ref mut env, ref registry) = setup();
+
+        assert_eq!(
+            (Ok(ExitStatus::Success(1)), "alias ll='ls -l'\ngreet\ncd\n/bin/sh\n".to_owned()),
+            run(command, env, registry, &["-v", "ll", "greet", "cd", "sh", "nonexistent"])
+        );
+    }
+
+    #[test]
+    fn test_command_skips_functions() {
+        let (ref mut env, ref registry) = setup();
+        let body = Parser::new().parse("return 3").expect("should parse").remove(0);
+        registry.define_function("true".to_owned(), body);
+
+        assert_eq!(Ok(ExitStatus::Success(0)), run(command, env, registry, &["true"]).0);
+    }
+
+    #[test]
+    fn test_which_prints_executables_on_path() {
+        let (ref mut env, ref registry) = setup();
+
+        assert_eq!((Ok(ExitStatus::Success(0)), "/bin/sh\n".to_owned()), run(which, env, registry, &["sh"]));
+        assert_eq!((Ok(ExitStatus::Success(1)), String::new()), run(which, env, registry, &["cd"]));
+    }
+}
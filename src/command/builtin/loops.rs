@@ -0,0 +1,126 @@
+use std::{
+    fs::File,
+    io::Write,
+};
+
+use command::{
+    Context,
+    ExitStatus,
+    Result,
+};
+
+pub fn break_loop(Context { env, args, mut io, .. }: Context) -> Result {
+    if !env.in_loop() {
+        writeln!(io.stderr, "gesh: break: only meaningful in a loop")?;
+        return Ok(ExitStatus::Success(0));
+    }
+
+    Ok(match levels("break", &args, &mut io.stderr) {
+        Some(n) => ExitStatus::Break(n),
+        None => ExitStatus::Success(1),
+    })
+}
+
+pub fn continue_loop(Context { env, args, mut io, .. }: Context) -> Result {
+    if !env.in_loop() {
+        writeln!(io.stderr, "gesh: continue: only meaningful in a loop")?;
+        return Ok(ExitStatus::Success(0));
+    }
+
+    Ok(match levels("continue", &args, &mut io.stderr) {
+        Some(n) => ExitStatus::Continue(n),
+        None => ExitStatus::Success(1),
+    })
+}
+
+/// Parses the number of loops affected by `builtin`, which defaults to 1.
+///
+/// Invalid arguments are reported on `stderr`.
+///
+fn levels(builtin: &str, args: &[String], stderr: &mut File) -> Option<u32> {
+    match args.first() {
+        None => Some(1),
+        Some(arg) => match arg.parse::<u32>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => {
+                let _ = writeln!(stderr, "gesh: {}: {}: loop count out of range", builtin, arg);
+                None
+            },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        pipe,
+        Io,
+        Registry,
+    };
+    use environment::Environment;
+    use jobs::JobTable;
+    use std::io::Read;
+    use super::*;
+
+    #[test]
+    fn test_break_returns_number_of_loops() {
+        let env = &mut Environment::empty();
+        env.enter_loop();
+        let args = vec!["2".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
+
+        let result = break_loop(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Break(2)), result);
+    }
+
+    #[test]
+    fn test_continue_defaults_to_innermost_loop() {
+        let env = &mut Environment::empty();
+        env.enter_loop();
+        let args = vec![];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
+
+        let result = continue_loop(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Continue(1)), result);
+    }
+
+    #[test]
+    fn test_break_fails_with_invalid_count() {
+        let env = &mut Environment::empty();
+        env.enter_loop();
+        let args = vec!["0".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
+
+        let result = break_loop(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+    }
+
+    #[test]
+    fn test_break_and_continue_complain_outside_a_loop() {
+        let env = &mut Environment::empty();
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let (mut reader, writer) = pipe().expect("pipe");
+
+        let io = Io { stderr: writer.try_clone().expect("clone"), ..Io::null().expect("io") };
+        let result = break_loop(Context { env, args: vec![], registry, jobs, io });
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+
+        let io = Io { stderr: writer, ..Io::null().expect("io") };
+        let result = continue_loop(Context { env, args: vec![], registry, jobs, io });
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+        assert_eq!("gesh: break: only meaningful in a loop\ngesh: continue: only meaningful in a loop\n", output);
+    }
+}
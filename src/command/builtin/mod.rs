@@ -1,19 +1,54 @@
 //! Support for builtins commands.
 //!
+mod alias;
+mod arithmetic;
+mod bg;
 mod cd;
 mod dirs;
 mod exec;
 mod exit;
 mod export;
+mod fg;
+mod functions;
+mod hash;
+mod jobs;
+mod loops;
+mod lookup;
 mod popd;
 mod pushd;
+mod shopt;
+mod wait;
 
 pub use self::{
+    alias::{
+        alias,
+        unalias,
+    },
+    arithmetic::let_arithmetic,
+    bg::bg,
     cd::cd,
     dirs::dirs,
     exec::exec,
     exit::exit,
     export::export,
+    fg::fg,
+    functions::{
+        local,
+        return_from_function,
+    },
+    hash::hash,
+    jobs::jobs,
+    loops::{
+        break_loop,
+        continue_loop,
+    },
+    lookup::{
+        command,
+        type_of_command,
+        which,
+    },
     popd::popd,
     pushd::pushd,
+    shopt::shopt,
+    wait::wait,
 };
@@ -1,33 +1,74 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+};
+
 use command::{
     Context,
     ExitStatus,
     Result,
 };
 
-use super::cd;
-
-pub fn popd(Context { env, args, registry }: Context) -> Result {
-    match args.len() {
-        0 => {
-            match env.pop_directory() {
-                Some(dir) => {
-                    match dir.into_os_string().into_string() {
-                        Ok(dir_string) => cd(Context { env, args: vec![dir_string], registry }),
-                        _ => Ok(ExitStatus::Success(3)),
-                    }
-                },
-                None => Ok(ExitStatus::Success(2)),
-            }
+use super::{
+    cd,
+    dirs::{
+        self,
+        Listing,
+    },
+};
+
+pub fn popd(Context { env, args, mut io, .. }: Context) -> Result {
+    let (no_change, operands) = match args.first().map(String::as_str) {
+        Some("-n") => (true, &args[1..]),
+        _ => (false, &args[..]),
+    };
+
+    let mut entries: Vec<PathBuf> = env.directory_stack_entries().cloned().collect();
+    if entries.len() < 2 {
+        writeln!(io.stderr, "gesh: popd: directory stack empty")?;
+        return Ok(ExitStatus::Success(1));
+    }
+
+    let n = match operands {
+        [] => 0,
+        [arg] => match dirs::stack_index(arg) {
+            Some((n, from_end)) if n < entries.len() => if from_end { entries.len() - 1 - n } else { n },
+            Some(_) => {
+                writeln!(io.stderr, "gesh: popd: {}: directory stack index out of range", arg)?;
+                return Ok(ExitStatus::Success(1));
+            },
+            None => {
+                writeln!(io.stderr, "gesh: popd: {}: invalid argument", arg)?;
+                writeln!(io.stderr, "gesh: popd: usage: popd [-n] [+N | -N]")?;
+                return Ok(ExitStatus::Success(2));
+            },
+        },
+        _ => {
+            writeln!(io.stderr, "gesh: popd: too many arguments")?;
+            return Ok(ExitStatus::Success(1));
         },
-        _ => Ok(ExitStatus::Success(1)),
+    };
+
+    // With `-n`, the working directory stays where it is, so the entry after it is removed instead
+    entries.remove(if no_change && n == 0 { 1 } else { n });
+    if let Err(err) = dirs::replace_stack(env, entries.clone()) {
+        writeln!(io.stderr, "gesh: popd: {}: {}", entries[0].display(), cd::describe(&err))?;
+        return Ok(ExitStatus::Success(1));
     }
-}
 
+    dirs::list(env, Listing::default(), &mut io.stdout)?;
+    Ok(ExitStatus::Success(0))
+}
 #[cfg(test)]
 mod tests {
-    use command::Registry;
+    use command::{
+        pipe,
+        Io,
+        Registry,
+    };
     use environment::Environment;
-    use std::path::PathBuf;
+    use jobs::JobTable;
+    use std::io::Read;
     use super::*;
 
     #[test]
@@ -36,9 +77,11 @@ mod tests {
         env.push_directory(PathBuf::from("src"));
 
         let args = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
-        let registry = &Registry::for_env(&env);
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
 
-        let result = popd(Context { env, args, registry });
+        let result = popd(Context { env, args, registry, jobs, io });
 
         assert_eq!(Ok(ExitStatus::Success(1)), result);
     }
@@ -47,11 +90,18 @@ mod tests {
     fn test_popd_returns_error_if_nothing_on_stack() {
         let env = &mut Environment::empty();
         let args = vec![];
-        let registry = &Registry::for_env(&env);
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let (mut reader, writer) = pipe().expect("pipe");
+        let io = Io { stderr: writer, ..Io::null().expect("io") };
+
+        let result = popd(Context { env, args, registry, jobs, io });
 
-        let result = popd(Context { env, args, registry });
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
 
-        assert_eq!(Ok(ExitStatus::Success(2)), result);
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+        assert_eq!("gesh: popd: directory stack empty\n", output);
     }
 
     #[test]
@@ -60,11 +110,38 @@ mod tests {
         env.push_directory(PathBuf::from("src"));
 
         let args = vec![];
-        let registry = &Registry::for_env(&env);
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
 
-        let result = popd(Context { env, args, registry });
+        let result = popd(Context { env, args, registry, jobs, io });
 
         assert_eq!(Ok(ExitStatus::Success(0)), result);
         assert_eq!(PathBuf::from("src").canonicalize().unwrap(), *env.working_directory());
     }
+
+    #[test]
+    fn test_popd_removes_given_entry() {
+        let env = &mut Environment::empty();
+        env.set_working_directory(PathBuf::from("/"));
+        env.set_directory_stack(vec![PathBuf::from("/usr"), PathBuf::from("/tmp"), PathBuf::from("/etc")]);
+
+        let args = vec!["-1".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
+        let result = popd(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!(PathBuf::from("/"), *env.working_directory());
+        assert_eq!(&vec![PathBuf::from("/etc"), PathBuf::from("/usr")], env.directory_stack());
+
+        let args = vec!["-n".to_owned()];
+        let io = Io::null().expect("io");
+        let result = popd(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!(PathBuf::from("/"), *env.working_directory());
+        assert_eq!(&vec![PathBuf::from("/etc")], env.directory_stack());
+    }
 }
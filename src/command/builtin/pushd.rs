@@ -1,4 +1,5 @@
 use std::{
+    io::Write,
     path::PathBuf,
 };
 
@@ -8,47 +9,146 @@ use command::{
     Result,
 };
 
-pub fn pushd(Context { env, args, .. }: Context) -> Result {
-    match args.len() {
-        0 => {
-            // TODO swap top two paths
-            Ok(ExitStatus::Success(1))
+use super::{
+    cd,
+    dirs::{
+        self,
+        Listing,
+    },
+};
+
+pub fn pushd(Context { env, args, mut io, .. }: Context) -> Result {
+    let (no_change, operands) = match args.first().map(String::as_str) {
+        Some("-n") => (true, &args[1..]),
+        _ => (false, &args[..]),
+    };
+
+    // With `-n`, the working directory stays where it is, so only the entries after it change
+    let start = if no_change { 1 } else { 0 };
+    let mut entries: Vec<PathBuf> = env.directory_stack_entries().cloned().collect();
+    match operands {
+        [] => {
+            if entries.len() < start + 2 {
+                writeln!(io.stderr, "gesh: pushd: no other directory")?;
+                return Ok(ExitStatus::Success(1));
+            }
+            entries.swap(start, start + 1);
+        },
+        [arg] => match dirs::stack_index(arg) {
+            Some((n, from_end)) if n < entries.len() => {
+                let n = if from_end { entries.len() - 1 - n } else { n };
+                if n >= start {
+                    entries[start..].rotate_left(n - start);
+                }
+            },
+            Some(_) => {
+                writeln!(io.stderr, "gesh: pushd: {}: directory stack index out of range", arg)?;
+                return Ok(ExitStatus::Success(1));
+            },
+            None => match cd::find_directory(env, arg, false) {
+                Ok((dir, _)) if !no_change => {
+                    let previous = env.working_directory().clone();
+                    env.push_directory(previous);
+                    env.set_working_directory(dir);
+                    dirs::list(env, Listing::default(), &mut io.stdout)?;
+                    return Ok(ExitStatus::Success(0));
+                },
+                Ok((dir, _)) => entries.insert(start, dir),
+                Err(err) => {
+                    writeln!(io.stderr, "gesh: pushd: {}: {}", arg, cd::describe(&err))?;
+                    return Ok(ExitStatus::Success(1));
+                },
+            },
         },
-        1 => {
-            // TODO check for existence of path before pushing
-            env.push_directory(PathBuf::from(&args[0]));
-            Ok(ExitStatus::Success(0))
+        _ => {
+            writeln!(io.stderr, "gesh: pushd: too many arguments")?;
+            return Ok(ExitStatus::Success(1));
         },
-        _ => Ok(ExitStatus::Success(1)),
     }
-}
 
+    if let Err(err) = dirs::replace_stack(env, entries.clone()) {
+        writeln!(io.stderr, "gesh: pushd: {}: {}", entries[0].display(), cd::describe(&err))?;
+        return Ok(ExitStatus::Success(1));
+    }
+
+    dirs::list(env, Listing::default(), &mut io.stdout)?;
+    Ok(ExitStatus::Success(0))
+}
 #[cfg(test)]
 mod tests {
-    use command::Registry;
+    use command::{
+        Io,
+        Registry,
+    };
     use environment::Environment;
+    use jobs::JobTable;
     use super::*;
 
+    /// Runs `pushd` with `args`, discarding what it prints.
+    ///
+    fn run(env: &mut Environment, args: &[&str]) -> Result {
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
+
+        pushd(Context { env, args, registry, jobs, io })
+    }
+
     #[test]
-    fn test_pushd_adds_given_directory_to_stack() {
+    fn test_pushd_changes_to_given_directory() {
         let env = &mut Environment::empty();
-        let args = vec!["./src".to_owned()];
-        let registry = &Registry::for_env(&env);
+        let original_working_directory = env.working_directory().clone();
 
-        let result = pushd(Context { env, args, registry });
+        let result = run(env, &["./src"]);
 
         assert_eq!(Ok(ExitStatus::Success(0)), result);
-        assert_eq!(Some(&PathBuf::from("./src")), env.directory_stack().last());
+        assert_eq!(original_working_directory.join("src"), *env.working_directory());
+        assert_eq!(&vec![original_working_directory], env.directory_stack());
     }
 
     #[test]
-    fn test_pushd_returns_error_with_too_many_arguments() {
+    fn test_pushd_adds_given_directory_to_stack_without_changing_directory() {
         let env = &mut Environment::empty();
-        let args = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
-        let registry = &Registry::for_env(&env);
+        let original_working_directory = env.working_directory().clone();
+
+        let result = run(env, &["-n", "src"]);
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!(original_working_directory, *env.working_directory());
+        assert_eq!(&vec![original_working_directory.join("src")], env.directory_stack());
+    }
 
-        let result = pushd(Context { env, args, registry });
+    #[test]
+    fn test_pushd_swaps_and_rotates_stack() {
+        let env = &mut Environment::empty();
+        env.set_working_directory(PathBuf::from("/"));
+        env.set_directory_stack(vec![PathBuf::from("/usr"), PathBuf::from("/tmp")]);
+
+        assert_eq!(Ok(ExitStatus::Success(0)), run(env, &[]));
+        assert_eq!(PathBuf::from("/usr"), *env.working_directory());
+        assert_eq!(&vec![PathBuf::from("/tmp"), PathBuf::from("/")], env.directory_stack());
+
+        assert_eq!(Ok(ExitStatus::Success(0)), run(env, &["+2"]));
+        assert_eq!(PathBuf::from("/tmp"), *env.working_directory());
+        assert_eq!(&vec![PathBuf::from("/"), PathBuf::from("/usr")], env.directory_stack());
+
+        assert_eq!(Ok(ExitStatus::Success(1)), run(env, &["-3"]));
+    }
+
+    #[test]
+    fn test_pushd_returns_error_when_directory_doesnt_exist() {
+        let env = &mut Environment::empty();
+
+        assert_eq!(Ok(ExitStatus::Success(1)), run(env, &["not/a/directory/that/exists"]));
+        assert_eq!(Ok(ExitStatus::Success(1)), run(env, &[]));
+        assert!(env.directory_stack().is_empty());
+    }
+
+    #[test]
+    fn test_pushd_returns_error_with_too_many_arguments() {
+        let env = &mut Environment::empty();
 
-        assert_eq!(Ok(ExitStatus::Success(1)), result);
+        assert_eq!(Ok(ExitStatus::Success(1)), run(env, &["a", "b", "c"]));
     }
 }
@@ -0,0 +1,126 @@
+use std::io::Write;
+
+use command::{
+    Context,
+    ExitStatus,
+    Result,
+};
+
+use environment::Options;
+
+pub fn shopt(Context { env, args, mut io, .. }: Context) -> Result {
+    let (flag, names) = match args.first().map(String::as_str) {
+        Some(flag @ "-s") | Some(flag @ "-u") | Some(flag @ "-q") => (Some(flag), &args[1..]),
+        Some(flag) if flag.starts_with('-') => {
+            writeln!(io.stderr, "gesh: shopt: {}: invalid option", flag)?;
+            writeln!(io.stderr, "gesh: shopt: usage: shopt [-squ] [optname ...]")?;
+            return Ok(ExitStatus::Success(2));
+        },
+        _ => (None, &args[..]),
+    };
+
+    let explicit = !names.is_empty();
+    let names = if !explicit {
+        Options::NAMES.iter()
+            .filter(|name| match flag {
+                Some("-s") => env.options().get(name) == Some(true),
+                Some("-u") => env.options().get(name) == Some(false),
+                _ => true,
+            })
+            .map(|name| name.to_string())
+            .collect()
+    } else {
+        names.to_vec()
+    };
+
+    let mut code = 0;
+    for name in names {
+        let value = match env.options().get(&name) {
+            Some(value) => value,
+            None => {
+                writeln!(io.stderr, "gesh: shopt: {}: invalid shell option name", name)?;
+                code = 1;
+                continue;
+            },
+        };
+
+        match flag {
+            Some("-s") if explicit => {
+                env.options_mut().set(&name, true);
+            },
+            Some("-u") if explicit => {
+                env.options_mut().set(&name, false);
+            },
+            Some("-q") => {
+                if !value {
+                    code = 1;
+                }
+            },
+            _ => {
+                writeln!(io.stdout, "{:<15}\t{}", name, if value { "on" } else { "off" })?;
+                if explicit && flag.is_none() && !value {
+                    code = 1;
+                }
+            },
+        }
+    }
+
+    Ok(ExitStatus::Success(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        Io,
+        Registry,
+    };
+    use environment::Environment;
+    use jobs::JobTable;
+    use super::*;
+
+    #[test]
+    fn test_shopt_sets_and_unsets_options() {
+        let env = &mut Environment::empty();
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+
+        let args = vec!["-s".to_owned(), "nullglob".to_owned(), "dotglob".to_owned()];
+        let result = shopt(Context { env, args, registry, jobs, io: Io::inherit().expect("io") });
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert!(env.options().nullglob && env.options().dotglob);
+
+        let args = vec!["-u".to_owned(), "dotglob".to_owned()];
+        let result = shopt(Context { env, args, registry, jobs, io: Io::inherit().expect("io") });
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert!(env.options().nullglob && !env.options().dotglob);
+    }
+
+    #[test]
+    fn test_shopt_queries_options() {
+        let env = &mut Environment::empty();
+        env.options_mut().extglob = true;
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+
+        let args = vec!["-q".to_owned(), "extglob".to_owned()];
+        let result = shopt(Context { env, args, registry, jobs, io: Io::inherit().expect("io") });
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+
+        let args = vec!["-q".to_owned(), "extglob".to_owned(), "failglob".to_owned()];
+        let result = shopt(Context { env, args, registry, jobs, io: Io::inherit().expect("io") });
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+    }
+
+    #[test]
+    fn test_shopt_fails_for_unknown_options() {
+        let env = &mut Environment::empty();
+        let args = vec!["-s".to_owned(), "nope".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
+
+        let result = shopt(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+    }
+}
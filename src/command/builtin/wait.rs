@@ -0,0 +1,106 @@
+use libc;
+
+use std::io::Write;
+
+use command::{
+    Context,
+    ExitStatus,
+    Result,
+};
+
+use jobs::State;
+
+pub fn wait(Context { args, jobs, mut io, .. }: Context) -> Result {
+    if args.is_empty() {
+        let ids = jobs.iter().map(|job| job.id).collect::<Vec<_>>();
+        for id in ids {
+            jobs.wait(id)?;
+        }
+        return Ok(ExitStatus::Success(0));
+    }
+
+    let mut status = 0;
+    for arg in &args {
+        // Job specs start with a '%', otherwise the argument is a pid
+        let id = if arg.starts_with('%') {
+            jobs.find(arg)
+        } else {
+            arg.parse().ok().and_then(|pid| jobs.iter().find(|job| job.contains(pid)).map(|job| job.id))
+        };
+
+        status = match id {
+            Some(id) => match jobs.wait(id)? {
+                Some(State::Done(code)) => code,
+                Some(State::Stopped) => 128 + libc::SIGTSTP as u32,
+                _ => 127,
+            },
+            None => {
+                writeln!(io.stderr, "gesh: wait: {}: no such job", arg)?;
+                127
+            },
+        };
+    }
+
+    Ok(ExitStatus::Success(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        self,
+        Group,
+        Io,
+        Registry,
+    };
+    use environment::Environment;
+    use jobs::JobTable;
+    use super::*;
+
+    #[test]
+    fn test_wait_waits_for_all_jobs_with_no_arguments() {
+        let env = &mut Environment::empty();
+        let args = vec![];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        for code in 1..4 {
+            let pid = command::fork(Group::New(None), || Ok(ExitStatus::Success(code))).expect("fork");
+            jobs.add(vec![pid], format!("exit {}", code));
+        }
+        let io = Io::inherit().expect("io");
+
+        let result = wait(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert!(jobs.is_empty());
+    }
+
+    #[test]
+    fn test_wait_returns_status_of_last_given_job() {
+        let env = &mut Environment::empty();
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let first = command::fork(Group::New(None), || Ok(ExitStatus::Success(1))).expect("fork");
+        jobs.add(vec![first], "exit 1".to_owned());
+        let second = command::fork(Group::New(None), || Ok(ExitStatus::Success(2))).expect("fork");
+        jobs.add(vec![second], "exit 2".to_owned());
+        let args = vec!["%1".to_owned(), second.to_string()];
+        let io = Io::inherit().expect("io");
+
+        let result = wait(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(2)), result);
+    }
+
+    #[test]
+    fn test_wait_returns_error_for_unknown_job() {
+        let env = &mut Environment::empty();
+        let args = vec!["%1".to_owned()];
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
+
+        let result = wait(Context { env, args, registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(127)), result);
+    }
+}
@@ -3,6 +3,27 @@
 //!
 use command::Registry;
 use environment::Environment;
+use jobs::JobTable;
+
+use libc;
+
+use std::{
+    fs::File,
+    io,
+    mem::ManuallyDrop,
+    os::unix::io::{
+        AsRawFd,
+        FromRawFd,
+    },
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+/// The number of times the shell's own streams have been replaced.
+///
+static SHELL_STREAMS_VERSION: AtomicUsize = AtomicUsize::new(0);
 
 /// A context for running commands.
 ///
@@ -10,4 +31,111 @@ pub struct Context<'c> {
     pub env: &'c mut Environment,
     pub args: Vec<String>,
     pub registry: &'c Registry,
+    pub jobs: &'c mut JobTable,
+    pub io: Io,
+}
+
+/// The standard streams for a command.
+///
+pub struct Io {
+    pub stdin: File,
+    pub stdout: File,
+    pub stderr: File,
+}
+
+impl Io {
+    /// Creates streams that duplicate the shell's own stdin, stdout, and stderr.
+    ///
+    pub fn inherit() -> io::Result<Io> {
+        Ok(Io {
+            stdin: duplicate(0)?,
+            stdout: duplicate(1)?,
+            stderr: duplicate(2)?,
+        })
+    }
+
+    /// Creates streams that all refer to `/dev/null`, for running commands whose input and
+    /// output don't matter.
+    ///
+    #[cfg(test)]
+    pub fn null() -> io::Result<Io> {
+        let null = File::options().read(true).write(true).open("/dev/null")?;
+        Ok(Io {
+            stdin: null.try_clone()?,
+            stdout: null.try_clone()?,
+            stderr: null,
+        })
+    }
+
+    /// Creates a new set of handles that refer to the same streams as this one.
+    ///
+    pub fn try_clone(&self) -> io::Result<Io> {
+        Ok(Io {
+            stdin: self.stdin.try_clone()?,
+            stdout: self.stdout.try_clone()?,
+            stderr: self.stderr.try_clone()?,
+        })
+    }
+
+    /// Returns the stream for the given file descriptor.
+    ///
+    /// Only the standard streams (0, 1, and 2) are supported.
+    ///
+    pub fn stream(&self, fd: u32) -> io::Result<&File> {
+        match fd {
+            0 => Ok(&self.stdin),
+            1 => Ok(&self.stdout),
+            2 => Ok(&self.stderr),
+            _ => Err(bad_file_descriptor()),
+        }
+    }
+
+    /// Replaces the stream for the given file descriptor.
+    ///
+    /// Only the standard streams (0, 1, and 2) are supported.
+    ///
+    pub fn set_stream(&mut self, fd: u32, file: File) -> io::Result<()> {
+        match fd {
+            0 => self.stdin = file,
+            1 => self.stdout = file,
+            2 => self.stderr = file,
+            _ => return Err(bad_file_descriptor()),
+        };
+        Ok(())
+    }
+
+    /// Makes these streams the shell's own stdin, stdout, and stderr.
+    ///
+    /// Since commands inherit the shell's streams by default, this affects every subsequent
+    /// command.
+    ///
+    pub fn replace_shell_streams(&self) -> io::Result<()> {
+        SHELL_STREAMS_VERSION.fetch_add(1, Ordering::Relaxed);
+        for (fd, file) in [&self.stdin, &self.stdout, &self.stderr].iter().enumerate() {
+            if unsafe { libc::dup2(file.as_raw_fd(), fd as i32) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a number that changes every time the shell's own streams are replaced, so that
+    /// streams duplicated from them beforehand can be recognized as out of date.
+    ///
+    pub fn shell_streams_version() -> usize {
+        SHELL_STREAMS_VERSION.load(Ordering::Relaxed)
+    }
+}
+
+/// Duplicates one of the shell's file descriptors, without taking ownership of the original.
+///
+fn duplicate(fd: i32) -> io::Result<File> {
+    let original = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    original.try_clone()
+}
+
+/// Error for file descriptors that aren't supported in redirections.
+///
+fn bad_file_descriptor() -> io::Error {
+    io::Error::from_raw_os_error(libc::EBADF)
 }
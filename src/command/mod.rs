@@ -10,16 +10,34 @@
 mod builtin;
 mod context;
 mod path;
+mod process;
 mod registry;
 
 pub use self::{
-    builtin::*,
-    context::Context,
+    context::{
+        Context,
+        Io,
+    },
     path::Executable,
+    process::{
+        Group,
+        Pid,
+        ProcessState,
+        continue_group,
+        fork,
+        ignore_job_control_signals,
+        keep_open_on_exec,
+        pipe,
+        reset_job_control_signals,
+        wait_for_change,
+    },
     registry::Registry,
 };
 
-use std::result;
+use std::{
+    io,
+    result,
+};
 
 /// Result type for executing commands.
 ///
@@ -33,6 +51,14 @@ pub enum Error {
     ///
     UnknownCommand,
 
+    /// An I/O error occurred while setting up or running a command.
+    ///
+    Io(io::ErrorKind),
+
+    /// A parameter was unset in `${VAR:?message}`, which stops a shell that isn't interactive.
+    ///
+    UnsetParameter,
+
     /// Generic error for unknown/uncateogrized errors
     ///
     Unknown,
@@ -49,4 +75,38 @@ pub enum ExitStatus {
     /// Successfully ran command, with the given status code.
     ///
     Success(u32),
+
+    /// Instructs the enclosing loops to stop. Holds the number of loops to break out of.
+    ///
+    Break(u32),
+
+    /// Instructs the enclosing loops to move on to their next iteration. Holds the number of
+    /// loops affected, where all but the outermost of them are stopped.
+    ///
+    Continue(u32),
+
+    /// Instructs the enclosing function to stop, with the given status code.
+    ///
+    Return(u32),
+}
+
+impl ExitStatus {
+    /// Returns whether or not this status represents a successful command.
+    ///
+    pub fn is_success(&self) -> bool {
+        *self == ExitStatus::Success(0)
+    }
+
+    /// Returns whether or not this status stops the statements that follow it from running, as
+    /// with `exit` or `break`.
+    ///
+    pub fn interrupts_execution(&self) -> bool {
+        !matches!(self, ExitStatus::Success(_))
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err.kind())
+    }
 }
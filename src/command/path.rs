@@ -1,15 +1,27 @@
 //! Support for executable units from the pat.
 //!
 use super::{
+    process::{
+        keep_open_on_exec,
+        reset_job_control_signals,
+    },
+
     Context,
     Error,
     ExitStatus,
+    Group,
+    Pid,
     Result,
 };
 
 use std::{
     ffi::OsStr,
+    os::unix::process::{
+        CommandExt,
+        ExitStatusExt,
+    },
     process,
+    result,
 };
 
 /// An executable on the path.
@@ -29,19 +41,50 @@ impl Executable {
 
     /// Execute this command within the given context.
     ///
-    pub fn execute(mut self, Context { env, args, .. }: Context) -> Result {
-        self.command
+    pub fn execute(self, context: Context) -> Result {
+        self.spawn(context, Group::Inherit)?
+            .wait()
+            .map(ExitStatus::from)
+            .map_err(|_| Error::Unknown)
+    }
+
+    /// Starts this command within the given context, without waiting for it to finish.
+    ///
+    /// The command is started in the given process group.
+    ///
+    pub fn spawn(mut self, Context { env, args, io, .. }: Context, group: Group) -> result::Result<process::Child, Error> {
+        let files = env.inherited_files().to_vec();
+        unsafe {
+            self.command.pre_exec(move || {
+                group.enter();
+                reset_job_control_signals();
+                for &fd in &files {
+                    keep_open_on_exec(fd)?;
+                }
+                Ok(())
+            });
+        }
+
+        let child = self.command
             .envs(env.exported_vars())
             .current_dir(env.working_directory())
             .args(args)
-            .status()
-            .map(|status| ExitStatus::Success(status.code().unwrap_or(1) as u32))
-            .map_err(|_| Error::Unknown)
+            .stdin(io.stdin)
+            .stdout(io.stdout)
+            .stderr(io.stderr)
+            .spawn()
+            .map_err(|_| Error::Unknown)?;
+
+        group.assign(child.id() as Pid);
+        Ok(child)
     }
 }
 
 impl From<process::ExitStatus> for ExitStatus {
     fn from(status: process::ExitStatus) -> Self {
-        ExitStatus::Success(status.code().unwrap_or(1) as u32)
+        match status.signal() {
+            Some(signal) => ExitStatus::Success(128 + signal as u32),
+            None => ExitStatus::Success(status.code().unwrap_or(1) as u32),
+        }
     }
 }
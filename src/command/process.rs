@@ -0,0 +1,283 @@
+//! Low-level support for processes and the plumbing between them.
+//!
+use libc;
+
+use super::{
+    Error,
+    ExitStatus,
+};
+
+use std::{
+    fs::File,
+    io,
+    os::unix::io::{
+        FromRawFd,
+        RawFd,
+    },
+    result,
+};
+
+/// Identifier for a process.
+///
+pub type Pid = libc::pid_t;
+
+/// The process group that a new process is started in.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Group {
+    /// Stay in the shell's process group.
+    ///
+    Inherit,
+
+    /// Lead a new process group. If a terminal is given, the new group is made the terminal's
+    /// foreground group.
+    ///
+    New(Option<RawFd>),
+
+    /// Join the process group with the given id.
+    ///
+    Join(Pid),
+}
+
+impl Group {
+    /// Moves the calling process into this group, taking over the terminal if needed.
+    ///
+    /// This is called by a new child process before it runs anything else, so that a foreground
+    /// job never tries to read from a terminal that hasn't been handed over yet. Only
+    /// async-signal-safe functions are used, so this is safe to call between fork and exec.
+    ///
+    pub fn enter(self) {
+        match self {
+            Group::Inherit => (),
+            Group::New(terminal) => unsafe {
+                libc::setpgid(0, 0);
+                if let Some(fd) = terminal {
+                    libc::tcsetpgrp(fd, libc::getpid());
+                }
+            },
+            Group::Join(pgid) => unsafe {
+                libc::setpgid(0, pgid);
+            },
+        }
+    }
+
+    /// Moves the child process `pid` into this group, from the parent.
+    ///
+    /// Doing this from both the parent and the child ensures the group is set no matter which
+    /// process gets scheduled first.
+    ///
+    pub fn assign(self, pid: Pid) {
+        match self {
+            Group::Inherit => (),
+            Group::New(_) => unsafe {
+                libc::setpgid(pid, pid);
+            },
+            Group::Join(pgid) => unsafe {
+                libc::setpgid(pid, pgid);
+            },
+        }
+    }
+}
+
+/// Signals sent by the terminal to its foreground process group.
+///
+/// An interactive shell ignores these, so that it isn't interrupted or stopped along with the
+/// jobs it runs. Child processes need to react to them as usual though.
+///
+const JOB_CONTROL_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTSTP,
+    libc::SIGTTIN,
+    libc::SIGTTOU,
+];
+
+/// Ignores the signals used for job control in this process.
+///
+pub fn ignore_job_control_signals() {
+    for &signal in JOB_CONTROL_SIGNALS.iter() {
+        unsafe { libc::signal(signal, libc::SIG_IGN) };
+    }
+}
+
+/// Restores the default handling of the signals used for job control in this process.
+///
+/// Only async-signal-safe functions are used, so this is safe to call between fork and exec.
+///
+pub fn reset_job_control_signals() {
+    for &signal in JOB_CONTROL_SIGNALS.iter() {
+        unsafe { libc::signal(signal, libc::SIG_DFL) };
+    }
+}
+
+/// Creates a pipe, returning the read and write ends, in that order.
+///
+/// Both ends are closed on exec, so they will only be seen by a child process if explicitly
+/// given to it as one of its standard streams.
+///
+pub fn pipe() -> io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    for fd in &fds {
+        if unsafe { libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+/// Keeps `fd` open when this process runs an executable, so that the executable can use it
+/// through `/dev/fd`.
+///
+/// This is meant to be called in a child process just before it runs the executable, so that
+/// no other process started by the shell gets the file as well.
+///
+pub fn keep_open_on_exec(fd: RawFd) -> io::Result<()> {
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, 0) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The state of a child process.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProcessState {
+    /// The process is running.
+    ///
+    Running,
+
+    /// The process has been stopped by a signal, and can be continued.
+    ///
+    Stopped,
+
+    /// The process has terminated with the given exit code.
+    ///
+    Exited(u32),
+}
+
+/// Forks the shell, running `f` in the child process.
+///
+/// The child exits with the status returned by `f`, and never returns from this function. The
+/// parent receives the pid of the child.
+///
+/// The child is moved into the given process group.
+///
+pub fn fork<F>(group: Group, f: F) -> result::Result<Pid, Error>
+    where F: FnOnce() -> super::Result
+{
+    match unsafe { libc::fork() } {
+        -1 => Err(Error::from(io::Error::last_os_error())),
+        0 => {
+            group.enter();
+            reset_job_control_signals();
+
+            let code = match f() {
+                Ok(ExitStatus::Success(code)) | Ok(ExitStatus::ExitWith(code)) | Ok(ExitStatus::Return(code)) => code,
+                Ok(ExitStatus::Break(_)) | Ok(ExitStatus::Continue(_)) => 0,
+                Err(Error::UnknownCommand) => 127,
+                Err(_) => 1,
+            };
+            unsafe { libc::_exit(code as i32) }
+        },
+        pid => {
+            group.assign(pid);
+            Ok(pid)
+        },
+    }
+}
+
+/// Waits for the process with the given pid to change state.
+///
+/// If `block` is false and the process hasn't changed state, returns `None` immediately.
+///
+pub fn wait_for_change(pid: Pid, block: bool) -> io::Result<Option<ProcessState>> {
+    let options = libc::WUNTRACED | libc::WCONTINUED | if block { 0 } else { libc::WNOHANG };
+
+    let mut status = 0;
+    loop {
+        match unsafe { libc::waitpid(pid, &mut status, options) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            },
+            0 => return Ok(None),
+            _ => break,
+        }
+    }
+
+    let state = unsafe {
+        if libc::WIFSTOPPED(status) {
+            ProcessState::Stopped
+        } else if libc::WIFCONTINUED(status) {
+            ProcessState::Running
+        } else {
+            ProcessState::Exited(status_code(status))
+        }
+    };
+    Ok(Some(state))
+}
+
+/// Sends `SIGCONT` to every process in the given process group.
+///
+pub fn continue_group(pgid: Pid) -> io::Result<()> {
+    if unsafe { libc::kill(-pgid, libc::SIGCONT) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Converts a raw status from `waitpid` into an exit code.
+///
+/// Processes terminated by a signal report `128 + signal`, like most other shells.
+///
+fn status_code(status: libc::c_int) -> u32 {
+    unsafe {
+        if libc::WIFSIGNALED(status) {
+            128 + libc::WTERMSIG(status) as u32
+        } else {
+            libc::WEXITSTATUS(status) as u32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{
+        Read,
+        Write,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_pipe_connects_write_end_to_read_end() {
+        let (mut reader, mut writer) = pipe().expect("pipe");
+        writer.write_all(b"hello").expect("write");
+        drop(writer);
+
+        let mut contents = String::new();
+        reader.read_to_string(&mut contents).expect("read");
+        assert_eq!("hello", contents);
+    }
+
+    #[test]
+    fn test_wait_returns_status_of_forked_child() {
+        let pid = fork(Group::Inherit, || Ok(ExitStatus::Success(7))).expect("fork");
+        assert_eq!(Ok(Some(ProcessState::Exited(7))), wait_for_change(pid, true).map_err(|err| err.kind()));
+    }
+
+    #[test]
+    fn test_fork_moves_child_into_new_process_group() {
+        let pid = fork(Group::New(None), || Ok(ExitStatus::Success(0))).expect("fork");
+        let pgid = unsafe { libc::getpgid(pid) };
+        assert_eq!(Ok(Some(ProcessState::Exited(0))), wait_for_change(pid, true).map_err(|err| err.kind()));
+        assert_eq!(pid, pgid);
+    }
+}
@@ -1,25 +1,51 @@
 //! A registry for commands.
 //!
 use std::{
+    cell::{
+        RefCell,
+        RefMut,
+    },
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
     fs,
+    io::Write,
     os::unix::{
         fs::PermissionsExt,
     },
     path::PathBuf,
+    result,
 };
 
 use super::{
     builtin,
+    process,
+
+    ExitStatus,
 
     Context,
     Error,
     Executable,
+    Group,
+    Pid,
     Result
 };
 
 use environment::{
     Environment,
 };
+use eval;
+use geshl::{
+    self,
+    Command,
+    For,
+    Function,
+    If,
+    ParsedLine,
+    Pipeline,
+    While,
+};
 
 /// A registry of commands.
 ///
@@ -27,64 +53,401 @@ use environment::{
 /// the entrypoint for executing a command on the shell.
 ///
 pub struct Registry {
-    executable_paths: Vec<PathBuf>,
-    working_directory: PathBuf,
+    functions: RefCell<HashMap<String, ParsedLine>>,
+    aliases: RefCell<BTreeMap<String, String>>,
+    hash_table: RefCell<HashTable>,
+}
+
+/// Where commands have been found on the path, so that it only has to be searched once for each
+/// of them.
+///
+/// Entries are only valid for the `PATH` they were found with, so they're all forgotten when it
+/// changes.
+///
+#[derive(Default)]
+struct HashTable {
+    paths: Vec<PathBuf>,
+    commands: BTreeMap<String, Hashed>,
+}
+
+/// A command that was found on the path.
+///
+struct Hashed {
+    path: PathBuf,
+
+    /// The number of times the command has been looked up since it was found.
+    ///
+    hits: usize,
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
 }
 
 impl Registry {
     /// Constructs a new registry.
     ///
-    pub fn for_env(env: &Environment) -> Registry {
+    /// Commands are looked up using the environment they run in, so the registry follows any
+    /// changes to `PATH` or the working directory.
+    ///
+    pub fn new() -> Registry {
         Registry {
-            executable_paths: env.paths().clone(),
-            working_directory: env.working_directory().clone(),
+            functions: RefCell::new(HashMap::new()),
+            aliases: RefCell::new(BTreeMap::new()),
+            hash_table: RefCell::new(HashTable::default()),
         }
     }
 
     /// Executes `command` within this environment.
     ///
-    /// If found, returns the exit status of the command.
+    /// If found, returns the exit status of the command. Otherwise, an error is written to the
+    /// command's stderr.
+    ///
+    pub fn execute(&self, command: &str, mut context: Context) -> Result {
+        if let Some(builtin) = self.builtin(command) {
+            return builtin(context);
+        }
+
+        if let Some(body) = self.function(command) {
+            return self.call(body, context);
+        }
+
+        match self.find_executable(&PathBuf::from(command), context.env) {
+            Some(path) => Executable::new(path).execute(context),
+            None => {
+                let _ = writeln!(context.io.stderr, "gesh: {}: command not found", command);
+                Err(Error::UnknownCommand)
+            },
+        }
+    }
+
+    /// Starts `command` within this environment, without waiting for it to finish.
+    ///
+    /// Executables are started directly, while everything else runs in a forked copy of the
+    /// shell. Either way, returns the pid of the new process. See `Executable::spawn` for the
+    /// meaning of `group`.
     ///
-    pub fn execute(&self, command: &str, context: Context) -> Result {
+    pub fn spawn(&self, command: &str, context: Context, group: Group) -> result::Result<Pid, Error> {
+        if !self.is_internal(command) {
+            if let Some(path) = self.find_executable(&PathBuf::from(command), context.env) {
+                return Executable::new(path).spawn(context, group).map(|child| child.id() as Pid);
+            }
+        }
+
+        process::fork(group, || self.execute(command, context))
+    }
+
+    /// Finds the builtin with the given name.
+    ///
+    pub fn builtin(&self, command: &str) -> Option<fn(Context) -> Result> {
         match command {
-            "cd" => builtin::cd(context),
-            "dirs" => builtin::dirs(context),
-            "exec" => builtin::exec(context),
-            "exit" => builtin::exit(context),
-            "export" => builtin::export(context),
-            "popd" => builtin::popd(context),
-            "pushd" => builtin::pushd(context),
-            _ => {
-                match self.find_executable(&PathBuf::from(command)) {
-                    Some(path) => Executable::new(path).execute(context),
-                    None => Err(Error::UnknownCommand),
-                }
+            "alias" => Some(builtin::alias),
+            "bg" => Some(builtin::bg),
+            "break" => Some(builtin::break_loop),
+            "cd" => Some(builtin::cd),
+            "continue" => Some(builtin::continue_loop),
+            "dirs" => Some(builtin::dirs),
+            "command" => Some(builtin::command),
+            "exec" => Some(builtin::exec),
+            "exit" => Some(builtin::exit),
+            "export" => Some(builtin::export),
+            "fg" => Some(builtin::fg),
+            "hash" => Some(builtin::hash),
+            "jobs" => Some(builtin::jobs),
+            "let" => Some(builtin::let_arithmetic),
+            "local" => Some(builtin::local),
+            "popd" => Some(builtin::popd),
+            "pushd" => Some(builtin::pushd),
+            "return" => Some(builtin::return_from_function),
+            "shopt" => Some(builtin::shopt),
+            "type" => Some(builtin::type_of_command),
+            "unalias" => Some(builtin::unalias),
+            "wait" => Some(builtin::wait),
+            "which" => Some(builtin::which),
+            _ => None,
+        }
+    }
+
+    /// Defines a function, replacing any previous definition with the same name.
+    ///
+    pub fn define_function(&self, name: String, body: ParsedLine) {
+        self.functions.borrow_mut().insert(name, body);
+    }
+
+    /// Finds the body of the function with the given name.
+    ///
+    pub fn function(&self, name: &str) -> Option<ParsedLine> {
+        self.functions.borrow().get(name).cloned()
+    }
+
+    /// Returns whether or not `command` runs within the shell itself, as builtins and functions
+    /// do.
+    ///
+    pub fn is_internal(&self, command: &str) -> bool {
+        self.builtin(command).is_some() || self.functions.borrow().contains_key(command)
+    }
+
+    /// Defines an alias, replacing any previous definition with the same name.
+    ///
+    pub fn define_alias(&self, name: String, value: String) {
+        self.aliases.borrow_mut().insert(name, value);
+    }
+
+    /// Finds the value of the alias with the given name.
+    ///
+    pub fn alias(&self, name: &str) -> Option<String> {
+        self.aliases.borrow().get(name).cloned()
+    }
+
+    /// Returns every alias and its value, sorted by name.
+    ///
+    pub fn aliases(&self) -> Vec<(String, String)> {
+        self.aliases.borrow().iter().map(|(name, value)| (name.clone(), value.clone())).collect()
+    }
+
+    /// Removes the alias with the given name, returning whether or not it existed.
+    ///
+    pub fn remove_alias(&self, name: &str) -> bool {
+        self.aliases.borrow_mut().remove(name).is_some()
+    }
+
+    /// Removes every alias.
+    ///
+    pub fn remove_all_aliases(&self) {
+        self.aliases.borrow_mut().clear();
+    }
+
+    /// Expands the aliases used by the commands in `line`.
+    ///
+    /// The first word of each command is replaced by the parsed value of the alias with that
+    /// name, if there is one. An alias is never expanded within its own
+    /// expansion, so aliases like `ls='ls -G'` or ones that refer to each other don't expand
+    /// forever.
+    ///
+    pub fn expand_aliases(&self, line: ParsedLine) -> geshl::Result<ParsedLine> {
+        self.expand(line, &[])
+    }
+
+    /// Expands the aliases used in `line`, except for those in `expanding`.
+    ///
+    fn expand(&self, line: ParsedLine, expanding: &[String]) -> geshl::Result<ParsedLine> {
+        let expand_box = |line: Box<ParsedLine>| self.expand(*line, expanding).map(Box::new);
+        Ok(match line {
+            ParsedLine::Command(command) => self.expand_pipeline(vec![command], expanding)?,
+            ParsedLine::Pipeline(Pipeline { commands }) => self.expand_pipeline(commands, expanding)?,
+            ParsedLine::And(lhs, rhs) => ParsedLine::And(expand_box(lhs)?, expand_box(rhs)?),
+            ParsedLine::Or(lhs, rhs) => ParsedLine::Or(expand_box(lhs)?, expand_box(rhs)?),
+            ParsedLine::List(lines) => {
+                let lines = lines.into_iter().map(|line| self.expand(line, expanding));
+                ParsedLine::List(lines.collect::<geshl::Result<_>>()?)
+            },
+            ParsedLine::Background(line) => ParsedLine::Background(expand_box(line)?),
+            ParsedLine::If(If { branches, otherwise }) => {
+                let branches = branches.into_iter().map(|(condition, body)| {
+                    Ok((self.expand(condition, expanding)?, self.expand(body, expanding)?))
+                });
+                ParsedLine::If(If {
+                    branches: branches.collect::<geshl::Result<_>>()?,
+                    otherwise: otherwise.map(expand_box).transpose()?,
+                })
+            },
+            ParsedLine::While(While { condition, body }) => {
+                ParsedLine::While(While { condition: expand_box(condition)?, body: expand_box(body)? })
+            },
+            ParsedLine::For(For { variable, words, body }) => {
+                ParsedLine::For(For { variable, words, body: expand_box(body)? })
+            },
+            ParsedLine::Function(Function { name, body }) => {
+                ParsedLine::Function(Function { name, body: expand_box(body)? })
+            },
+            line => line,
+        })
+    }
+
+    /// Expands the aliases at the start of the commands in a pipeline, except for those in
+    /// `expanding`.
+    ///
+    /// Within a longer pipeline, each alias has to expand to a command or a pipeline, which is
+    /// spliced into its place.
+    ///
+    fn expand_pipeline(&self, commands: Vec<Command>, expanding: &[String]) -> geshl::Result<ParsedLine> {
+        if commands.len() == 1 {
+            return self.expand_command(commands.into_iter().next().unwrap(), expanding);
+        }
+
+        let mut expanded = Vec::with_capacity(commands.len());
+        for command in commands {
+            match self.expand_command(command, expanding)? {
+                ParsedLine::Command(command) => expanded.push(command),
+                ParsedLine::Pipeline(Pipeline { commands }) => expanded.extend(commands),
+                _ => return Err(geshl::Error::Invalid),
             }
         }
+        Ok(ParsedLine::Pipeline(Pipeline { commands: expanded }))
+    }
+
+    /// Expands the alias at the start of `command`, unless it's one of those in `expanding`.
+    ///
+    /// The value of the alias is parsed, and the rest of the command is spliced into it as it
+    /// is: its variable assignments go to the first command of the value, and its other words
+    /// and redirections to the last one. The result is then expanded again, since an alias can
+    /// use other aliases.
+    ///
+    fn expand_command(&self, command: Command, expanding: &[String]) -> geshl::Result<ParsedLine> {
+        let name = command.args.first().and_then(|word| {
+            match word.iter().collect::<Vec<_>>().as_slice() {
+                [geshl::Piece::Fixed(name)] if !expanding.contains(name) => Some(name.clone()),
+                _ => None,
+            }
+        });
+        let (name, value) = match name.and_then(|name| self.alias(&name).map(|value| (name, value))) {
+            Some(alias) => alias,
+            None => return Ok(ParsedLine::Command(command)),
+        };
+
+        let Command { vars, mut args, redirects } = command;
+        args.remove(0);
+
+        let mut lines = geshl::Parser::new().parse(&value)?;
+        let mut line = match lines.len() {
+            0 => ParsedLine::Empty,
+            1 => lines.remove(0),
+            _ => ParsedLine::List(lines),
+        };
+        if line == ParsedLine::Empty {
+            if !vars.is_empty() || !args.is_empty() || !redirects.is_empty() {
+                line = ParsedLine::Command(Command { vars, args, redirects });
+            }
+        } else {
+            if !vars.is_empty() {
+                let first = first_command(&mut line).ok_or(geshl::Error::Invalid)?;
+                first.vars.splice(0..0, vars);
+            }
+            if !args.is_empty() || !redirects.is_empty() {
+                let last = last_command(&mut line).ok_or(geshl::Error::Invalid)?;
+                last.args.extend(args);
+                last.redirects.extend(redirects);
+            }
+        }
+
+        let mut expanded = expanding.to_vec();
+        expanded.push(name);
+        self.expand(line, &expanded)
+    }
+
+    /// Runs a function's `body`, with the context's arguments as its positional parameters.
+    ///
+    /// The function gets its own scope for local variables, and stops early on `return`.
+    ///
+    fn call(&self, body: ParsedLine, context: Context) -> Result {
+        let Context { env, args, jobs, io, .. } = context;
+
+        let arguments = env.replace_arguments(args);
+        env.push_scope();
+        let result = eval::evaluate(body, env, self, jobs, io);
+        env.pop_scope();
+        env.replace_arguments(arguments);
+
+        match result? {
+            ExitStatus::Return(code) => Ok(ExitStatus::Success(code)),
+            ExitStatus::Break(_) | ExitStatus::Continue(_) => Ok(ExitStatus::Success(0)),
+            status => Ok(status),
+        }
     }
 
     /// Finds an executable on the path.
     ///
-    pub fn find_executable(&self, command: &PathBuf) -> Option<PathBuf> {
+    /// Commands found by searching `PATH` are hashed, so it isn't searched again for them unless
+    /// `PATH` changes, or the executable that was found goes away.
+    ///
+    pub fn find_executable(&self, command: &PathBuf, env: &Environment) -> Option<PathBuf> {
         if command.is_absolute() {
             self.executable(command.clone())
         } else if command.parent() != Some(&PathBuf::from("")) {
             // A relative path, like foo/bar or ./spam
             // TODO make the condition for this branch nicer
-            let command_in_working_directory = self.working_directory.join(command);
-            self.executable(command_in_working_directory)
+            self.executable(env.resolve_path(command))
         } else {
-            let mut executables_in_path = self.executable_paths.iter().map(|path| self.executable(path.join(command)));
-            match executables_in_path.find(|path| path.is_some()) {
-                Some(executable) => executable,
+            let name = String::from(command.to_string_lossy());
+            let mut hash_table = self.hash_table(env);
+            if let Some(hashed) = hash_table.commands.get_mut(&name) {
+                if let Some(executable) = self.executable(hashed.path.clone()) {
+                    hashed.hits += 1;
+                    return Some(executable);
+                }
+            }
+
+            let found = self.search_path(&name, env).next();
+            match found {
+                Some(executable) => {
+                    hash_table.commands.insert(name, Hashed { path: executable.clone(), hits: 1 });
+                    Some(executable)
+                },
                 None => {
-                    let command_in_working_directory = self.working_directory.join(command);
-                    self.executable(command_in_working_directory)
+                    hash_table.commands.remove(&name);
+                    self.executable(env.resolve_path(command))
                 }
             }
         }
     }
 
+    /// Returns every executable named `name` in the directories on `PATH`, in order.
+    ///
+    /// Unlike `find_executable`, this always searches `PATH`, and doesn't hash what it finds.
+    ///
+    pub fn search_path<'a>(&'a self, name: &'a str, env: &Environment) -> impl Iterator<Item = PathBuf> + 'a {
+        let directories = env.paths().clone();
+        directories.into_iter().filter_map(move |directory| self.executable(directory.join(name)))
+    }
+
+    /// Returns where the command with the given name was found on the path, if it's been hashed.
+    ///
+    pub fn hashed(&self, name: &str, env: &Environment) -> Option<PathBuf> {
+        self.hash_table(env).commands.get(name).map(|hashed| hashed.path.clone())
+    }
+
+    /// Hashes the command with the given name, so that it's found at `path` from now on.
+    ///
+    pub fn hash_command(&self, name: String, path: PathBuf, env: &Environment) {
+        self.hash_table(env).commands.insert(name, Hashed { path, hits: 0 });
+    }
+
+    /// Returns every hashed command, along with where it was found and the number of times it's
+    /// been looked up since, sorted by name.
+    ///
+    pub fn hashed_commands(&self, env: &Environment) -> Vec<(String, PathBuf, usize)> {
+        self.hash_table(env).commands.iter()
+            .map(|(name, hashed)| (name.clone(), hashed.path.clone(), hashed.hits))
+            .collect()
+    }
+
+    /// Forgets where the command with the given name was found, returning whether or not it was
+    /// hashed.
+    ///
+    pub fn forget_hashed(&self, name: &str, env: &Environment) -> bool {
+        self.hash_table(env).commands.remove(name).is_some()
+    }
+
+    /// Forgets where every command was found.
+    ///
+    pub fn forget_all_hashed(&self) {
+        self.hash_table.borrow_mut().commands.clear();
+    }
+
+    /// Returns the hash table, after forgetting everything in it if `PATH` has changed since it
+    /// was filled.
+    ///
+    fn hash_table(&self, env: &Environment) -> RefMut<'_, HashTable> {
+        let mut hash_table = self.hash_table.borrow_mut();
+        if hash_table.paths != *env.paths() {
+            hash_table.commands.clear();
+            hash_table.paths = env.paths().clone();
+        }
+        hash_table
+    }
+
     /// Returns whether or not the file is executable.
     ///
     fn executable(&self, command: PathBuf) -> Option<PathBuf> {
@@ -99,29 +462,148 @@ impl Registry {
     }
 }
 
+/// Returns the first simple command of `line`, if it starts with one.
+///
+fn first_command(line: &mut ParsedLine) -> Option<&mut Command> {
+    match line {
+        ParsedLine::Command(command) => Some(command),
+        ParsedLine::Pipeline(Pipeline { commands }) => commands.first_mut(),
+        ParsedLine::And(lhs, _) | ParsedLine::Or(lhs, _) => first_command(lhs),
+        ParsedLine::List(lines) => lines.first_mut().and_then(first_command),
+        _ => None,
+    }
+}
+
+/// Returns the last simple command of `line`, if it ends with one.
+///
+fn last_command(line: &mut ParsedLine) -> Option<&mut Command> {
+    match line {
+        ParsedLine::Command(command) => Some(command),
+        ParsedLine::Pipeline(Pipeline { commands }) => commands.last_mut(),
+        ParsedLine::And(_, rhs) | ParsedLine::Or(_, rhs) => last_command(rhs),
+        ParsedLine::List(lines) => lines.last_mut().and_then(last_command),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    #[cfg(target_famlily = "unix")]
+    use command::{
+        ExitStatus,
+        Io,
+    };
+    use jobs::JobTable;
+    use std::collections::HashMap;
+    use super::*;
+
+    /// Parses a single statement.
+    ///
+    fn parse(source: &str) -> ParsedLine {
+        geshl::Parser::new().parse(source).expect("should parse").remove(0)
+    }
+
+    #[test]
+    fn test_expand_aliases_replaces_first_word_of_commands() {
+        let registry = Registry::new();
+        registry.define_alias("ll".to_owned(), "ls -l".to_owned());
+        registry.define_alias("count".to_owned(), "wc -l | tr -d ' '".to_owned());
+
+        let line = registry.expand_aliases(parse("FOO=bar ll /tmp > out | count; echo ll")).expect("should expand");
+
+        assert_eq!("FOO=bar ls -l /tmp > out | wc -l | tr -d ' '; echo ll", line.to_string());
+    }
+
+    #[test]
+    fn test_expand_aliases_expands_nested_aliases_once() {
+        let registry = Registry::new();
+        registry.define_alias("ls".to_owned(), "ls -G".to_owned());
+        registry.define_alias("ll".to_owned(), "ls -l".to_owned());
+        registry.define_alias("a".to_owned(), "b 1".to_owned());
+        registry.define_alias("b".to_owned(), "a 2".to_owned());
+
+        assert_eq!("ls -G -l", registry.expand_aliases(parse("ll")).expect("should expand").to_string());
+        assert_eq!("a 2 1", registry.expand_aliases(parse("a")).expect("should expand").to_string());
+        assert_eq!(
+            "if true; then ls -G; fi",
+            registry.expand_aliases(parse("if true; then ls; fi")).expect("should expand").to_string()
+        );
+    }
+
+    #[test]
+    fn test_expand_aliases_keeps_the_rest_of_the_command_as_it_is() {
+        let registry = Registry::new();
+        registry.define_alias("ll".to_owned(), "ls -l".to_owned());
+        registry.define_alias("both".to_owned(), "true && cat".to_owned());
+        registry.define_alias("nothing".to_owned(), "".to_owned());
+
+        let line = registry.expand_aliases(parse("ll <<EOF\nsome text\nEOF\n")).expect("should expand");
+        assert_eq!(parse("ls -l <<EOF\nsome text\nEOF\n"), line);
+
+        let line = registry.expand_aliases(parse("X=1 both \"$(echo a)\" < in")).expect("should expand");
+        assert_eq!(parse("X=1 true && cat \"$(echo a)\" < in"), line);
+
+        assert_eq!(parse("ls -l"), registry.expand_aliases(parse("nothing ll")).expect("should expand"));
+    }
+
+    #[test]
+    fn test_expand_aliases_fails_for_invalid_alias() {
+        let registry = Registry::new();
+        registry.define_alias("broken".to_owned(), "echo 'abc".to_owned());
+
+        assert_eq!(Err(geshl::Error::Incomplete), registry.expand_aliases(parse("broken")));
+    }
+
+    #[cfg(target_family = "unix")]
     #[test]
     fn test_execute_finds_and_executes_relative_command() {
-        let env = Environment::from_existing_env();
-        let registry = Registry::for_env(&env);
-        assert_eq!(Some(0), registry.execute(&env, "true", vec![]).unwrap().code());
+        let env = &mut Environment::from_existing_env();
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
+
+        let result = registry.execute("true", Context { env, args: vec![], registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
     }
 
-    #[cfg(target_famlily = "unix")]
+    #[cfg(target_family = "unix")]
     #[test]
     fn test_execute_returns_error_when_not_on_path() {
-        let env = Environment::new(HashMap::new());
-        let registry = Registry::for_env(&env);
-        assert_eq!(Err(Error::CommandNotFound), env.execute(&env, "true", vec![]));
+        let env = &mut Environment::new(HashMap::new());
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::null().expect("io");
+
+        let result = registry.execute("true", Context { env, args: vec![], registry, jobs, io });
+
+        assert_eq!(Err(Error::UnknownCommand), result);
     }
 
-    #[cfg(target_famlily = "unix")]
+    #[cfg(target_family = "unix")]
     #[test]
     fn test_execute_executes_absolute_command() {
-        let env = Environment::new(HashMap::new());
-        let registry = Registry::for_env(&env);
-        assert_eq!(Some(1), env.execute(&env, "/usr/bin/false", vec![]).unwrap().code());
+        let env = &mut Environment::new(HashMap::new());
+        let registry = &Registry::new();
+        let jobs = &mut JobTable::new();
+        let io = Io::inherit().expect("io");
+
+        let result = registry.execute("/usr/bin/false", Context { env, args: vec![], registry, jobs, io });
+
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+    }
+
+    #[test]
+    fn test_find_executable_hashes_commands_until_path_changes() {
+        let env = &mut Environment::new(HashMap::new());
+        env.set("PATH".to_owned(), "/nonexistent:/bin".to_owned());
+        let registry = Registry::new();
+
+        assert_eq!(Some(PathBuf::from("/bin/sh")), registry.find_executable(&PathBuf::from("sh"), env));
+        assert_eq!(Some(PathBuf::from("/bin/sh")), registry.find_executable(&PathBuf::from("sh"), env));
+        assert_eq!(vec![("sh".to_owned(), PathBuf::from("/bin/sh"), 2)], registry.hashed_commands(env));
+
+        env.set("PATH".to_owned(), "/usr/bin".to_owned());
+        assert_eq!(None, registry.hashed("sh", env));
+        assert_eq!(Some(PathBuf::from("/usr/bin/sh")), registry.find_executable(&PathBuf::from("sh"), env));
     }
 }
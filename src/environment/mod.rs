@@ -1,10 +1,30 @@
 //! Encapsulates the environment in which commands within a shell executes.
 //!
+mod options;
+
+pub use self::options::Options;
+
+use libc;
+
 use std::{
     borrow::Borrow,
     collections::HashMap,
     env,
-    path::PathBuf,
+    ffi::{
+        CStr,
+        CString,
+    },
+    io,
+    iter,
+    mem,
+    os::unix::io::RawFd,
+    path::{
+        Component,
+        Path,
+        PathBuf,
+    },
+    process,
+    ptr,
 };
 
 /// Supports executing commands within the context of a specific environment.
@@ -17,6 +37,43 @@ pub struct Environment {
 
     vars: HashMap<String, String>,
     exported_vars: HashMap<String, String>,
+
+    /// The name of the shell or script (`$0`), followed by its arguments (`$1`, `$2`, ...).
+    ///
+    positional_parameters: Vec<String>,
+
+    /// Local scopes, innermost last. Each scope holds the values that its local variables had
+    /// before they were declared, so they can be restored when the scope ends.
+    ///
+    scopes: Vec<HashMap<String, Option<String>>>,
+
+    /// The pid of the shell (`$$`), which stays the same in subshells.
+    ///
+    shell_pid: u32,
+
+    /// The exit status of the most recent command (`$?`).
+    ///
+    last_status: u32,
+
+    /// The exit status of the most recent command substitution, until it's taken by the
+    /// statement it ran in.
+    ///
+    substitution_status: Option<u32>,
+
+    /// The pid of the most recent background job (`$!`).
+    ///
+    last_background_pid: Option<u32>,
+
+    /// The number of loops that are running, since `break` and `continue` only work within one.
+    ///
+    loop_depth: usize,
+
+    /// Files, besides the standard streams, that executables started in this environment keep
+    /// open, such as the pipes of process substitutions.
+    ///
+    inherited_files: Vec<RawFd>,
+
+    options: Options,
 }
 
 impl Environment {
@@ -24,12 +81,22 @@ impl Environment {
     ///
     /// Defaults to containing the same paths as the shell's PATH environment variable.
     ///
+    #[cfg(test)]
     pub fn empty() -> Environment {
         Environment {
             paths: Vec::new(),
             vars: HashMap::new(),
             exported_vars: HashMap::new(),
             directory_stack: Vec::new(),
+            positional_parameters: vec!["gesh".to_owned()],
+            scopes: Vec::new(),
+            shell_pid: process::id(),
+            last_status: 0,
+            substitution_status: None,
+            last_background_pid: None,
+            loop_depth: 0,
+            inherited_files: Vec::new(),
+            options: Options::default(),
 
             // TODO something better than '/'?
             working_directory: env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
@@ -41,19 +108,23 @@ impl Environment {
     /// Defaults to containing the same paths as the shell's PATH environment variable.
     ///
     pub fn new(vars: HashMap<String, String>) -> Environment {
-        let paths = match vars.get("PATH") {
-            Some(paths) => env::split_paths(&paths).collect(),
-            None => vec![]
-        };
-
+        let paths = paths_in(&vars);
+        let working_directory = initial_working_directory(&vars);
         Environment {
             paths,
             vars: vars.clone(),
             exported_vars: vars,
             directory_stack: Vec::new(),
-
-            // TODO something better than '/'?
-            working_directory: env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
+            positional_parameters: vec!["gesh".to_owned()],
+            scopes: Vec::new(),
+            shell_pid: process::id(),
+            last_status: 0,
+            substitution_status: None,
+            last_background_pid: None,
+            loop_depth: 0,
+            inherited_files: Vec::new(),
+            options: Options::default(),
+            working_directory,
         }
     }
 
@@ -73,9 +144,15 @@ impl Environment {
 
     /// Sets the current working directory.
     ///
+    /// `PWD` is set to the new working directory, and `OLDPWD` to the previous one. Whichever
+    /// of them are exported are passed on to commands with their new values.
+    ///
     pub fn set_working_directory(&mut self, path: PathBuf) {
         self.vars.insert("PWD".to_owned(), String::from(path.to_string_lossy()));
-        self.working_directory = path;
+        let previous = mem::replace(&mut self.working_directory, path);
+        self.vars.insert("OLDPWD".to_owned(), String::from(previous.to_string_lossy()));
+        self.refresh_export("PWD");
+        self.refresh_export("OLDPWD");
     }
 
     /// Returns the current working directory.
@@ -84,6 +161,25 @@ impl Environment {
         &self.working_directory
     }
 
+    /// Resolves `path` against the working directory.
+    ///
+    /// The shell never changes its process's working directory, so anything that works with a
+    /// path given to the shell should resolve it with this first. Absolute paths are returned as
+    /// they are.
+    ///
+    pub fn resolve_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.working_directory.join(path)
+    }
+
+    /// Resolves `path` against the working directory, then resolves any symbolic links and `.`
+    /// or `..` components in it.
+    ///
+    /// Fails if the path doesn't exist.
+    ///
+    pub fn canonicalize_path<P: AsRef<Path>>(&self, path: P) -> io::Result<PathBuf> {
+        self.resolve_path(path).canonicalize()
+    }
+
     /// Push directory onto the directory stack.
     ///
     pub fn push_directory(&mut self, path: PathBuf) {
@@ -92,35 +188,243 @@ impl Environment {
 
     /// Pop a directory from the directory stack.
     ///
+    #[cfg(test)]
     pub fn pop_directory(&mut self) -> Option<PathBuf> {
         self.directory_stack.pop()
     }
 
     /// Pop a directory from the directory stack.
     ///
+    #[cfg(test)]
     pub fn directory_stack(&self) -> &Vec<PathBuf> {
         &self.directory_stack
     }
 
+    /// Replaces the directory stack.
+    ///
+    /// `entries` are in the order `dirs` lists them, without the working directory, so the most
+    /// recently pushed entry comes first.
+    ///
+    pub fn set_directory_stack(&mut self, entries: Vec<PathBuf>) {
+        self.directory_stack = entries;
+        self.directory_stack.reverse();
+    }
+
+    /// Returns the entries of the directory stack as `dirs` lists them, where the current working
+    /// directory comes first and is followed by the stack from the most recently pushed entry.
+    ///
+    pub fn directory_stack_entries<'a>(&'a self) -> impl DoubleEndedIterator<Item = &'a PathBuf> + 'a {
+        iter::once(&self.working_directory).chain(self.directory_stack.iter().rev())
+    }
+
+    /// Returns an entry from the directory stack as `dirs` lists it.
+    ///
+    /// Entries are counted from the start of the list, or from its end if `from_end` is set.
+    ///
+    pub fn directory_stack_entry(&self, n: usize, from_end: bool) -> Option<&PathBuf> {
+        let mut entries = self.directory_stack_entries();
+        if from_end {
+            entries.rev().nth(n)
+        } else {
+            entries.nth(n)
+        }
+    }
+
+    /// Returns `path` as a string, replacing the home directory at its start with `~`.
+    ///
+    pub fn abbreviate_home(&self, path: &Path) -> String {
+        if let Some(home) = self.vars.get("HOME").filter(|home| !home.is_empty() && *home != "/") {
+            if let Ok(rest) = path.strip_prefix(home) {
+                return if rest.as_os_str().is_empty() {
+                    "~".to_owned()
+                } else {
+                    format!("~/{}", rest.to_string_lossy())
+                };
+            }
+        }
+        String::from(path.to_string_lossy())
+    }
+
     /// Gets the value of the `PATH` variable as a `Vec`.
     ///
     pub fn paths(&self) -> &Vec<PathBuf> {
         &self.paths
     }
 
+    /// Sets the name of the shell or script (`$0`), and its arguments (`$1`, `$2`, ...).
+    ///
+    pub fn set_positional_parameters(&mut self, name: String, args: Vec<String>) {
+        self.positional_parameters = Some(name).into_iter().chain(args).collect();
+    }
+
+    /// Returns the arguments given to the shell or script, excluding its name.
+    ///
+    pub fn arguments(&self) -> &[String] {
+        &self.positional_parameters[1..]
+    }
+
+    /// Replaces the arguments (`$1`, `$2`, ...), returning the previous ones.
+    ///
+    pub fn replace_arguments(&mut self, args: Vec<String>) -> Vec<String> {
+        let name = self.positional_parameters[0].clone();
+        let previous = self.positional_parameters.split_off(1);
+        self.positional_parameters = Some(name).into_iter().chain(args).collect();
+        previous
+    }
+
+    /// Returns the exit status of the most recent command.
+    ///
+    pub fn last_status(&self) -> u32 {
+        self.last_status
+    }
+
+    /// Records the exit status of the most recent command.
+    ///
+    pub fn set_last_status(&mut self, status: u32) {
+        self.last_status = status;
+    }
+
+    /// Records the exit status of a command substitution, which also becomes `$?`.
+    ///
+    pub fn set_substitution_status(&mut self, status: u32) {
+        self.last_status = status;
+        self.substitution_status = Some(status);
+    }
+
+    /// Returns the exit status of the most recent command substitution since this was last
+    /// called, if any have run.
+    ///
+    pub fn take_substitution_status(&mut self) -> Option<u32> {
+        self.substitution_status.take()
+    }
+
+    /// Records the pid of the most recent background job.
+    ///
+    pub fn set_last_background_pid(&mut self, pid: u32) {
+        self.last_background_pid = Some(pid);
+    }
+
+    /// Records that a loop has started running.
+    ///
+    pub fn enter_loop(&mut self) {
+        self.loop_depth += 1;
+    }
+
+    /// Records that the innermost running loop has finished.
+    ///
+    pub fn leave_loop(&mut self) {
+        self.loop_depth -= 1;
+    }
+
+    /// Returns whether or not a loop is running.
+    ///
+    pub fn in_loop(&self) -> bool {
+        self.loop_depth > 0
+    }
+
+    /// Returns the shell's options.
+    ///
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Returns the shell's options, so that they can be changed.
+    ///
+    pub fn options_mut(&mut self) -> &mut Options {
+        &mut self.options
+    }
+
+    /// Starts a new local scope, such as for a function call.
+    ///
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Ends the innermost local scope, restoring the variables that were declared local to it.
+    ///
+    pub fn pop_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            for (name, value) in scope {
+                match value {
+                    Some(value) => self.vars.insert(name.clone(), value),
+                    None => self.vars.remove(&name),
+                };
+                self.variable_changed(&name);
+            }
+        }
+    }
+
+    /// Returns whether or not there is a local scope.
+    ///
+    pub fn in_local_scope(&self) -> bool {
+        !self.scopes.is_empty()
+    }
+
+    /// Declares a variable local to the innermost scope, giving it `value`, or leaving it unset.
+    ///
+    /// Returns false if there is no local scope.
+    ///
+    pub fn declare_local(&mut self, name: String, value: Option<String>) -> bool {
+        let previous = self.vars.get(&name).cloned();
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.entry(name.clone()).or_insert(previous);
+            },
+            None => return false,
+        }
+
+        match value {
+            Some(value) => self.vars.insert(name.clone(), value),
+            None => self.vars.remove(&name),
+        };
+        self.variable_changed(&name);
+        true
+    }
+
     /// Gets the value of a variable from this environment.
     ///
+    /// Positional parameters are available by number, along with `#` for the number of
+    /// arguments and `@` or `*` for all arguments, separated by spaces. The special parameters
+    /// `?`, `$`, and `!` give the last exit status, the shell's pid, and the pid of the last
+    /// background job.
+    ///
     pub fn get<S: Borrow<String>>(&self, name: &S) -> Option<String> {
-        match self.vars.get(name.borrow()) {
-            Some(s) => Some(s.clone()),
-            None => None,
+        let name = name.borrow();
+        match name.as_str() {
+            "#" => Some(self.arguments().len().to_string()),
+            "@" | "*" => Some(self.arguments().join(" ")),
+            "?" => Some(self.last_status.to_string()),
+            "$" => Some(self.shell_pid.to_string()),
+            "!" => self.last_background_pid.map(|pid| pid.to_string()),
+            "DIRSTACK" => {
+                let entries: Vec<_> = self.directory_stack_entries().map(|dir| self.abbreviate_home(dir)).collect();
+                Some(entries.join(" "))
+            },
+            _ => match name.parse::<usize>() {
+                Ok(index) => self.positional_parameters.get(index).cloned(),
+                Err(_) => self.vars.get(name).cloned(),
+            },
         }
     }
 
     /// Sets a variable in the environment.
     ///
+    /// Setting `PATH` also changes the paths that commands are looked up in.
+    ///
     pub fn set(&mut self, name: String, value: String) -> Option<String> {
-        self.vars.insert(name, value)
+        let previous = self.vars.insert(name.clone(), value);
+        self.variable_changed(&name);
+        previous
+    }
+
+    /// Keeps anything derived from the variable with the given name up to date, after it's been
+    /// changed, including the value commands are given if it's been exported.
+    ///
+    fn variable_changed(&mut self, name: &str) {
+        if name == "PATH" {
+            self.paths = paths_in(&self.vars);
+        }
+        self.refresh_export(name);
     }
 
     /// Exports a varable.
@@ -134,6 +438,14 @@ impl Environment {
         }
     }
 
+    /// Updates the value that commands are given for a variable, if it's been exported.
+    ///
+    fn refresh_export(&mut self, name: &str) {
+        if let (Some(exported), Some(value)) = (self.exported_vars.get_mut(name), self.vars.get(name)) {
+            exported.clone_from(value);
+        }
+    }
+
     /// Gets the exported variables in this environment.
     ///
     /// Exported variables are the ones that get used in subsequent commands.
@@ -141,6 +453,73 @@ impl Environment {
     pub fn exported_vars(&self) -> HashMap<String, String> {
         self.exported_vars.clone()
     }
+
+    /// Returns the files, besides the standard streams, that executables keep open.
+    ///
+    pub fn inherited_files(&self) -> &[RawFd] {
+        &self.inherited_files
+    }
+
+    /// Sets the files, besides the standard streams, that executables keep open, returning the
+    /// previous ones.
+    ///
+    pub fn set_inherited_files(&mut self, files: Vec<RawFd>) -> Vec<RawFd> {
+        mem::replace(&mut self.inherited_files, files)
+    }
+}
+
+/// Returns the directories listed in the `PATH` variable.
+///
+fn paths_in(vars: &HashMap<String, String>) -> Vec<PathBuf> {
+    match vars.get("PATH") {
+        Some(paths) => env::split_paths(paths).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Returns the working directory a new environment starts in.
+///
+/// This is the process's working directory, but `PWD` is used instead if it's the same
+/// directory, so that any symbolic links in it are kept.
+///
+fn initial_working_directory(vars: &HashMap<String, String>) -> PathBuf {
+    // TODO something better than '/'?
+    let physical = env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+    match vars.get("PWD").map(PathBuf::from) {
+        Some(logical) => {
+            let normal = logical.is_absolute()
+                && logical.components().all(|c| matches!(c, Component::RootDir | Component::Normal(_)));
+            if normal && logical.canonicalize().ok() == physical.canonicalize().ok() {
+                logical
+            } else {
+                physical
+            }
+        },
+        None => physical,
+    }
+}
+
+/// Looks up the home directory of `user` in the system's user database.
+///
+pub fn home_directory_of(user: &str) -> Option<PathBuf> {
+    let name = CString::new(user).ok()?;
+    let mut buffer = vec![0 as libc::c_char; 1024];
+    loop {
+        let mut entry: libc::passwd = unsafe { mem::zeroed() };
+        let mut result = ptr::null_mut();
+        let code = unsafe {
+            libc::getpwnam_r(name.as_ptr(), &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut result)
+        };
+
+        match code {
+            libc::ERANGE => buffer.resize(buffer.len() * 2, 0),
+            0 if !result.is_null() => {
+                let directory = unsafe { CStr::from_ptr(entry.pw_dir) };
+                return Some(PathBuf::from(directory.to_string_lossy().into_owned()));
+            },
+            _ => return None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -163,6 +542,75 @@ mod tests {
         assert_eq!(None, env.get(&"TMPDIR".to_owned()));
     }
 
+    #[test]
+    fn test_setting_path_changes_paths() {
+        let mut env = Environment::new(HashMap::new());
+        env.set("PATH".to_owned(), "/bin:/usr/bin".to_owned());
+        assert_eq!(&vec![PathBuf::from("/bin"), PathBuf::from("/usr/bin")], env.paths());
+
+        env.push_scope();
+        env.declare_local("PATH".to_owned(), Some("/opt/bin".to_owned()));
+        assert_eq!(&vec![PathBuf::from("/opt/bin")], env.paths());
+
+        env.pop_scope();
+        assert_eq!(&vec![PathBuf::from("/bin"), PathBuf::from("/usr/bin")], env.paths());
+    }
+
+    #[test]
+    fn test_get_returns_positional_parameters() {
+        let mut env = Environment::new(HashMap::new());
+        env.set_positional_parameters("script.gesh".to_owned(), vec!["a".to_owned(), "b c".to_owned()]);
+
+        assert_eq!(Some("script.gesh".to_owned()), env.get(&"0".to_owned()));
+        assert_eq!(Some("b c".to_owned()), env.get(&"2".to_owned()));
+        assert_eq!(None, env.get(&"3".to_owned()));
+        assert_eq!(Some("2".to_owned()), env.get(&"#".to_owned()));
+        assert_eq!(Some("a b c".to_owned()), env.get(&"@".to_owned()));
+    }
+
+    #[test]
+    fn test_pop_scope_restores_local_variables() {
+        let mut env = Environment::new(HashMap::new());
+        env.set("FOO".to_owned(), "outer".to_owned());
+        assert!(!env.declare_local("FOO".to_owned(), None));
+
+        env.push_scope();
+        assert!(env.declare_local("FOO".to_owned(), Some("inner".to_owned())));
+        assert!(env.declare_local("BAR".to_owned(), Some("bar".to_owned())));
+        env.set("FOO".to_owned(), "changed".to_owned());
+        assert_eq!(Some("changed".to_owned()), env.get(&"FOO".to_owned()));
+
+        env.pop_scope();
+        assert_eq!(Some("outer".to_owned()), env.get(&"FOO".to_owned()));
+        assert_eq!(None, env.get(&"BAR".to_owned()));
+        assert!(!env.in_local_scope());
+    }
+
+    #[test]
+    fn test_get_returns_special_parameters() {
+        let mut env = Environment::new(HashMap::new());
+        env.set_positional_parameters("gesh".to_owned(), vec!["a".to_owned(), "b".to_owned()]);
+        assert_eq!(None, env.get(&"!".to_owned()));
+
+        env.set_last_status(3);
+        env.set_last_background_pid(42);
+
+        assert_eq!(Some("3".to_owned()), env.get(&"?".to_owned()));
+        assert_eq!(Some("42".to_owned()), env.get(&"!".to_owned()));
+        assert_eq!(Some(process::id().to_string()), env.get(&"$".to_owned()));
+        assert_eq!(Some("a b".to_owned()), env.get(&"*".to_owned()));
+    }
+
+    #[test]
+    fn test_replace_arguments_keeps_name() {
+        let mut env = Environment::new(HashMap::new());
+        env.set_positional_parameters("script".to_owned(), vec!["a".to_owned()]);
+
+        assert_eq!(vec!["a".to_owned()], env.replace_arguments(vec!["b".to_owned(), "c".to_owned()]));
+        assert_eq!(Some("script".to_owned()), env.get(&"0".to_owned()));
+        assert_eq!(Some("b c".to_owned()), env.get(&"@".to_owned()));
+    }
+
     #[test]
     fn test_push_and_pop() {
         let mut env = Environment::new(HashMap::new());
@@ -182,6 +630,37 @@ mod tests {
         assert_eq!(None, env.pop_directory());
     }
 
+    #[test]
+    fn test_resolve_path_uses_working_directory() {
+        let mut env = Environment::new(HashMap::new());
+        env.set_working_directory(PathBuf::from("/usr/local"));
+
+        assert_eq!(PathBuf::from("/usr/local/bin"), env.resolve_path("bin"));
+        assert_eq!(PathBuf::from("/etc"), env.resolve_path("/etc"));
+    }
+
+    #[test]
+    fn test_set_working_directory_records_previous_directory() {
+        let mut env = Environment::new(HashMap::new());
+        env.set_working_directory(PathBuf::from("/usr"));
+        env.set_working_directory(PathBuf::from("/usr/local"));
+
+        assert_eq!(Some("/usr/local".to_owned()), env.get(&"PWD".to_owned()));
+        assert_eq!(Some("/usr".to_owned()), env.get(&"OLDPWD".to_owned()));
+    }
+
+    #[test]
+    fn test_get_returns_directory_stack() {
+        let mut env = Environment::new(HashMap::new());
+        env.set("HOME".to_owned(), "/home/me".to_owned());
+        env.set_working_directory(PathBuf::from("/home/me/src"));
+        env.set_directory_stack(vec![PathBuf::from("/usr"), PathBuf::from("/home/me")]);
+
+        assert_eq!(Some("~/src /usr ~".to_owned()), env.get(&"DIRSTACK".to_owned()));
+        assert_eq!(Some(&PathBuf::from("/usr")), env.directory_stack_entry(1, false));
+        assert_eq!(Some(&PathBuf::from("/home/me")), env.directory_stack().first());
+    }
+
     #[test]
     fn test_export_moves_variable_to_exported_variables() {
         let mut env = Environment::new(HashMap::new());
@@ -189,7 +668,16 @@ mod tests {
         env.set("BAR".to_owned(), "barval".to_owned());
         env.export("FOO".to_owned());
 
-        assert_eq!(Some(&"fooval".to_owned()), env.exported_vars().get(&"FOO".to_owned()));
-        assert_eq!(None, env.exported_vars().get(&"BAR".to_owned()));
+        assert_eq!(Some(&"fooval".to_owned()), env.exported_vars().get("FOO"));
+        assert_eq!(None, env.exported_vars().get("BAR"));
+    }
+
+    #[test]
+    fn test_setting_exported_variable_updates_exported_value() {
+        let mut env = Environment::new(vec![("PATH".to_owned(), "/usr/bin".to_owned())].into_iter().collect());
+        env.set("PATH".to_owned(), "/bin".to_owned());
+
+        assert_eq!(Some(&"/bin".to_owned()), env.exported_vars().get("PATH"));
+        assert_eq!(&vec![PathBuf::from("/bin")], env.paths());
     }
 }
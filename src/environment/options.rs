@@ -0,0 +1,84 @@
+//! Options that change how the shell behaves, set with the `shopt` builtin.
+//!
+
+/// The shell's options.
+///
+/// All options are off by default.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    /// Globs match files whose names start with a `.`.
+    ///
+    pub dotglob: bool,
+
+    /// Globs can contain pattern groups, like `!(*.o)` or `@(foo|bar)`.
+    ///
+    pub extglob: bool,
+
+    /// A glob that doesn't match any files is an error.
+    ///
+    pub failglob: bool,
+
+    /// A `**` component in a glob matches files and directories at any depth.
+    ///
+    pub globstar: bool,
+
+    /// Globs match files without regard to case.
+    ///
+    pub nocaseglob: bool,
+
+    /// A glob that doesn't match any files is removed, instead of being left as it is.
+    ///
+    pub nullglob: bool,
+}
+
+impl Options {
+    /// The names of all options, in alphabetical order.
+    ///
+    pub const NAMES: [&'static str; 6] = ["dotglob", "extglob", "failglob", "globstar", "nocaseglob", "nullglob"];
+
+    /// Returns whether or not the option with the given name is on, or `None` if there is no
+    /// such option.
+    ///
+    pub fn get(&self, name: &str) -> Option<bool> {
+        self.option(name).copied()
+    }
+
+    /// Turns the option with the given name on or off.
+    ///
+    /// Returns false if there is no such option.
+    ///
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        match self.option_mut(name) {
+            Some(option) => {
+                *option = value;
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn option(&self, name: &str) -> Option<&bool> {
+        match name {
+            "dotglob" => Some(&self.dotglob),
+            "extglob" => Some(&self.extglob),
+            "failglob" => Some(&self.failglob),
+            "globstar" => Some(&self.globstar),
+            "nocaseglob" => Some(&self.nocaseglob),
+            "nullglob" => Some(&self.nullglob),
+            _ => None,
+        }
+    }
+
+    fn option_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "dotglob" => Some(&mut self.dotglob),
+            "extglob" => Some(&mut self.extglob),
+            "failglob" => Some(&mut self.failglob),
+            "globstar" => Some(&mut self.globstar),
+            "nocaseglob" => Some(&mut self.nocaseglob),
+            "nullglob" => Some(&mut self.nullglob),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,1073 @@
+//! Evaluates parsed lines within an environment.
+//!
+use arithmetic;
+
+use command::{
+    self,
+    Context,
+    Error,
+    ExitStatus,
+    Group,
+    Io,
+    Pid,
+    ProcessState,
+    Registry,
+};
+
+use environment::{
+    Environment,
+};
+
+use jobs::{
+    JobTable,
+    State,
+};
+
+use geshl::{
+    Command,
+    Direction,
+    For,
+    Function,
+    If,
+    ParsedLine,
+    Piece,
+    Pipeline,
+    Redirect,
+    SetVariable,
+    ShellString,
+    While,
+};
+
+use libc;
+
+use strings;
+
+use std::{
+    env,
+    fs::{
+        self,
+        File,
+        OpenOptions,
+    },
+    io::{
+        self,
+        Read,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    os::unix::io::{
+        AsRawFd,
+        RawFd,
+    },
+    process,
+    result,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+/// Evaluates `line`, using `io` as the standard streams for any commands that are run.
+///
+/// The exit status is recorded in the environment, as `$?`.
+///
+pub fn evaluate(line: ParsedLine, env: &mut Environment, registry: &Registry, jobs: &mut JobTable, io: Io) -> command::Result {
+    let result = evaluate_line(line, env, registry, jobs, io);
+    if let Ok(ExitStatus::Success(code)) = result {
+        env.set_last_status(code);
+    }
+    result
+}
+
+/// Evaluates `line` without recording its exit status as `$?`.
+///
+fn evaluate_line(line: ParsedLine, env: &mut Environment, registry: &Registry, jobs: &mut JobTable, mut io: Io) -> command::Result {
+    match line {
+        ParsedLine::Command(command) => execute(command, env, registry, jobs, io),
+
+        ParsedLine::Pipeline(pipeline) => {
+            let description = pipeline.to_string();
+            let mut stderr = io.stderr.try_clone()?;
+            let group = jobs.foreground_group();
+            let pids = start_pipeline(pipeline.commands, env, registry, jobs, io, group)?;
+            run_in_foreground(pids, description, jobs, &mut stderr)
+        },
+
+        ParsedLine::And(lhs, rhs) => {
+            let status = evaluate_part(*lhs, env, registry, jobs, &mut io)?;
+            if status.is_success() {
+                evaluate(*rhs, env, registry, jobs, io)
+            } else {
+                Ok(status)
+            }
+        },
+
+        ParsedLine::Or(lhs, rhs) => {
+            let status = evaluate_part(*lhs, env, registry, jobs, &mut io)?;
+            match status {
+                ExitStatus::Success(code) if code != 0 => evaluate(*rhs, env, registry, jobs, io),
+                _ => Ok(status),
+            }
+        },
+
+        ParsedLine::List(lines) => {
+            let mut status = ExitStatus::Success(0);
+            for line in lines {
+                status = evaluate_part(line, env, registry, jobs, &mut io)?;
+                if status.interrupts_execution() {
+                    break;
+                }
+            }
+            Ok(status)
+        },
+
+        ParsedLine::Background(line) => {
+            let description = line.to_string();
+            let mut stderr = io.stderr.try_clone()?;
+
+            // Commands and pipelines can be started directly, but anything else needs a copy of
+            // the shell to evaluate it.
+            let pids = match *line {
+                ParsedLine::Command(command) => start_pipeline(vec![command], env, registry, jobs, io, Group::New(None))?,
+                ParsedLine::Pipeline(Pipeline { commands }) => start_pipeline(commands, env, registry, jobs, io, Group::New(None))?,
+                line => vec![command::fork(Group::New(None), || evaluate(line, env, registry, jobs, io))?],
+            };
+
+            // Only an interactive shell, which is the one with job control, reports the job
+            let interactive = jobs.has_job_control();
+            let job = jobs.add(pids, description);
+            if interactive {
+                writeln!(stderr, "[{}] {}", job.id, job.last_pid())?;
+            }
+            env.set_last_background_pid(job.last_pid() as u32);
+            Ok(ExitStatus::Success(0))
+        },
+
+        ParsedLine::If(If { branches, otherwise }) => {
+            for (condition, body) in branches {
+                let status = evaluate_part(condition, env, registry, jobs, &mut io)?;
+                if status.interrupts_execution() {
+                    return Ok(status);
+                }
+                if status.is_success() {
+                    return evaluate(body, env, registry, jobs, io);
+                }
+            }
+
+            match otherwise {
+                Some(body) => evaluate(*body, env, registry, jobs, io),
+                None => Ok(ExitStatus::Success(0)),
+            }
+        },
+
+        ParsedLine::While(clause) => {
+            env.enter_loop();
+            let result = evaluate_while(clause, env, registry, jobs, io);
+            env.leave_loop();
+            result
+        },
+
+        ParsedLine::For(clause) => {
+            env.enter_loop();
+            let result = evaluate_for(clause, env, registry, jobs, io);
+            env.leave_loop();
+            result
+        },
+
+        ParsedLine::Arithmetic(expression) => match arithmetic::evaluate(&expression, env) {
+            Ok(0) => Ok(ExitStatus::Success(1)),
+            Ok(_) => Ok(ExitStatus::Success(0)),
+            Err(err) => expansion_failed(strings::Error::Arithmetic(err), &io.stderr),
+        },
+
+        ParsedLine::Function(Function { name, body }) => {
+            registry.define_function(name, *body);
+            Ok(ExitStatus::Success(0))
+        },
+
+        ParsedLine::SetVariables(vars) => {
+            // Without a command to run, the status is that of the last command substitution
+            env.take_substitution_status();
+            for SetVariable { name, value } in vars {
+                let interpolated_value = match strings::shellstring_to_string(&value, env, registry) {
+                    Ok(value) => value,
+                    Err(err) => return expansion_failed(err, &io.stderr),
+                };
+                env.set(name, interpolated_value);
+            }
+            Ok(ExitStatus::Success(env.take_substitution_status().unwrap_or(0)))
+        },
+
+        ParsedLine::Empty => Ok(ExitStatus::Success(0)),
+    }
+}
+
+/// Evaluates `line`, which is part of a larger statement, with a copy of `io`.
+///
+/// If `exec` replaces the shell's own streams while `line` runs, `io` is replaced with them
+/// too, so that the rest of the statement uses them.
+///
+fn evaluate_part(line: ParsedLine, env: &mut Environment, registry: &Registry, jobs: &mut JobTable, io: &mut Io) -> command::Result {
+    let version = Io::shell_streams_version();
+    let result = evaluate(line, env, registry, jobs, io.try_clone()?);
+    if Io::shell_streams_version() != version {
+        *io = Io::inherit()?;
+    }
+    result
+}
+
+/// Runs `program` in a subshell, returning what it writes to stdout without any trailing
+/// newlines, along with its exit status.
+///
+/// The subshell is a forked copy of the shell, so the program can't affect the shell's own
+/// environment.
+///
+pub fn substitute(program: &[ParsedLine], env: &Environment, registry: &Registry) -> io::Result<(String, u32)> {
+    let (mut reader, writer) = command::pipe()?;
+    let child = command::fork(Group::Inherit, || {
+        run_program(program, env, registry, Io { stdout: writer, ..Io::inherit()? })
+    });
+    let pid = match child {
+        Ok(pid) => pid,
+        Err(Error::Io(kind)) => return Err(kind.into()),
+        Err(_) => return Err(io::ErrorKind::Other.into()),
+    };
+
+    // The subshell can't finish writing if the output isn't read, so stop reading before
+    // waiting for it
+    let mut output = Vec::new();
+    let read = reader.read_to_end(&mut output);
+    drop(reader);
+    let status = loop {
+        if let Some(ProcessState::Exited(code)) = command::wait_for_change(pid, true)? {
+            break code;
+        }
+    };
+    read?;
+
+    Ok((String::from_utf8_lossy(&output).trim_end_matches('\n').to_owned(), status))
+}
+
+/// Replaces the process substitutions in a command's words and redirections with `/dev/fd`
+/// paths to pipes connected to their programs.
+///
+/// Returns the shell's end of each pipe. These need to stay open until the command has started,
+/// or until it's finished if it runs within the shell.
+///
+fn substitute_processes(words: &mut [ShellString], redirects: &mut [Redirect], env: &Environment, registry: &Registry) -> result::Result<Vec<File>, Error> {
+    let mut pipes = Vec::new();
+    for word in words {
+        *word = substitute_word(word, env, registry, &mut pipes)?;
+    }
+
+    for redirect in redirects {
+        match redirect {
+            Redirect::Read(_, file) | Redirect::Write(_, file) | Redirect::Append(_, file) => {
+                *file = substitute_word(file, env, registry, &mut pipes)?;
+            },
+            _ => (),
+        }
+    }
+
+    Ok(pipes)
+}
+
+/// Replaces the process substitutions in `word`, adding the shell's end of each pipe to `pipes`.
+///
+fn substitute_word(word: &ShellString, env: &Environment, registry: &Registry, pipes: &mut Vec<File>) -> result::Result<ShellString, Error> {
+    let mut pieces = Vec::new();
+    for piece in word.iter() {
+        pieces.push(match piece {
+            Piece::ProcessSubstitution { program, direction } => {
+                let pipe = substitute_process(program, *direction, env, registry)?;
+                let path = format!("/dev/fd/{}", pipe.as_raw_fd());
+                pipes.push(pipe);
+                Piece::Fixed(path)
+            },
+            piece => piece.clone(),
+        });
+    }
+    Ok(ShellString::from(pieces))
+}
+
+/// Starts `program` in a subshell with its stdout, or stdin, connected to a pipe, returning the
+/// shell's end of the pipe.
+///
+/// The subshell is a grandchild of the shell, which is adopted by init as soon as its parent
+/// exits. That way the shell never needs to wait for it, even if it outlives the command that
+/// uses it.
+///
+fn substitute_process(program: &[ParsedLine], direction: Direction, env: &Environment, registry: &Registry) -> result::Result<File, Error> {
+    let (reader, writer) = command::pipe()?;
+    let (ours, theirs) = match direction {
+        Direction::Input => (reader, writer),
+        Direction::Output => (writer, reader),
+    };
+
+    let our_fd = ours.as_raw_fd();
+    let child = command::fork(Group::Inherit, || {
+        command::fork(Group::Inherit, || {
+            unsafe { libc::close(our_fd) };
+            let io = match direction {
+                Direction::Input => Io { stdout: theirs, ..Io::inherit()? },
+                Direction::Output => Io { stdin: theirs, ..Io::inherit()? },
+            };
+            run_program(program, env, registry, io)
+        })?;
+        Ok(ExitStatus::Success(0))
+    })?;
+    while let Ok(Some(ProcessState::Running)) = command::wait_for_change(child, true) {}
+
+    Ok(ours)
+}
+
+/// Runs `program` in a copy of `env`, as the body of a subshell.
+///
+fn run_program(program: &[ParsedLine], env: &Environment, registry: &Registry, io: Io) -> command::Result {
+    let mut env = env.clone();
+    let mut jobs = JobTable::new();
+
+    let mut status = ExitStatus::Success(0);
+    for line in program {
+        status = evaluate(line.clone(), &mut env, registry, &mut jobs, io.try_clone()?)?;
+        if let ExitStatus::ExitWith(_) = status {
+            break;
+        }
+    }
+    Ok(status)
+}
+
+/// Runs a `while` loop, until its condition fails.
+///
+fn evaluate_while(While { condition, body }: While, env: &mut Environment, registry: &Registry, jobs: &mut JobTable, mut io: Io) -> command::Result {
+    let mut last_code = 0;
+    loop {
+        let status = evaluate_part((*condition).clone(), env, registry, jobs, &mut io)?;
+        match loop_control(status) {
+            LoopControl::Next(0) => (),
+            LoopControl::Next(_) => return Ok(ExitStatus::Success(last_code)),
+            LoopControl::Finish(status) => return Ok(status),
+        }
+
+        let status = evaluate_part((*body).clone(), env, registry, jobs, &mut io)?;
+        match loop_control(status) {
+            LoopControl::Next(code) => last_code = code,
+            LoopControl::Finish(status) => return Ok(status),
+        }
+    }
+}
+
+/// Runs a `for` loop, once for each of its words.
+///
+fn evaluate_for(For { variable, words, body }: For, env: &mut Environment, registry: &Registry, jobs: &mut JobTable, mut io: Io) -> command::Result {
+    let words = match strings::to_string_vec(words.into_iter(), env, registry) {
+        Ok(words) => words,
+        Err(err) => return expansion_failed(err, &io.stderr),
+    };
+
+    let mut last_code = 0;
+    for word in words {
+        env.set(variable.clone(), word);
+
+        let status = evaluate_part((*body).clone(), env, registry, jobs, &mut io)?;
+        match loop_control(status) {
+            LoopControl::Next(code) => last_code = code,
+            LoopControl::Finish(status) => return Ok(status),
+        }
+    }
+    Ok(ExitStatus::Success(last_code))
+}
+
+/// What a loop does once part of it has run.
+///
+enum LoopControl {
+    /// Carry on with the loop. Holds the exit code of the part that ran.
+    ///
+    Next(u32),
+
+    /// Stop the loop, finishing with the given status.
+    ///
+    Finish(ExitStatus),
+}
+
+/// Decides what a loop does after part of it finishes with `status`.
+///
+/// `break` and `continue` affect the innermost loop, and what's left of them is passed on to the
+/// enclosing loops.
+///
+fn loop_control(status: ExitStatus) -> LoopControl {
+    match status {
+        ExitStatus::Success(code) => LoopControl::Next(code),
+        ExitStatus::Continue(1) => LoopControl::Next(0),
+        ExitStatus::Continue(n) => LoopControl::Finish(ExitStatus::Continue(n - 1)),
+        ExitStatus::Break(1) => LoopControl::Finish(ExitStatus::Success(0)),
+        ExitStatus::Break(n) => LoopControl::Finish(ExitStatus::Break(n - 1)),
+        status => LoopControl::Finish(status),
+    }
+}
+
+/// Executes a single command, waiting for it to finish.
+///
+/// Builtins and functions run within the shell itself. With job control, executables run as a foreground job
+/// so that they can be stopped from the terminal.
+///
+fn execute(command: Command, env: &mut Environment, registry: &Registry, jobs: &mut JobTable, io: Io) -> command::Result {
+    let description = command.to_string();
+    let Command { vars, args: mut pieces, mut redirects } = command;
+
+    // First, process the pieces. Any process substitutions stay connected until the command
+    // finishes.
+    let pipes = substitute_processes(&mut pieces, &mut redirects, env, registry)?;
+    let mut args = match strings::to_string_vec(pieces.into_iter(), env, registry) {
+        Ok(args) => args,
+        Err(err) => return expansion_failed(err, &io.stderr),
+    };
+    let io = match redirect(io, &redirects, env, registry) {
+        Some(io) => io,
+        None => return Ok(ExitStatus::Success(1)),
+    };
+
+    if args.is_empty() {
+        return Ok(ExitStatus::Success(0));
+    }
+
+    let cmd = args.remove(0);
+
+    if jobs.has_job_control() && !registry.is_internal(&cmd) {
+        let mut stderr = io.stderr.try_clone()?;
+        let mut temp_env = match with_variables(env, vars, registry) {
+            Ok(temp_env) => temp_env,
+            Err(err) => return expansion_failed(err, &stderr),
+        };
+        inherit_pipes(&mut temp_env, &pipes);
+        let group = jobs.foreground_group();
+        let pid = registry.spawn(&cmd, Context { env: &mut temp_env, args, registry, jobs, io }, group)?;
+        return run_in_foreground(vec![pid], description, jobs, &mut stderr);
+    }
+
+    // If there are variables, we need to create a temporary environment with
+    // the new vars. Otherwise we can just use the current one.
+    let result = if vars.is_empty() {
+        let outer = inherit_pipes(env, &pipes);
+        let result = registry.execute(&cmd, Context { env, args, registry, jobs, io });
+        env.set_inherited_files(outer);
+        result
+    } else {
+        let mut temp_env = match with_variables(env, vars, registry) {
+            Ok(temp_env) => temp_env,
+            Err(err) => return expansion_failed(err, &io.stderr),
+        };
+        inherit_pipes(&mut temp_env, &pipes);
+        registry.execute(&cmd, Context { env: &mut temp_env, args, registry, jobs, io })
+    };
+
+    match result {
+        Err(Error::UnknownCommand) => Ok(ExitStatus::Success(127)),
+        _ => result,
+    }
+}
+
+/// Runs the job made up of the given processes in the foreground, returning its status.
+///
+/// If the job is stopped, it's reported on `stderr` and the shell carries on without it.
+///
+fn run_in_foreground(pids: Vec<Pid>, description: String, jobs: &mut JobTable, stderr: &mut File) -> command::Result {
+    match jobs.run_in_foreground(pids, description)? {
+        State::Stopped => {
+            if let Some(job) = jobs.current().and_then(|id| jobs.get(id)) {
+                writeln!(stderr, "\n{}", jobs.describe(job))?;
+            }
+            Ok(ExitStatus::Success(128 + libc::SIGTSTP as u32))
+        },
+        State::Done(code) => Ok(ExitStatus::Success(code)),
+        State::Running => Ok(ExitStatus::Success(0)),
+    }
+}
+
+/// Starts every command in a pipeline, without waiting for them to finish.
+///
+/// Every command in the pipeline runs in its own process, so builtins in a pipeline can't
+/// affect the shell's environment. The first command is started in `group`, and if that's a
+/// new group, the remaining commands join it.
+///
+/// Returns the pids of the started processes, in order.
+///
+fn start_pipeline(commands: Vec<Command>, env: &mut Environment, registry: &Registry, jobs: &mut JobTable, io: Io, group: Group) -> result::Result<Vec<Pid>, Error> {
+    let last = commands.len() - 1;
+    let mut pids = Vec::with_capacity(commands.len());
+    let mut group = group;
+
+    let mut stdin = io.stdin;
+    for (index, command) in commands.into_iter().enumerate() {
+        let (stdout, next_stdin) = if index == last {
+            (io.stdout.try_clone()?, None)
+        } else {
+            let (reader, writer) = command::pipe()?;
+            (writer, Some(reader))
+        };
+
+        let stage_io = Io { stdin, stdout, stderr: io.stderr.try_clone()? };
+        let pid = spawn(command, env, registry, jobs, stage_io, group)?;
+        if let Group::New(_) = group {
+            group = Group::Join(pid);
+        }
+        pids.push(pid);
+
+        match next_stdin {
+            Some(reader) => stdin = reader,
+            None => break,
+        }
+    }
+
+    Ok(pids)
+}
+
+/// Starts a single command, without waiting for it to finish.
+///
+fn spawn(Command { vars, args: mut pieces, mut redirects }: Command, env: &mut Environment, registry: &Registry, jobs: &mut JobTable, io: Io, group: Group) -> result::Result<Pid, Error> {
+    // The shell's ends of any process substitutions are closed once the command has started
+    let pipes = substitute_processes(&mut pieces, &mut redirects, env, registry)?;
+    let mut args = match strings::to_string_vec(pieces.into_iter(), env, registry) {
+        Ok(args) => args,
+        Err(err) => return command::fork(group, || expansion_failed(err, &io.stderr)),
+    };
+    let io = match redirect(io, &redirects, env, registry) {
+        Some(io) => io,
+        None => return command::fork(group, || Ok(ExitStatus::Success(1))),
+    };
+
+    if args.is_empty() {
+        return command::fork(group, || Ok(ExitStatus::Success(0)));
+    }
+
+    let cmd = args.remove(0);
+    let mut temp_env = match with_variables(env, vars, registry) {
+        Ok(temp_env) => temp_env,
+        Err(err) => return command::fork(group, || expansion_failed(err, &io.stderr)),
+    };
+    inherit_pipes(&mut temp_env, &pipes);
+    registry.spawn(&cmd, Context { env: &mut temp_env, args, registry, jobs, io }, group)
+}
+
+/// Applies `redirects`, in order, to the given streams.
+///
+/// Files are relative to the environment's working directory. If a redirection fails, an error
+/// is written to stderr and `None` is returned.
+///
+fn redirect(mut io: Io, redirects: &[Redirect], env: &mut Environment, registry: &Registry) -> Option<Io> {
+    for redirect in redirects {
+        let (fd, target, result) = match redirect {
+            Redirect::Read(fd, file) => {
+                let file = redirect_target(file, env, registry, &io.stderr)?;
+                let result = OpenOptions::new().read(true).open(env.resolve_path(&file));
+                (*fd, file, result)
+            },
+            Redirect::Write(fd, file) => {
+                let file = redirect_target(file, env, registry, &io.stderr)?;
+                let result = OpenOptions::new().write(true).create(true).truncate(true).open(env.resolve_path(&file));
+                (*fd, file, result)
+            },
+            Redirect::Append(fd, file) => {
+                let file = redirect_target(file, env, registry, &io.stderr)?;
+                let result = OpenOptions::new().append(true).create(true).open(env.resolve_path(&file));
+                (*fd, file, result)
+            },
+            Redirect::Duplicate(fd, other) => {
+                let result = io.stream(*other).and_then(|stream| stream.try_clone());
+                (*fd, other.to_string(), result)
+            },
+            Redirect::HereDocument(fd, body) => {
+                let body = redirect_target(body, env, registry, &io.stderr)?;
+                (*fd, "here-document".to_owned(), input_from(&body))
+            },
+            Redirect::HereString(fd, word) => {
+                let word = redirect_target(word, env, registry, &io.stderr)?;
+                (*fd, "here-string".to_owned(), input_from(&(word + "\n")))
+            },
+        };
+
+        if let Err(err) = result.and_then(|file| io.set_stream(fd, file)) {
+            let _ = writeln!(io.stderr, "gesh: {}: {}", target, err);
+            return None;
+        }
+    }
+
+    Some(io)
+}
+
+/// Expands the file that is the target of a redirection, or the text it reads from, reporting
+/// any error on `stderr`.
+///
+fn redirect_target(file: &ShellString, env: &mut Environment, registry: &Registry, mut stderr: &File) -> Option<String> {
+    match strings::shellstring_to_string(file, env, registry) {
+        Ok(file) => Some(file),
+        Err(err) => {
+            let _ = writeln!(stderr, "gesh: {}", err);
+            None
+        },
+    }
+}
+
+/// Creates a file to read `text` from, for a here-document or here-string.
+///
+/// The file is removed as soon as it's created, so it's gone once the last handle to it is
+/// closed.
+///
+fn input_from(text: &str) -> io::Result<File> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let name = format!("gesh-{}-{}", process::id(), COUNT.fetch_add(1, Ordering::Relaxed));
+    let path = env::temp_dir().join(name);
+    let mut file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+    fs::remove_file(&path)?;
+
+    file.write_all(text.as_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+/// Creates a copy of `env` with the given variables set and exported.
+///
+fn with_variables(env: &Environment, vars: Vec<SetVariable>, registry: &Registry) -> strings::Result<Environment> {
+    let mut temp_env = env.clone();
+    for SetVariable { name, value } in vars {
+        let interpolated_value = strings::shellstring_to_string(&value, &mut temp_env, registry)?;
+        temp_env.set(name.clone(), interpolated_value);
+        temp_env.export(name);
+    }
+    Ok(temp_env)
+}
+
+/// Lets executables started in `env` use the shell's ends of a command's process substitutions,
+/// returning the files they could use before.
+///
+fn inherit_pipes(env: &mut Environment, pipes: &[File]) -> Vec<RawFd> {
+    let mut files = env.inherited_files().to_vec();
+    files.extend(pipes.iter().map(AsRawFd::as_raw_fd));
+    env.set_inherited_files(files)
+}
+
+/// Reports an error expanding a command's words on `stderr`, failing the command.
+///
+/// An unset parameter in `${VAR:?message}` is an error, so that it can stop the shell.
+///
+fn expansion_failed(err: strings::Error, mut stderr: &File) -> command::Result {
+    writeln!(stderr, "gesh: {}", err)?;
+    match err {
+        strings::Error::Unset { .. } => Err(Error::UnsetParameter),
+        _ => Ok(ExitStatus::Success(1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geshl::Parser;
+
+    use std::{
+        env,
+        fs,
+        io::{
+            Read,
+        },
+        os::unix::fs::PermissionsExt,
+        path::PathBuf,
+    };
+
+    use super::*;
+
+    /// Parses a single statement.
+    ///
+    fn parse(source: &str) -> ParsedLine {
+        Parser::new().parse(source).expect("should parse").remove(0)
+    }
+
+    /// Returns a path in the temporary directory that's unique to the test called `name`.
+    ///
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().canonicalize().expect("temp dir").join(format!("gesh-test-{}-{}", name, process::id()))
+    }
+
+    #[test]
+    fn test_evaluate_pipes_output_between_commands() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let line = parse("echo foo bar | tr a-z A-Z | tr -d ' '");
+        let result = evaluate(line, &mut env, &registry, &mut JobTable::new(), io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!("FOOBAR\n", output);
+    }
+
+    #[test]
+    fn test_evaluate_pipes_builtin_output_into_executable() {
+        let mut env = Environment::empty();
+        env.set("PATH".to_owned(), "/bin:/usr/bin".to_owned());
+        env.export("PATH".to_owned());
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let line = parse("export | grep PATH");
+        let result = evaluate(line, &mut env, &registry, &mut JobTable::new(), io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!("PATH=/bin:/usr/bin\n", output);
+    }
+
+    #[test]
+    fn test_evaluate_redirects_output_to_files() {
+        let directory = temp_path("redirects");
+        fs::create_dir_all(&directory).expect("create directory");
+        let mut env = Environment::from_existing_env();
+        env.set_working_directory(directory.clone());
+        let registry = Registry::new();
+
+        let line = parse("echo foo > out.txt");
+        assert_eq!(Ok(ExitStatus::Success(0)), evaluate(line, &mut env, &registry, &mut JobTable::new(), Io::inherit().expect("io")));
+
+        let line = parse("ls out.txt /nonexistent &>> out.txt");
+        evaluate(line, &mut env, &registry, &mut JobTable::new(), Io::inherit().expect("io")).expect("should execute");
+
+        let contents = fs::read_to_string(directory.join("out.txt")).expect("read");
+        fs::remove_dir_all(&directory).expect("remove directory");
+
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(3, lines.len());
+        assert_eq!("foo", lines[0]);
+        assert!(lines.contains(&"out.txt"));
+    }
+
+    #[test]
+    fn test_evaluate_applies_exec_redirections_to_the_rest_of_the_line() {
+        let directory = temp_path("exec");
+        fs::create_dir_all(&directory).expect("create directory");
+        let mut env = Environment::from_existing_env();
+        env.set_working_directory(directory.clone());
+        let registry = Registry::new();
+
+        // `exec` replaces the streams of the whole process, so it runs in a child
+        let child = command::fork(Group::Inherit, || {
+            evaluate(parse("exec 2> first; ls /nonexistent"), &mut env, &registry, &mut JobTable::new(), Io::inherit()?)?;
+            let line = parse("if true; then exec 2> second; ls /nonexistent; fi");
+            evaluate(line, &mut env, &registry, &mut JobTable::new(), Io::inherit()?)
+        }).expect("fork");
+        while let Ok(Some(ProcessState::Running)) = command::wait_for_change(child, true) {}
+
+        let first = fs::read_to_string(directory.join("first")).expect("read");
+        let second = fs::read_to_string(directory.join("second")).expect("read");
+        fs::remove_dir_all(&directory).expect("remove directory");
+
+        assert!(first.contains("/nonexistent"));
+        assert!(second.contains("/nonexistent"));
+    }
+
+    #[test]
+    fn test_evaluate_runs_relative_commands_after_changing_directory_and_path() {
+        let directory = temp_path("relative");
+        fs::create_dir_all(directory.join("bin")).expect("create directory");
+        let script = directory.join("bin/run.sh");
+        fs::write(&script, "#!/bin/sh\necho ran\n").expect("write");
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).expect("chmod");
+
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let source = format!("cd {0}; ./bin/run.sh; cd bin; ./run.sh; PATH={0}/bin; run.sh", directory.display());
+        let result = evaluate(parse(&source), &mut env, &registry, &mut JobTable::new(), io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+        fs::remove_dir_all(&directory).expect("remove directory");
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!("ran\nran\nran\n", output);
+    }
+
+    #[test]
+    fn test_evaluate_redirects_input_and_stderr_in_pipeline() {
+        let mut env = Environment::from_existing_env();
+        env.set_working_directory(PathBuf::from(env!("CARGO_MANIFEST_DIR")));
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let line = parse("grep -c rustyline < Cargo.toml | cat - /nonexistent 2>&1");
+        let result = evaluate(line, &mut env, &registry, &mut JobTable::new(), io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+        assert!(output.starts_with("1\n"));
+        assert!(output.contains("/nonexistent"));
+    }
+
+    #[test]
+    fn test_evaluate_substitutes_processes_as_files() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let line = parse("cat <(echo a) < <(echo b) - | tr a-z A-Z");
+        let result = evaluate(line, &mut env, &registry, &mut JobTable::new(), io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!("A\nB\n", output);
+    }
+
+    #[test]
+    fn test_evaluate_keeps_substituted_processes_from_other_commands() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        // The second program mustn't be given the first one's pipe
+        let line = parse("cat <(true) <(ls /proc/self/fd)");
+        let result = evaluate(line, &mut env, &registry, &mut JobTable::new(), io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!("0\n1\n2\n3\n", output);
+    }
+
+    #[test]
+    fn test_evaluate_fails_when_redirect_cannot_be_opened() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+
+        let line = parse("cat < /nonexistent/file 2> /dev/null");
+        assert_eq!(Ok(ExitStatus::Success(1)), evaluate(line, &mut env, &registry, &mut JobTable::new(), Io::inherit().expect("io")));
+    }
+
+    #[test]
+    fn test_evaluate_short_circuits_and_or() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let line = parse("false && echo a || echo b; true || echo c && echo d; true && false");
+        let result = evaluate(line, &mut env, &registry, &mut JobTable::new(), io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+        assert_eq!("b\nd\n", output);
+    }
+
+    #[test]
+    fn test_evaluate_treats_unknown_command_as_failure() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+        let io = Io::null().expect("io");
+
+        let line = parse("gesh-not-a-command || FOO=bar");
+        assert_eq!(Ok(ExitStatus::Success(0)), evaluate(line, &mut env, &registry, &mut JobTable::new(), io));
+        assert_eq!(Some("bar".to_owned()), env.get(&"FOO".to_owned()));
+    }
+
+    #[test]
+    fn test_evaluate_stops_list_when_exiting() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+
+        let line = parse("exit 3; FOO=bar");
+        assert_eq!(Ok(ExitStatus::ExitWith(3)), evaluate(line, &mut env, &registry, &mut JobTable::new(), Io::inherit().expect("io")));
+        assert_eq!(None, env.get(&"FOO".to_owned()));
+    }
+
+    #[test]
+    fn test_evaluate_starts_background_jobs_without_waiting() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+        let mut jobs = JobTable::new();
+        let log = temp_path("background");
+        let io = Io { stderr: fs::File::create(&log).expect("create"), ..Io::inherit().expect("io") };
+
+        let line = parse("sleep 5 | cat & false && true &");
+        assert_eq!(Ok(ExitStatus::Success(0)), evaluate(line, &mut env, &registry, &mut jobs, io));
+
+        // Without job control, the shell isn't interactive, so the jobs aren't reported
+        let notices = fs::read_to_string(&log).expect("read");
+        fs::remove_file(&log).expect("remove");
+        assert_eq!("", notices);
+
+        let descriptions = jobs.iter().map(|job| job.command.clone()).collect::<Vec<_>>();
+        assert_eq!(vec!["sleep 5 | cat".to_owned(), "false && true".to_owned()], descriptions);
+
+        let pgid = jobs.get(1).expect("job").pgid;
+        unsafe { libc::kill(-pgid, libc::SIGKILL) };
+        assert_eq!(Some(State::Done(128 + libc::SIGKILL as u32)), jobs.wait(1).expect("wait"));
+        assert_eq!(Some(State::Done(1)), jobs.wait(2).expect("wait"));
+    }
+
+    #[test]
+    fn test_evaluate_returns_status_of_last_command_in_pipeline() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+
+        let line = parse("true | false");
+        assert_eq!(Ok(ExitStatus::Success(1)), evaluate(line, &mut env, &registry, &mut JobTable::new(), Io::inherit().expect("io")));
+
+        let line = parse("false | true");
+        assert_eq!(Ok(ExitStatus::Success(0)), evaluate(line, &mut env, &registry, &mut JobTable::new(), Io::inherit().expect("io")));
+    }
+
+    #[test]
+    fn test_evaluate_runs_first_branch_whose_condition_succeeds() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let line = parse("if false; then echo a; elif true; then echo b; else echo c; fi; if false; then echo d; fi");
+        let result = evaluate(line, &mut env, &registry, &mut JobTable::new(), io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!("b\n", output);
+    }
+
+    #[test]
+    fn test_evaluate_loops_with_break_and_continue() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let source = "for x in a b c d; do \
+            if test \"${x}\" = b; then continue; fi; \
+            for y in 1 2; do \
+                if test \"${x}\" = d; then break 2; fi; \
+                echo \"${x}${y}\"; \
+            done; \
+        done; \
+        while true; do echo e; break; done";
+        let result = evaluate(parse(source), &mut env, &registry, &mut JobTable::new(), io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!("a1\na2\nc1\nc2\ne\n", output);
+        assert_eq!(Some("d".to_owned()), env.get(&"x".to_owned()));
+    }
+
+    #[test]
+    fn test_evaluate_loops_until_condition_fails() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+
+        let line = parse("while test -z \"${DONE}\"; do DONE=yes; false; done");
+        let result = evaluate(line, &mut env, &registry, &mut JobTable::new(), Io::inherit().expect("io"));
+
+        assert_eq!(Ok(ExitStatus::Success(1)), result);
+        assert_eq!(Some("yes".to_owned()), env.get(&"DONE".to_owned()));
+    }
+
+    #[test]
+    fn test_evaluate_records_last_status() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let line = parse("false; echo $? \"$?\"; sh -c 'exit 4' || echo $?");
+        let result = evaluate(line, &mut env, &registry, &mut JobTable::new(), io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!("1 1\n4\n", output);
+        assert_eq!(0, env.last_status());
+    }
+
+    #[test]
+    fn test_evaluate_records_status_of_substitutions_in_assignments() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let line = parse("x=$(false); echo $?; false; y=$? z=$(exit 3); echo $? $y; x=done; echo $?");
+        let result = evaluate(line, &mut env, &registry, &mut JobTable::new(), io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!("1\n3 1\n0\n", output);
+    }
+
+    #[test]
+    fn test_evaluate_calls_functions_with_their_own_arguments_and_locals() {
+        let mut env = Environment::from_existing_env();
+        env.set_positional_parameters("gesh".to_owned(), vec!["outer".to_owned()]);
+        env.set("x".to_owned(), "global".to_owned());
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let source = "greet() {\n\
+            local x=\"${2}\"\n\
+            echo \"${1} ${x} ${#}\"\n\
+            return 3\n\
+            echo unreachable\n\
+        }\n\
+        greet hello world | cat; greet hi there";
+        let mut result = Ok(ExitStatus::Success(0));
+        for statement in Parser::new().parse(source).expect("should parse") {
+            result = evaluate(statement, &mut env, &registry, &mut JobTable::new(), io.try_clone().expect("io"));
+        }
+        drop(io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+
+        assert_eq!(Ok(ExitStatus::Success(3)), result);
+        assert_eq!("hello world 2\nhi there 2\n", output);
+        assert_eq!(Some("global".to_owned()), env.get(&"x".to_owned()));
+        assert_eq!(Some("outer".to_owned()), env.get(&"1".to_owned()));
+    }
+
+    #[test]
+    fn test_evaluate_counts_with_arithmetic() {
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+        let (mut reader, writer) = command::pipe().expect("pipe");
+        let io = Io { stdout: writer, ..Io::inherit().expect("io") };
+
+        let line = parse("while (( i < 3 )); do echo \"$(( i++ * 2 ))\"; done");
+        let result = evaluate(line, &mut env, &registry, &mut JobTable::new(), io);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).expect("read");
+
+        assert_eq!(Ok(ExitStatus::Success(0)), result);
+        assert_eq!("0\n2\n4\n", output);
+        assert_eq!(Some("3".to_owned()), env.get(&"i".to_owned()));
+    }
+}
@@ -0,0 +1,455 @@
+//! Job management.
+//!
+//! A job is a line that the shell started without waiting for it to finish, or one that was
+//! stopped while running in the foreground. Every job runs in its own process group, so that the
+//! job can be continued or waited on as a whole.
+//!
+//! When the shell has a terminal, the job table also takes care of handing the terminal to jobs
+//! running in the foreground, and taking it back once they finish or stop.
+//!
+use command::{
+    self,
+    Group,
+    Pid,
+    ProcessState,
+};
+
+use terminal::{
+    Modes,
+    Terminal,
+};
+
+use std::{
+    io,
+};
+
+/// The state of a job.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    /// At least one process in the job is still running.
+    ///
+    Running,
+
+    /// The job has been stopped by a signal, and can be continued.
+    ///
+    Stopped,
+
+    /// Every process in the job has terminated. Holds the exit code of the last process.
+    ///
+    Done(u32),
+}
+
+/// A job started by the shell.
+///
+pub struct Job {
+    pub id: usize,
+    pub pgid: Pid,
+    pub command: String,
+    processes: Vec<(Pid, ProcessState)>,
+    modes: Option<Modes>,
+}
+
+/// A table of the jobs started by the shell.
+///
+#[derive(Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+    terminal: Option<Terminal>,
+}
+
+impl Job {
+    /// Constructs a running job made up of the given processes, the first of which leads the
+    /// job's process group.
+    ///
+    fn new(id: usize, pids: Vec<Pid>, command: String) -> Job {
+        Job {
+            id,
+            pgid: pids[0],
+            command,
+            processes: pids.into_iter().map(|pid| (pid, ProcessState::Running)).collect(),
+            modes: None,
+        }
+    }
+
+    /// Returns the state of the job, as of the last update.
+    ///
+    pub fn state(&self) -> State {
+        if self.processes.iter().any(|&(_, state)| state == ProcessState::Stopped) {
+            return State::Stopped;
+        }
+
+        match self.processes.last() {
+            Some(&(_, ProcessState::Exited(code))) if self.is_terminated() => State::Done(code),
+            _ => State::Running,
+        }
+    }
+
+    /// Returns the pid of the last process in the job.
+    ///
+    pub fn last_pid(&self) -> Pid {
+        self.processes.last().map(|&(pid, _)| pid).unwrap_or(self.pgid)
+    }
+
+    /// Returns whether or not the given pid belongs to this job.
+    ///
+    pub fn contains(&self, pid: Pid) -> bool {
+        self.processes.iter().any(|&(other, _)| other == pid)
+    }
+
+    /// Returns whether or not every process in this job has terminated.
+    ///
+    fn is_terminated(&self) -> bool {
+        self.processes.iter().all(|&(_, state)| matches!(state, ProcessState::Exited(_)))
+    }
+
+    /// Updates the state of every process in this job that hasn't terminated yet.
+    ///
+    /// If `block` is true, waits until every process has terminated, or any process has stopped.
+    ///
+    fn update(&mut self, block: bool) -> io::Result<()> {
+        for process in self.processes.iter_mut() {
+            while !matches!(process.1, ProcessState::Exited(_)) {
+                match command::wait_for_change(process.0, block)? {
+                    Some(state) => {
+                        process.1 = state;
+                        if block && state == ProcessState::Stopped {
+                            return Ok(());
+                        }
+                    },
+                    None => break,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Continues this job, if it was stopped.
+    ///
+    fn resume(&mut self) -> io::Result<()> {
+        if self.state() == State::Stopped {
+            command::continue_group(self.pgid)?;
+            for process in self.processes.iter_mut() {
+                if process.1 == ProcessState::Stopped {
+                    process.1 = ProcessState::Running;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl JobTable {
+    /// Constructs an empty job table.
+    ///
+    pub fn new() -> JobTable {
+        JobTable { jobs: Vec::new(), terminal: None }
+    }
+
+    /// Constructs an empty job table that runs foreground jobs on the given terminal.
+    ///
+    pub fn with_terminal(terminal: Terminal) -> JobTable {
+        JobTable { jobs: Vec::new(), terminal: Some(terminal) }
+    }
+
+    /// Returns whether or not jobs can be moved between the foreground and background.
+    ///
+    /// If so, every job should be started in its own process group.
+    ///
+    pub fn has_job_control(&self) -> bool {
+        self.terminal().is_some()
+    }
+
+    /// Returns the process group to start a foreground job in.
+    ///
+    /// With job control, the job gets a new group that takes over the terminal. Otherwise, it
+    /// stays in the shell's group.
+    ///
+    pub fn foreground_group(&self) -> Group {
+        match self.terminal() {
+            Some(terminal) => Group::New(Some(terminal.fd())),
+            None => Group::Inherit,
+        }
+    }
+
+    /// Adds a job made up of the given processes, the first of which leads the job's process
+    /// group.
+    ///
+    /// Returns the new job.
+    ///
+    pub fn add(&mut self, pids: Vec<Pid>, command: String) -> &Job {
+        let id = self.next_id();
+        self.jobs.push(Job::new(id, pids, command));
+        &self.jobs[self.jobs.len() - 1]
+    }
+
+    /// Runs a job made up of the given processes in the foreground, waiting for it to finish or
+    /// stop.
+    ///
+    /// The job is only added to the table if it stops, in which case it becomes the current job.
+    ///
+    pub fn run_in_foreground(&mut self, pids: Vec<Pid>, command: String) -> io::Result<State> {
+        let mut job = Job::new(0, pids, command);
+        let state = self.wait_in_foreground(&mut job)?;
+        if state == State::Stopped {
+            job.id = self.next_id();
+            self.jobs.push(job);
+        }
+        Ok(state)
+    }
+
+    /// Continues the job with the given id in the foreground, waiting for it to finish or stop.
+    ///
+    /// Finished jobs are removed from the table, and stopped jobs become the current job.
+    ///
+    pub fn foreground(&mut self, id: usize) -> io::Result<Option<State>> {
+        let mut job = match self.remove(id) {
+            Some(job) => job,
+            None => return Ok(None),
+        };
+
+        let result = self.wait_in_foreground(&mut job);
+        let state = job.state();
+        if !matches!(state, State::Done(_)) {
+            self.jobs.push(job);
+        }
+        result.map(|_| Some(state))
+    }
+
+    /// Returns an iterator over all jobs, from oldest to newest.
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    /// Returns whether or not there are any jobs.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Finds the id of the job referenced by `spec`.
+    ///
+    /// A job spec is one of:
+    /// - `%N` or `N`, for job number `N`,
+    /// - `%+` or `%%`, for the current job,
+    /// - `%-`, for the previous job, or
+    /// - `%string`, for the job whose command starts with `string`.
+    ///
+    pub fn find(&self, spec: &str) -> Option<usize> {
+        let spec = spec.trim_start_matches('%');
+        match spec {
+            "" | "+" | "%" => self.current(),
+            "-" => self.previous(),
+            _ => match spec.parse::<usize>() {
+                Ok(id) => self.get(id).map(|job| job.id),
+                Err(_) => self.jobs.iter().rev().find(|job| job.command.starts_with(spec)).map(|job| job.id),
+            },
+        }
+    }
+
+    /// Returns the id of the current job, which is the one most recently started.
+    ///
+    pub fn current(&self) -> Option<usize> {
+        self.jobs.last().map(|job| job.id)
+    }
+
+    /// Returns the id of the previous job, which is the one started before the current job.
+    ///
+    pub fn previous(&self) -> Option<usize> {
+        self.jobs.iter().rev().nth(1).map(|job| job.id)
+    }
+
+    /// Returns the job with the given id.
+    ///
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    /// Removes the job with the given id.
+    ///
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    /// Updates the state of every job, without blocking.
+    ///
+    pub fn update(&mut self) -> io::Result<()> {
+        for job in self.jobs.iter_mut() {
+            job.update(false)?;
+        }
+        Ok(())
+    }
+
+    /// Removes and returns every job that has finished.
+    ///
+    pub fn remove_finished(&mut self) -> Vec<Job> {
+        let (finished, remaining) = self.jobs.drain(..).partition(|job| job.is_terminated());
+        self.jobs = remaining;
+        finished
+    }
+
+    /// Waits for the job with the given id to finish or stop, returning its state.
+    ///
+    /// Finished jobs are removed from the table.
+    ///
+    pub fn wait(&mut self, id: usize) -> io::Result<Option<State>> {
+        let state = match self.jobs.iter_mut().find(|job| job.id == id) {
+            Some(job) => {
+                job.update(true)?;
+                job.state()
+            },
+            None => return Ok(None),
+        };
+
+        if let State::Done(_) = state {
+            self.remove(id);
+        }
+        Ok(Some(state))
+    }
+
+    /// Continues the job with the given id, if it was stopped.
+    ///
+    pub fn resume(&mut self, id: usize) -> io::Result<()> {
+        match self.jobs.iter_mut().find(|job| job.id == id) {
+            Some(job) => job.resume(),
+            None => Ok(()),
+        }
+    }
+
+    /// Formats a job for display, as in the output of `jobs`.
+    ///
+    /// # Examples
+    ///
+    /// - `[1]+  Running                 sleep 10 &`
+    /// - `[2]-  Exit 1                  false`
+    ///
+    pub fn describe(&self, job: &Job) -> String {
+        let marker = if Some(job.id) == self.current() {
+            '+'
+        } else if Some(job.id) == self.previous() {
+            '-'
+        } else {
+            ' '
+        };
+
+        let (state, suffix) = match job.state() {
+            State::Running => ("Running".to_owned(), " &"),
+            State::Stopped => ("Stopped".to_owned(), ""),
+            State::Done(0) => ("Done".to_owned(), ""),
+            State::Done(code) => (format!("Exit {}", code), ""),
+        };
+
+        format!("[{}]{}  {:<24}{}{}", job.id, marker, state, job.command, suffix)
+    }
+
+    /// Returns the id to use for the next job added to the table.
+    ///
+    fn next_id(&self) -> usize {
+        self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1
+    }
+
+    /// Returns the terminal, if this process is the shell that owns it.
+    ///
+    fn terminal(&self) -> Option<&Terminal> {
+        self.terminal.as_ref().filter(|terminal| terminal.is_owner())
+    }
+
+    /// Continues `job` with the terminal, if there is one, and waits for it to finish or stop.
+    ///
+    /// The terminal is always returned to the shell, even if waiting fails.
+    ///
+    fn wait_in_foreground(&self, job: &mut Job) -> io::Result<State> {
+        let terminal = self.terminal();
+        if let Some(terminal) = terminal {
+            terminal.give_to(job.pgid, job.modes.as_ref())?;
+        }
+
+        let result = job.resume().and_then(|_| job.update(true));
+
+        if let Some(terminal) = terminal {
+            let modes = terminal.reclaim()?;
+            if job.state() == State::Stopped {
+                job.modes = Some(modes);
+            }
+        }
+
+        result.map(|_| job.state())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use command::{
+        ExitStatus,
+    };
+    use libc;
+
+    use super::*;
+
+    #[test]
+    fn test_add_assigns_increasing_ids() {
+        let mut jobs = JobTable::new();
+        assert_eq!(1, jobs.add(vec![100, 101], "a | b".to_owned()).id);
+        assert_eq!(2, jobs.add(vec![200], "c".to_owned()).id);
+
+        jobs.remove(2);
+        assert_eq!(2, jobs.add(vec![300], "d".to_owned()).id);
+    }
+
+    #[test]
+    fn test_find_resolves_job_specs() {
+        let mut jobs = JobTable::new();
+        jobs.add(vec![100], "sleep 10".to_owned());
+        jobs.add(vec![200], "vim foo.txt".to_owned());
+        jobs.add(vec![300], "make".to_owned());
+
+        assert_eq!(Some(3), jobs.find("%%"));
+        assert_eq!(Some(3), jobs.find("%+"));
+        assert_eq!(Some(3), jobs.find("%"));
+        assert_eq!(Some(2), jobs.find("%-"));
+        assert_eq!(Some(1), jobs.find("%1"));
+        assert_eq!(Some(1), jobs.find("1"));
+        assert_eq!(Some(2), jobs.find("%vim"));
+        assert_eq!(None, jobs.find("%4"));
+        assert_eq!(None, jobs.find("%emacs"));
+    }
+
+    #[test]
+    fn test_wait_returns_status_of_last_process_and_removes_job() {
+        let first = command::fork(Group::New(None), || Ok(ExitStatus::Success(1))).expect("fork");
+        let second = command::fork(Group::Join(first), || Ok(ExitStatus::Success(2))).expect("fork");
+
+        let mut jobs = JobTable::new();
+        let id = jobs.add(vec![first, second], "a | b".to_owned()).id;
+
+        assert_eq!(Some(State::Done(2)), jobs.wait(id).expect("wait"));
+        assert!(jobs.is_empty());
+    }
+
+    #[test]
+    fn test_stopped_foreground_job_is_added_and_can_be_resumed() {
+        let pid = command::fork(Group::New(None), || {
+            unsafe { libc::raise(libc::SIGSTOP) };
+            Ok(ExitStatus::Success(4))
+        }).expect("fork");
+
+        let mut jobs = JobTable::new();
+        assert_eq!(State::Stopped, jobs.run_in_foreground(vec![pid], "vim".to_owned()).expect("run"));
+        assert_eq!(Some(1), jobs.current());
+
+        assert_eq!(Some(State::Done(4)), jobs.foreground(1).expect("foreground"));
+        assert!(jobs.is_empty());
+    }
+
+    #[test]
+    fn test_describe_formats_job() {
+        let mut jobs = JobTable::new();
+        jobs.add(vec![100], "sleep 10".to_owned());
+        jobs.add(vec![200], "sleep 20".to_owned());
+
+        assert_eq!("[1]-  Running                 sleep 10 &", jobs.describe(jobs.get(1).unwrap()));
+        assert_eq!("[2]+  Running                 sleep 20 &", jobs.describe(jobs.get(2).unwrap()));
+    }
+}
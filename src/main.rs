@@ -1,29 +1,83 @@
-// Needed to impl FnMut by command::path::Executable
-#![feature(unboxed_closures)]
-#![feature(fn_traits)]
-
 extern crate geshl;
-extern crate glob;
+extern crate libc;
 extern crate rustyline;
 
+mod arithmetic;
 mod command;
 mod environment;
+mod eval;
+mod jobs;
+mod pattern;
 mod prompt;
 mod shell;
 mod strings;
+mod terminal;
 
-use std::process;
+use std::{
+  env,
+  io,
+  process,
+};
 use command::ExitStatus;
+use prompt::{
+  Prompt,
+  rustyline::RustylinePrompt,
+  script::ScriptPrompt,
+};
 
+/// Usage:
+///
+/// - `gesh`, for an interactive shell, or to run commands piped into stdin,
+/// - `gesh script [args...]`, to run the commands in a script, or
+/// - `gesh -c commands [name [args...]]`, to run the given commands.
+///
 fn main() {
+  let mut args = env::args();
+  let name = args.next().unwrap_or_else(|| "gesh".to_owned());
+  let mut args = args.collect::<Vec<_>>();
+
+  if args.is_empty() {
+    if unsafe { libc::isatty(0) } == 0 {
+      run(ScriptPrompt::new(io::stdin().lock()), name, args);
+    }
+    run(RustylinePrompt::new(), name, args);
+  }
+
+  let first = args.remove(0);
+  if first == "-c" {
+    if args.is_empty() {
+      eprintln!("gesh: -c: option requires an argument");
+      process::exit(2);
+    }
+
+    let commands = args.remove(0);
+    let name = if args.is_empty() { name } else { args.remove(0) };
+    run(ScriptPrompt::from_string(commands), name, args);
+  }
+
+  match ScriptPrompt::from_file(&first) {
+    Ok(prompt) => run(prompt, first, args),
+    Err(err) => {
+      eprintln!("gesh: {}: {}", first, err);
+      process::exit(127);
+    }
+  }
+}
+
+/// Runs a shell that reads commands from `prompt`, then exits with the shell's status.
+///
+fn run<R: Prompt>(prompt: R, name: String, args: Vec<String>) -> ! {
   let mut my_shell = shell::Shell {
-    prompt: prompt::rustyline::RustylinePrompt::new(),
+    prompt,
     parser: geshl::Parser::new(),
+    name,
+    args,
   };
 
   match my_shell.run() {
     Ok(ExitStatus::Success(status)) => process::exit(status as i32),
-    Ok(ExitStatus::ExitWith(status)) => process::exit(status as i32),
+    Ok(ExitStatus::ExitWith(status)) | Ok(ExitStatus::Return(status)) => process::exit(status as i32),
+    Ok(ExitStatus::Break(_)) | Ok(ExitStatus::Continue(_)) => process::exit(0),
     Err(error) => {
       println!("error: {:?}", error);
       process::exit(255);
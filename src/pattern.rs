@@ -0,0 +1,473 @@
+//! Matches file names against glob patterns, and expands globs into the paths they match.
+//!
+//! Patterns support `?`, `*`, and `[...]` character classes. With the `extglob` option, they
+//! also support pattern groups, where each group holds alternatives separated by `|`:
+//!
+//! - `?(...)` matches zero or one of the alternatives,
+//! - `*(...)` matches zero or more of them,
+//! - `+(...)` matches one or more of them,
+//! - `@(...)` matches exactly one of them, and
+//! - `!(...)` matches anything except one of them.
+//!
+use std::fs;
+
+use environment::{
+    Environment,
+    Options,
+};
+
+/// A pattern that matches a single file name.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+    case_sensitive: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyString,
+    Class { negated: bool, ranges: Vec<(char, char)> },
+    Group { kind: GroupKind, alternatives: Vec<Vec<Token>> },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GroupKind {
+    ZeroOrOne,
+    ZeroOrMore,
+    OneOrMore,
+    ExactlyOne,
+    Not,
+}
+
+impl Pattern {
+    /// Creates a pattern from its source, using `options` to decide whether pattern groups are
+    /// allowed and whether matching ignores case.
+    ///
+    /// Anything that isn't valid pattern syntax, like an unclosed `[`, matches itself.
+    ///
+    pub fn new(source: &str, options: &Options) -> Pattern {
+        let chars = source.chars().collect::<Vec<_>>();
+        let (tokens, _) = parse_sequence(&chars, 0, false, options.extglob);
+        Pattern { tokens, case_sensitive: !options.nocaseglob }
+    }
+
+    /// Returns whether or not `name` matches this pattern in its entirety.
+    ///
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.chars().collect::<Vec<_>>();
+        matches_tokens(&self.tokens, &name, self.case_sensitive)
+    }
+
+    /// Returns whether or not this pattern starts with a literal `.`, which lets it match the
+    /// names of hidden files.
+    ///
+    fn starts_with_dot(&self) -> bool {
+        self.tokens.first() == Some(&Token::Char('.'))
+    }
+}
+
+/// Returns whether or not `word` contains any characters with a special meaning in patterns.
+///
+pub fn has_pattern(word: &str, options: &Options) -> bool {
+    let chars = word.chars().collect::<Vec<_>>();
+    parse_sequence(&chars, 0, false, options.extglob).0
+        .iter()
+        .any(|token| !matches!(token, Token::Char(_)))
+}
+
+/// Expands `word` into the paths that it matches, sorted by name.
+///
+/// Each component of `word` is matched separately. With the `globstar` option, a `**` component
+/// matches every file and directory beneath the directories before it, or any number of nested
+/// directories if other components follow it. Relative paths are resolved against the
+/// environment's working directory, but are returned relative. Hidden files are only matched by
+/// components that start with a `.`, unless the `dotglob` option is set.
+///
+pub fn expand(word: &str, env: &Environment) -> Vec<String> {
+    let options = env.options();
+    let mut candidates = vec![if word.starts_with('/') { "/".to_owned() } else { String::new() }];
+    let components = word.split('/').filter(|component| !component.is_empty()).collect::<Vec<_>>();
+    for (index, &component) in components.iter().enumerate() {
+        candidates = if component == "**" && options.globstar {
+            let last = index + 1 == components.len();
+            candidates.into_iter()
+                .flat_map(|candidate| {
+                    let mut paths = if last { Vec::new() } else { vec![candidate.clone()] };
+                    descendants(&candidate, env, !last, &mut paths);
+                    paths
+                })
+                .collect()
+        } else if has_pattern(component, options) {
+            let pattern = Pattern::new(component, options);
+            candidates.into_iter()
+                .flat_map(|candidate| {
+                    entries(&candidate, env)
+                        .into_iter()
+                        .filter(|(name, _)| !name.starts_with('.') || options.dotglob || pattern.starts_with_dot())
+                        .filter(|(name, _)| pattern.matches(name))
+                        .map(|(name, _)| join(&candidate, &name))
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        } else {
+            candidates.into_iter().map(|candidate| join(&candidate, &unescape(component))).collect()
+        };
+    }
+
+    let directories_only = word.ends_with('/');
+    let mut paths = candidates.into_iter()
+        .filter_map(|candidate| {
+            let metadata = fs::metadata(env.resolve_path(&candidate)).ok()?;
+            match (directories_only, metadata.is_dir()) {
+                (true, true) if !candidate.ends_with('/') => Some(candidate + "/"),
+                (true, false) => None,
+                _ => Some(candidate),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Returns the names of the entries in a directory, and whether or not each is a directory.
+///
+/// Symbolic links are not followed, so that `**` can't loop forever.
+///
+fn entries(directory: &str, env: &Environment) -> Vec<(String, bool)> {
+    let path = env.resolve_path(if directory.is_empty() { "." } else { directory });
+    let mut entries = match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let is_dir = entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false);
+                entry.file_name().into_string().ok().map(|name| (name, is_dir))
+            })
+            .collect::<Vec<_>>(),
+        Err(_) => Vec::new(),
+    };
+    entries.sort();
+    entries
+}
+
+/// Adds every file nested within `directory` to `acc`, or only the directories if
+/// `directories_only` is set. Hidden files are skipped unless the `dotglob` option is set.
+///
+fn descendants(directory: &str, env: &Environment, directories_only: bool, acc: &mut Vec<String>) {
+    for (name, is_dir) in entries(directory, env) {
+        if name.starts_with('.') && !env.options().dotglob {
+            continue;
+        }
+
+        let path = join(directory, &name);
+        if is_dir || !directories_only {
+            acc.push(path.clone());
+        }
+        if is_dir {
+            descendants(&path, env, directories_only, acc);
+        }
+    }
+}
+
+/// Joins a name onto a path.
+///
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() || path.ends_with('/') {
+        format!("{}{}", path, name)
+    } else {
+        format!("{}/{}", path, name)
+    }
+}
+
+/// Escapes every character in `text` that has a special meaning in patterns, so that the
+/// pattern only matches `text` itself.
+///
+pub fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\?*[]|()!+@".contains(c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Removes the backslashes that escape characters in a component without any patterns.
+///
+fn unescape(component: &str) -> String {
+    let mut result = String::with_capacity(component.len());
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Parses tokens from `chars`, starting at `start`.
+///
+/// Inside a group, parsing stops at a `|` or `)`. Returns the tokens along with the index where
+/// parsing stopped, which is `None` if a group wasn't closed.
+///
+fn parse_sequence(chars: &[char], start: usize, in_group: bool, extglob: bool) -> (Vec<Token>, Option<usize>) {
+    let mut tokens = Vec::new();
+    let mut index = start;
+    while index < chars.len() {
+        let c = chars[index];
+        match c {
+            '|' | ')' if in_group => return (tokens, Some(index)),
+            '?' | '*' | '+' | '@' | '!' if extglob && chars.get(index + 1) == Some(&'(') => {
+                if let Some((token, end)) = parse_group(chars, index, extglob) {
+                    tokens.push(token);
+                    index = end;
+                    continue;
+                }
+                tokens.push(Token::Char(c));
+            },
+            '?' => tokens.push(Token::AnyChar),
+            '*' => tokens.push(Token::AnyString),
+            '[' => match parse_class(chars, index) {
+                Some((token, end)) => {
+                    tokens.push(token);
+                    index = end;
+                    continue;
+                },
+                None => tokens.push(Token::Char(c)),
+            },
+            '\\' if index + 1 < chars.len() => {
+                index += 1;
+                tokens.push(Token::Char(chars[index]));
+            },
+            c => tokens.push(Token::Char(c)),
+        }
+        index += 1;
+    }
+
+    (tokens, if in_group { None } else { Some(index) })
+}
+
+/// Parses a pattern group like `@(a|b)` that starts at `start`, returning it along with the
+/// index just past its closing parenthesis.
+///
+fn parse_group(chars: &[char], start: usize, extglob: bool) -> Option<(Token, usize)> {
+    let kind = match chars[start] {
+        '?' => GroupKind::ZeroOrOne,
+        '*' => GroupKind::ZeroOrMore,
+        '+' => GroupKind::OneOrMore,
+        '@' => GroupKind::ExactlyOne,
+        _ => GroupKind::Not,
+    };
+
+    let mut alternatives = Vec::new();
+    let mut index = start + 2;
+    loop {
+        let (tokens, end) = parse_sequence(chars, index, true, extglob);
+        let end = end?;
+        alternatives.push(tokens);
+        if chars[end] == ')' {
+            return Some((Token::Group { kind, alternatives }, end + 1));
+        }
+        index = end + 1;
+    }
+}
+
+/// Parses a character class like `[a-z]` or `[!0-9]` that starts at `start`, returning it along
+/// with the index just past its closing bracket.
+///
+/// A `]` right after the opening bracket (or the negation) is part of the class.
+///
+fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut index = start + 1;
+    let negated = matches!(chars.get(index), Some('!') | Some('^'));
+    if negated {
+        index += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let first = index;
+    while index < chars.len() {
+        let c = chars[index];
+        if c == ']' && index > first {
+            return Some((Token::Class { negated, ranges }, index + 1));
+        }
+
+        match (chars.get(index + 1), chars.get(index + 2)) {
+            (Some('-'), Some(&end)) if end != ']' => {
+                ranges.push((c, end));
+                index += 3;
+            },
+            _ => {
+                ranges.push((c, c));
+                index += 1;
+            },
+        }
+    }
+
+    None
+}
+
+/// Returns whether or not `tokens` match all of `text`.
+///
+fn matches_tokens(tokens: &[Token], text: &[char], case_sensitive: bool) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return text.is_empty(),
+    };
+
+    match token {
+        Token::Char(c) => match text.first() {
+            Some(t) if same_char(*c, *t, case_sensitive) => matches_tokens(rest, &text[1..], case_sensitive),
+            _ => false,
+        },
+        Token::AnyChar => !text.is_empty() && matches_tokens(rest, &text[1..], case_sensitive),
+        Token::AnyString => (0..=text.len()).any(|index| matches_tokens(rest, &text[index..], case_sensitive)),
+        Token::Class { negated, ranges } => match text.first() {
+            Some(&t) => {
+                let in_class = ranges.iter().any(|&(low, high)| {
+                    in_range(t, low, high) || (!case_sensitive && other_cases(t).any(|t| in_range(t, low, high)))
+                });
+                in_class != *negated && matches_tokens(rest, &text[1..], case_sensitive)
+            },
+            None => false,
+        },
+        Token::Group { kind, alternatives } => (0..=text.len()).any(|index| {
+            matches_group(*kind, alternatives, &text[..index], case_sensitive)
+                && matches_tokens(rest, &text[index..], case_sensitive)
+        }),
+    }
+}
+
+/// Returns whether or not a pattern group matches all of `text`.
+///
+fn matches_group(kind: GroupKind, alternatives: &[Vec<Token>], text: &[char], case_sensitive: bool) -> bool {
+    let any = |text: &[char]| alternatives.iter().any(|tokens| matches_tokens(tokens, text, case_sensitive));
+    match kind {
+        GroupKind::ExactlyOne => any(text),
+        GroupKind::ZeroOrOne => text.is_empty() || any(text),
+        GroupKind::ZeroOrMore if text.is_empty() => true,
+        GroupKind::ZeroOrMore | GroupKind::OneOrMore => (1..=text.len()).any(|index| {
+            any(&text[..index]) && matches_group(GroupKind::ZeroOrMore, alternatives, &text[index..], case_sensitive)
+        }),
+        GroupKind::Not => !any(text),
+    }
+}
+
+fn same_char(a: char, b: char, case_sensitive: bool) -> bool {
+    a == b || (!case_sensitive && a.to_lowercase().eq(b.to_lowercase()))
+}
+
+fn in_range(c: char, low: char, high: char) -> bool {
+    low <= c && c <= high
+}
+
+fn other_cases(c: char) -> impl Iterator<Item = char> {
+    c.to_lowercase().chain(c.to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs::File,
+        path::PathBuf,
+        process,
+    };
+
+    use super::*;
+
+    fn pattern(source: &str, extglob: bool, nocaseglob: bool) -> Pattern {
+        Pattern::new(source, &Options { extglob, nocaseglob, ..Options::default() })
+    }
+
+    #[test]
+    fn test_pattern_matches_wildcards_and_classes() {
+        let p = pattern("[!a-c]?x*.[]rs]", false, false);
+
+        assert!(p.matches("d1x.r"));
+        assert!(p.matches("zzxyz.]"));
+        assert!(!p.matches("a1x.r"));
+        assert!(!p.matches("d1x.o"));
+    }
+
+    #[test]
+    fn test_pattern_matches_groups_with_extglob() {
+        assert!(pattern("!(*.o)", true, false).matches("main.c"));
+        assert!(!pattern("!(*.o)", true, false).matches("main.o"));
+        assert!(pattern("+(ab|c).txt", true, false).matches("abcab.txt"));
+        assert!(!pattern("@(ab|c).txt", true, false).matches("abc.txt"));
+        assert!(pattern("x?(y)z", true, false).matches("xz"));
+        assert!(!pattern("!(*.o)", false, false).matches("main.c"));
+    }
+
+    #[test]
+    fn test_pattern_ignores_case_with_nocaseglob() {
+        assert!(pattern("*.RS", false, true).matches("main.rs"));
+        assert!(pattern("[A-C]*", false, true).matches("cargo"));
+        assert!(!pattern("*.RS", false, false).matches("main.rs"));
+    }
+
+    #[test]
+    fn test_has_pattern_ignores_escaped_characters() {
+        let options = Options::default();
+
+        assert!(has_pattern("src/*.rs", &options));
+        assert!(!has_pattern("what\\?", &options));
+        assert!(!has_pattern("[unclosed", &options));
+    }
+
+    /// Creates a directory for a test with the given files in it, creating any directories they
+    /// need along the way.
+    ///
+    fn fixture(test: &str, files: &[&str]) -> PathBuf {
+        let directory = env::temp_dir().join(format!("gesh-{}-{}", test, process::id()));
+        for file in files {
+            let path = directory.join(file);
+            fs::create_dir_all(path.parent().expect("parent")).expect("create directory");
+            File::create(path).expect("create file");
+        }
+        directory
+    }
+
+    #[test]
+    fn test_expand_matches_every_depth_with_globstar() {
+        let directory = fixture("globstar", &["main.rs", "a/b.rs", "a/c/d.rs", "a/c/e.txt"]);
+        let mut env = Environment::empty();
+        env.set_working_directory(directory.clone());
+        env.options_mut().globstar = true;
+
+        let everything = expand("**", &env);
+        let sources = expand("**/*.rs", &env);
+        env.options_mut().globstar = false;
+        let shallow = expand("**/*.rs", &env);
+        fs::remove_dir_all(&directory).expect("remove directory");
+
+        assert_eq!(vec!["a", "a/b.rs", "a/c", "a/c/d.rs", "a/c/e.txt", "main.rs"], everything);
+        assert_eq!(vec!["a/b.rs", "a/c/d.rs", "main.rs"], sources);
+        assert_eq!(vec!["a/b.rs"], shallow);
+    }
+
+    #[test]
+    fn test_expand_returns_sorted_paths() {
+        let files = ["Cargo.toml", "Cargo.lock", "src/main.rs", "src/prompt/mod.rs", "src/command/builtin/cd.rs"];
+        let directory = fixture("sorted", &files);
+        let mut env = Environment::empty();
+        env.set_working_directory(directory.clone());
+        env.options_mut().globstar = true;
+
+        let directories = expand("src/**/[bp]*/", &env);
+        let manifests = expand("Cargo.*", &env);
+        let nothing = expand("*.nonexistent", &env);
+        fs::remove_dir_all(&directory).expect("remove directory");
+
+        assert_eq!(vec!["src/command/builtin/", "src/prompt/"], directories);
+        assert_eq!(vec!["Cargo.lock", "Cargo.toml"], manifests);
+        assert_eq!(Vec::<String>::new(), nothing);
+    }
+}
@@ -11,9 +11,11 @@ use std::io;
 use std::result;
 
 pub mod rustyline;
+pub mod script;
 
 /// Errors when reading commands
 ///
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum Error {
     /// Generic I/O error when trying to get input from the TTY
@@ -39,9 +41,13 @@ type Result<T> = result::Result<T, Error>;
 
 /// Abstraction for an input prompt
 pub trait Prompt {
+    /// Returns whether or not commands are being typed in by a user.
+    ///
+    fn is_interactive(&self) -> bool;
+
     /// Sets the text displayed for the input prompt.
     ///
-    fn set_prompt(&mut self, String);
+    fn set_prompt(&mut self, prompt: String);
 
     /// Gets the next command from the input
     ///
@@ -23,6 +23,10 @@ impl RustylinePrompt {
 }
 
 impl Prompt for RustylinePrompt {
+    fn is_interactive(&self) -> bool {
+        true
+    }
+
     fn set_prompt(&mut self, prompt: String) {
         self.prompt = prompt;
     }
@@ -0,0 +1,84 @@
+//! A prompt that reads commands from a script, rather than from a user.
+//!
+use super::{
+    Error,
+    Prompt,
+    Result,
+};
+
+use std::{
+    fs::File,
+    io::{
+        BufRead,
+        BufReader,
+        Cursor,
+    },
+    io,
+    path::Path,
+};
+
+/// Reads commands, one line at a time, from a file or string.
+///
+pub struct ScriptPrompt<R: BufRead> {
+    input: R,
+}
+
+impl ScriptPrompt<BufReader<File>> {
+    /// Constructs a prompt that reads commands from the file at `path`.
+    ///
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<ScriptPrompt<BufReader<File>>> {
+        Ok(ScriptPrompt::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl ScriptPrompt<Cursor<String>> {
+    /// Constructs a prompt that reads commands from the given string, like `gesh -c`.
+    ///
+    pub fn from_string(commands: String) -> ScriptPrompt<Cursor<String>> {
+        ScriptPrompt::new(Cursor::new(commands))
+    }
+}
+
+impl<R: BufRead> ScriptPrompt<R> {
+    /// Constructs a prompt that reads commands from `input`.
+    ///
+    pub fn new(input: R) -> ScriptPrompt<R> {
+        ScriptPrompt { input }
+    }
+}
+
+impl<R: BufRead> Prompt for ScriptPrompt<R> {
+    fn is_interactive(&self) -> bool {
+        false
+    }
+
+    fn set_prompt(&mut self, _prompt: String) {
+    }
+
+    fn get(&mut self) -> Result<String> {
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) => Err(Error::Eof()),
+            Ok(_) => {
+                if line.ends_with('\n') {
+                    line.pop();
+                }
+                Ok(line)
+            },
+            Err(err) => Err(Error::Io(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_returns_each_line_then_eof() {
+        let mut prompt = ScriptPrompt::from_string("echo a\necho b".to_owned());
+        assert_eq!("echo a", prompt.get().expect("line"));
+        assert_eq!("echo b", prompt.get().expect("line"));
+        assert!(matches!(prompt.get(), Err(Error::Eof())));
+    }
+}
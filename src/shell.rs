@@ -7,8 +7,8 @@
 //!
 use command::{
     self,
-    Context,
     ExitStatus,
+    Io,
     Registry,
 };
 
@@ -16,23 +16,28 @@ use environment::{
     Environment,
 };
 
-use geshl::{
-    self,
+use eval;
 
-    Command,
-    ParsedLine,
-    SetVariable,
+use jobs::{
+    JobTable,
+    State,
 };
 
+use geshl;
+
 use prompt::{
     self,
     Prompt,
 };
 
-use strings;
+use terminal::Terminal;
 
 use std::{
     env,
+    io::{
+        self,
+        Write,
+    },
 };
 
 /// A user shell.
@@ -40,10 +45,19 @@ use std::{
 pub struct Shell<R: Prompt> {
     pub prompt: R,
     pub parser: geshl::Parser,
+
+    /// The name of the shell or script, available as `$0`.
+    ///
+    pub name: String,
+
+    /// The arguments given to the script, available as `$1`, `$2`, and so on.
+    ///
+    pub args: Vec<String>,
 }
 
 /// Enumeration of all possible errors that can occur in the shell.
 ///
+#[allow(dead_code, clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum Error {
     CommandError(command::Error),
@@ -55,61 +69,137 @@ pub enum Error {
 impl<R: Prompt> Shell<R> {
     /// Runs the shell's main read -> parse -> execute loop.
     ///
+    /// Lines are read until they make up complete statements, showing a continuation prompt
+    /// while more input is needed.
+    ///
+    /// Once the input runs out, returns the status of the last command. If the prompt isn't
+    /// interactive, a syntax error stops the shell with a status of 2, and an unset parameter in
+    /// `${VAR:?message}` stops it with a status of 127.
+    ///
     pub fn run(&mut self) -> Result<ExitStatus, Error> {
         let mut env = Environment::from_existing_env();
-        let registry = Registry::for_env(&env);
+        env.set_positional_parameters(self.name.clone(), self.args.clone());
+
+        let registry = Registry::new();
+        let mut jobs = match self.acquire_terminal() {
+            Some(terminal) => JobTable::with_terminal(terminal),
+            None => JobTable::new(),
+        };
 
+        let mut input = String::new();
         loop {
-            self.prompt.set_prompt(env.working_directory().to_string_lossy().into_owned().to_string() + "$ ");
+            if input.is_empty() {
+                self.notify(&mut jobs);
+                self.prompt.set_prompt(env.working_directory().to_string_lossy().into_owned().to_string() + "$ ");
+            } else {
+                self.prompt.set_prompt("> ".to_owned());
+            }
 
-            let parsed_line = match self.prompt.get() {
-                Ok(raw_line) => self.parser.parse(raw_line)?,
-                Err(prompt::Error::Eof()) => break,
-                Err(prompt::Error::Interrupted()) => continue,
+            match self.prompt.get() {
+                Ok(raw_line) => {
+                    input.push_str(&raw_line);
+                    input.push('\n');
+                },
+                Err(prompt::Error::Eof()) if input.is_empty() => break,
+                Err(prompt::Error::Eof()) => {
+                    input.clear();
+                    if let Some(status) = self.syntax_error("unexpected end of file") {
+                        return Ok(status);
+                    }
+                    env.set_last_status(2);
+                    continue;
+                },
+                Err(prompt::Error::Interrupted()) => {
+                    input.clear();
+                    continue;
+                },
                 Err(err) => return Err(Error::PromptError(err)),
-            };
+            }
 
-            match parsed_line {
-                ParsedLine::Command(Command { vars, args: pieces }) => {
-                    // First, process the pieces
-                    let mut args = strings::to_string_vec(pieces.into_iter(), &env);
-                    if args.is_empty() {
-                        continue
+            let statements = match self.parser.parse(&input) {
+                Ok(statements) => statements,
+                Err(geshl::Error::Incomplete) => continue,
+                Err(geshl::Error::Invalid) => {
+                    input.clear();
+                    if let Some(status) = self.syntax_error("invalid input") {
+                        return Ok(status);
                     }
-
-                    let cmd = args.remove(0);
-
-                    // If there are variables, we need to create a temporary environment with
-                    // the new vars. Otherwise we can just use the current one.
-                    let result = if vars.is_empty() {
-                        registry.execute(&cmd, Context { env: &mut env, args, registry: &registry })
-                    } else {
-                        let mut temp_env = env.clone();
-                        for SetVariable { name, value } in vars {
-                            let interpolated_value = strings::shellstring_to_string(&value, &temp_env);
-                            temp_env.set(name.clone(), interpolated_value);
-                            temp_env.export(name);
+                    env.set_last_status(2);
+                    continue;
+                },
+            };
+            input.clear();
+
+            for statement in statements {
+                // Aliases are expanded as each statement runs, so that they can be used by the
+                // statements that follow their definition
+                let statement = match registry.expand_aliases(statement) {
+                    Ok(statement) => statement,
+                    Err(_) => {
+                        if let Some(status) = self.syntax_error("invalid alias") {
+                            return Ok(status);
                         }
+                        env.set_last_status(2);
+                        continue;
+                    },
+                };
+
+                let io = Io::inherit().map_err(command::Error::from)?;
+                let status = match eval::evaluate(statement, &mut env, &registry, &mut jobs, io) {
+                    Ok(ExitStatus::ExitWith(code)) => return Ok(ExitStatus::ExitWith(code)),
+                    Ok(ExitStatus::Success(code)) | Ok(ExitStatus::Return(code)) => code,
+                    Ok(ExitStatus::Break(_)) | Ok(ExitStatus::Continue(_)) => 0,
+                    Err(command::Error::UnsetParameter) if !self.prompt.is_interactive() => {
+                        return Ok(ExitStatus::ExitWith(127));
+                    },
+                    Err(_) => 1,
+                };
+                env.set_last_status(status);
+            }
+        }
+        Ok(ExitStatus::Success(env.last_status()))
+    }
 
-                        registry.execute(&cmd, Context { env: &mut temp_env, args, registry: &registry })
-                    };
+    /// Reports a syntax error.
+    ///
+    /// An interactive shell carries on with the next input, but otherwise the shell has to stop,
+    /// so the status to stop with is returned.
+    ///
+    fn syntax_error(&self, message: &str) -> Option<ExitStatus> {
+        eprintln!("gesh: syntax error: {}", message);
+        if self.prompt.is_interactive() {
+            None
+        } else {
+            Some(ExitStatus::Success(2))
+        }
+    }
 
-                    if let Ok(ExitStatus::ExitWith(code)) = result {
-                        return Ok(ExitStatus::ExitWith(code));
-                    };
-                },
+    /// Takes control of the terminal for job control, if the shell is interactive.
+    ///
+    fn acquire_terminal(&self) -> Option<Terminal> {
+        if self.prompt.is_interactive() {
+            Terminal::acquire().ok().and_then(|terminal| terminal)
+        } else {
+            None
+        }
+    }
 
-                ParsedLine::SetVariables(vars) => {
-                    for SetVariable { name, value } in vars {
-                        let interpolated_value = strings::shellstring_to_string(&value, &env);
-                        env.set(name, interpolated_value);
-                    }
-                },
+    /// Reports background jobs that have finished since the last prompt, if the shell is
+    /// interactive.
+    ///
+    fn notify(&self, jobs: &mut JobTable) {
+        if jobs.is_empty() || jobs.update().is_err() {
+            return;
+        }
 
-                ParsedLine::Empty => continue,
+        if self.prompt.is_interactive() {
+            let stderr = io::stderr();
+            let mut stderr = stderr.lock();
+            for job in jobs.iter().filter(|job| matches!(job.state(), State::Done(_))) {
+                let _ = writeln!(stderr, "{}", jobs.describe(job));
             }
         }
-        Ok(ExitStatus::Success(0))
+        jobs.remove_finished();
     }
 }
 
@@ -136,3 +226,29 @@ impl From<prompt::Error> for Error {
         Error::PromptError(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use prompt::script::ScriptPrompt;
+
+    use super::*;
+
+    /// Runs `script` in a shell that isn't interactive.
+    ///
+    fn run(script: &str) -> Result<ExitStatus, Error> {
+        let mut shell = Shell {
+            prompt: ScriptPrompt::from_string(script.to_owned()),
+            parser: geshl::Parser::new(),
+            name: "gesh".to_owned(),
+            args: Vec::new(),
+        };
+        shell.run()
+    }
+
+    #[test]
+    fn test_run_stops_script_when_parameter_is_unset() {
+        let status = run("GESH_TEST=${GESH_TEST_UNSET:?missing} && exit 3\nexit 4").expect("run");
+
+        assert_eq!(ExitStatus::ExitWith(127), status);
+    }
+}
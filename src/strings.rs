@@ -1,60 +1,258 @@
 //! Provides string types that can be interpolated within an environment.
 //!
-use glob;
-
 use geshl::{
+    Brace,
+    Expansion,
+    ExpansionOperator,
     Piece,
     ShellString,
 };
 
 use super::{
-    environment::Environment,
+    arithmetic,
+    command::Registry,
+    environment::{
+        self,
+        Environment,
+        Options,
+    },
+    eval,
+    pattern,
+};
+
+use std::{
+    fmt,
+    io,
+    result,
 };
 
+/// Result type for expanding strings.
+///
+pub type Result<T> = result::Result<T, Error>;
+
+/// The error type for expanding strings.
+///
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// A variable was unset in `${VAR?message}`, or null in `${VAR:?message}`.
+    ///
+    Unset { name: String, message: String },
+
+    /// An arithmetic expansion couldn't be evaluated.
+    ///
+    Arithmetic(arithmetic::Error),
+
+    /// A glob didn't match any files, and the `failglob` option is set.
+    ///
+    NoMatch(String),
+
+    /// A command substitution couldn't be run, or its output couldn't be read.
+    ///
+    Substitution(io::ErrorKind),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Unset { name, message } if message.is_empty() => write!(f, "{}: parameter null or not set", name),
+            Error::Unset { name, message } => write!(f, "{}: {}", name, message),
+            Error::Arithmetic(err) => write!(f, "{}", err),
+            Error::NoMatch(pattern) => write!(f, "no match: {}", pattern),
+            Error::Substitution(kind) => write!(f, "command substitution: {}", io::Error::from(*kind)),
+        }
+    }
+}
+
 /// Converts a list of `ShellString`s to a list of `String`s.
 ///
-pub fn to_string_vec<V>(values: V, env: &Environment) -> Vec<String>
+/// Braces are expanded first, and then the output of unquoted command substitutions is split
+/// into separate words, so a single `ShellString` may produce any number of `String`s. Words
+/// with globs are replaced by the paths they match, with the shell's options deciding what
+/// happens to a glob that matches nothing.
+///
+pub fn to_string_vec<V>(values: V, env: &mut Environment, registry: &Registry) -> Result<Vec<String>>
     where V: Iterator<Item = ShellString>
 {
-    values.fold(Vec::new(), |mut acc, string| {
+    let mut acc = Vec::new();
+    for string in values.flat_map(|string| expand_braces(&string)) {
+        let words = split_words(&string, env, registry)?;
         if has_glob(&string) {
-            let paths = glob::glob_with(
-                &shellstring_to_string(&string, env),
-                &glob::MatchOptions {
-                    case_sensitive: true,
-                    require_literal_separator: true,
-                    require_literal_leading_dot: true,
+            for word in words {
+                let paths = pattern::expand(&word.pattern, env);
+                if !paths.is_empty() {
+                    acc.extend(paths);
+                } else if env.options().failglob {
+                    return Err(Error::NoMatch(word.text));
+                } else if !env.options().nullglob {
+                    acc.push(word.text);
                 }
-            );
-
-            if paths.is_err() {
-                return Vec::new();
             }
-
-            acc.extend(
-                paths.unwrap()
-                    .filter_map(|path| path.ok())
-                    .filter_map(|path| path.into_os_string().into_string().ok())
-                    .collect::<Vec<_>>()
-            )
         } else {
-            acc.push(shellstring_to_string(&string, env));
+            acc.extend(words.into_iter().map(|word| word.text));
         }
+    }
 
-        acc
-    })
+    Ok(acc)
 }
 
-/// Returns whether or not there is a glob component in this `ShellString`
+/// Expands the braces in a shell string, producing a separate string for every combination of
+/// their alternatives.
 ///
-fn has_glob(string: &ShellString) -> bool {
-    string.iter().any(|v| {
-        if let Piece::Glob(_) = v {
-            true
+/// Alternatives are expanded recursively, so `a{b,c{d,e}}` produces `ab`, `acd` and `ace`.
+///
+fn expand_braces(string: &ShellString) -> Vec<ShellString> {
+    let mut acc = vec![ShellString::from(Vec::new())];
+    for piece in string.iter() {
+        let alternatives = match piece {
+            Piece::Brace(Brace::Alternatives(items)) => items.iter().flat_map(expand_braces).collect(),
+            Piece::Brace(Brace::Sequence { start, end, step }) => {
+                sequence(start, end, *step).into_iter().map(ShellString::from).collect()
+            },
+            piece => vec![ShellString::from(piece.clone())],
+        };
+
+        acc = acc.into_iter()
+            .flat_map(|prefix| alternatives.iter().map(move |alternative| prefix.clone() + alternative.clone()))
+            .collect();
+    }
+    acc
+}
+
+/// Returns the values in a sequence brace expansion, from `start` to `end` inclusive.
+///
+/// The endpoints are either both integers or both letters. Integers starting with a zero pad
+/// every value to the width of the longest endpoint. The sign of `step` is ignored, since the
+/// direction comes from the endpoints.
+///
+fn sequence(start: &str, end: &str, step: Option<i64>) -> Vec<String> {
+    let step = step.map_or(1, |step| step.unsigned_abs().max(1)) as i128;
+    let range = |start: i128, end: i128| {
+        let count = (end - start).abs() / step;
+        let direction = if start <= end { step } else { -step };
+        (0..=count).map(move |index| start + index * direction)
+    };
+
+    match (start.parse::<i64>(), end.parse::<i64>()) {
+        (Ok(first), Ok(last)) => {
+            let padded = |n: &str| n.trim_start_matches('-').len() > 1 && n.trim_start_matches('-').starts_with('0');
+            let width = if padded(start) || padded(end) { start.len().max(end.len()) } else { 0 };
+            range(first.into(), last.into()).map(|n| format!("{:0width$}", n, width = width)).collect()
+        },
+        _ => {
+            let letter = |s: &str| s.bytes().next().unwrap_or(b'a') as i128;
+            range(letter(start), letter(end)).map(|c| (c as u8 as char).to_string()).collect()
+        },
+    }
+}
+
+/// The field separators used when `IFS` is unset.
+///
+const DEFAULT_IFS: &str = " \t\n";
+
+/// A word produced by splitting a shell string.
+///
+#[derive(Debug, Default, PartialEq)]
+struct Word {
+    /// The text of the word.
+    ///
+    text: String,
+
+    /// The pattern that the word is matched against if it has a glob, where only globs and
+    /// the values of unquoted expansions have a special meaning.
+    ///
+    pattern: String,
+}
+
+impl Word {
+    /// Adds text that only matches itself.
+    ///
+    fn push_literal(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.pattern.push_str(&pattern::escape(text));
+    }
+
+    /// Adds text that keeps any special meaning it has in patterns.
+    ///
+    fn push_pattern(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.pattern.push_str(text);
+    }
+}
+
+/// Converts this shell string into words, splitting the values of unquoted variables,
+/// parameter expansions and command substitutions on the characters in `IFS`.
+///
+/// `"$@"` produces a separate word for each argument, joining the first and last to any text
+/// around them. A string that is nothing but an unquoted expansion with an empty value, or
+/// `"$@"` without any arguments, produces no words at all.
+///
+fn split_words(string: &ShellString, env: &mut Environment, registry: &Registry) -> Result<Vec<Word>> {
+    let ifs = env.get(&"IFS".to_owned()).unwrap_or_else(|| DEFAULT_IFS.to_owned());
+    let mut words = Vec::new();
+    let mut current: Option<Word> = None;
+    for piece in string.iter() {
+        match piece {
+            Piece::Variable { name, quoted } if name == "@" || (name == "*" && !quoted) => {
+                for (index, argument) in env.arguments().iter().enumerate() {
+                    if index > 0 {
+                        words.extend(current.take());
+                    }
+
+                    if *quoted {
+                        current.get_or_insert_with(Word::default).push_literal(argument);
+                    } else {
+                        split_fields(argument, &ifs, &mut current, &mut words);
+                    }
+                }
+            },
+            Piece::Variable { quoted: false, .. }
+            | Piece::Expansion { quoted: false, .. }
+            | Piece::CommandSubstitution { quoted: false, .. } => {
+                let value = piece_to_string(piece, env, registry)?;
+                split_fields(&value, &ifs, &mut current, &mut words);
+            },
+            Piece::Glob(glob) => current.get_or_insert_with(Word::default).push_pattern(glob),
+            piece => current.get_or_insert_with(Word::default).push_literal(&piece_to_string(piece, env, registry)?),
+        }
+    }
+
+    words.extend(current);
+    Ok(words)
+}
+
+/// Splits `value` into fields on the characters in `ifs`, continuing the word in `current` and
+/// adding each word that's finished to `words`.
+///
+/// Runs of whitespace separators count as a single separator, and are ignored at the start and
+/// end of `value`. Every other separator ends a field, even an empty one, along with any
+/// whitespace separators around it.
+///
+fn split_fields(value: &str, ifs: &str, current: &mut Option<Word>, words: &mut Vec<Word>) {
+    let mut ended_by_whitespace = false;
+    for c in value.chars() {
+        if !ifs.contains(c) {
+            current.get_or_insert_with(Word::default).push_pattern(c.encode_utf8(&mut [0; 4]));
+            ended_by_whitespace = false;
+        } else if c.is_whitespace() {
+            if let Some(word) = current.take() {
+                words.push(word);
+                ended_by_whitespace = true;
+            }
         } else {
-            false
+            match current.take() {
+                Some(word) => words.push(word),
+                None if !ended_by_whitespace => words.push(Word::default()),
+                None => (),
+            }
+            ended_by_whitespace = false;
         }
-    })
+    }
+}
+
+/// Returns whether or not there is a glob component in this `ShellString`
+///
+fn has_glob(string: &ShellString) -> bool {
+    string.iter().any(|v| matches!(v, Piece::Glob(_)))
 }
 
 /// Converts this shell string into a regular string.
@@ -62,21 +260,169 @@ fn has_glob(string: &ShellString) -> bool {
 /// Path and variable interpolations are done via the given `Environment`, as specified
 /// in `Piece::to_string`.
 ///
-pub fn shellstring_to_string(string: &ShellString, env: &Environment) -> String {
-    string.iter().map(|piece| piece_to_string(piece, env)).collect()
+pub fn shellstring_to_string(string: &ShellString, env: &mut Environment, registry: &Registry) -> Result<String> {
+    string.iter().map(|piece| piece_to_string(piece, env, registry)).collect()
 }
 
 /// Converts this piece into a `String` with a given environment.
 ///
 /// If the variable referenced in `Piece::Variable` isn't in the environment, it will be
-/// substituted with an empty string.
+/// substituted with an empty string. Command substitutions are run with `registry`, and their
+/// exit status becomes `$?`. Process substitutions are only replaced in a command's arguments,
+/// before they're expanded, so any others are kept as written.
 ///
-fn piece_to_string(piece: &Piece, env: &Environment) -> String {
-    match &piece {
+fn piece_to_string(piece: &Piece, env: &mut Environment, registry: &Registry) -> Result<String> {
+    Ok(match &piece {
         Piece::Fixed(ref s) => s.clone(),
         Piece::Glob(ref s) => s.clone(),
-        Piece::Variable(ref name) => env.get(&name).unwrap_or_else(|| "".to_owned()),
+        Piece::Tilde(ref prefix) => tilde(prefix, env).unwrap_or_else(|| format!("~{}", prefix)),
+        Piece::Brace(Brace::Alternatives(ref items)) => {
+            let items = items.iter()
+                .map(|item| shellstring_to_string(item, env, registry))
+                .collect::<Result<Vec<_>>>()?;
+            format!("{{{}}}", items.join(","))
+        },
+        Piece::Brace(ref brace) => brace.to_string(),
+        Piece::Variable { ref name, .. } if name == "*" => {
+            let ifs = env.get(&"IFS".to_owned()).unwrap_or_else(|| DEFAULT_IFS.to_owned());
+            env.arguments().join(&ifs.chars().take(1).collect::<String>())
+        },
+        Piece::Variable { ref name, .. } => env.get(name).unwrap_or_else(|| "".to_owned()),
+        Piece::Expansion { ref expansion, .. } => expand(expansion, env, registry)?,
+        Piece::CommandSubstitution { ref program, .. } => {
+            let (output, status) = eval::substitute(program, env, registry).map_err(|err| Error::Substitution(err.kind()))?;
+            env.set_substitution_status(status);
+            output
+        },
+        Piece::ProcessSubstitution { .. } => piece.to_string(),
+        Piece::Arithmetic(ref expression) => arithmetic::evaluate(expression, env).map_err(Error::Arithmetic)?.to_string(),
+    })
+}
+
+/// Returns the directory that a tilde prefix refers to, or `None` if there is no such directory,
+/// in which case the prefix is left as it is.
+///
+fn tilde(prefix: &str, env: &Environment) -> Option<String> {
+    let path = match prefix {
+        "" => return env.get(&"HOME".to_owned()),
+        "+" => env.working_directory().clone(),
+        "-" => return env.get(&"OLDPWD".to_owned()),
+        _ => {
+            let (from_end, digits) = match prefix.chars().next() {
+                Some('+') => (false, &prefix[1..]),
+                Some('-') => (true, &prefix[1..]),
+                _ => (false, prefix),
+            };
+
+            match digits.parse::<usize>() {
+                Ok(n) => env.directory_stack_entry(n, from_end)?.clone(),
+                Err(_) => environment::home_directory_of(prefix)?,
+            }
+        },
+    };
+    Some(path.to_string_lossy().into_owned())
+}
+
+/// Applies the operator of a parameter expansion to the value of its variable.
+///
+/// Words are only expanded when they're used, so `${VAR:-$(slow)}` doesn't run `slow` when
+/// `VAR` is set.
+///
+fn expand(Expansion { name, operator }: &Expansion, env: &mut Environment, registry: &Registry) -> Result<String> {
+    let value = env.get(name);
+    let is_unset = |if_null: &bool| value.as_ref().is_none_or(|value| *if_null && value.is_empty());
+
+    let value = match operator {
+        ExpansionOperator::Length => value.map_or(0, |value| value.chars().count()).to_string(),
+        ExpansionOperator::Default { word, if_null } if is_unset(if_null) => shellstring_to_string(word, env, registry)?,
+        ExpansionOperator::Assign { word, if_null } if is_unset(if_null) => {
+            let word = shellstring_to_string(word, env, registry)?;
+            env.set(name.clone(), word.clone());
+            word
+        },
+        ExpansionOperator::Error { word, if_null } if is_unset(if_null) => {
+            let message = shellstring_to_string(word, env, registry)?;
+            return Err(Error::Unset { name: name.clone(), message });
+        },
+        ExpansionOperator::RemovePrefix { pattern, longest } => {
+            let value = value.unwrap_or_default();
+            let pattern = glob_pattern(&shellstring_to_string(pattern, env, registry)?, env);
+            let mut ends = boundaries(&value);
+            if *longest {
+                ends.reverse();
+            }
+            match ends.into_iter().find(|&end| pattern.matches(&value[..end])) {
+                Some(end) => value[end..].to_owned(),
+                None => value,
+            }
+        },
+        ExpansionOperator::RemoveSuffix { pattern, longest } => {
+            let value = value.unwrap_or_default();
+            let pattern = glob_pattern(&shellstring_to_string(pattern, env, registry)?, env);
+            let mut starts = boundaries(&value);
+            if !*longest {
+                starts.reverse();
+            }
+            match starts.into_iter().find(|&start| pattern.matches(&value[start..])) {
+                Some(start) => value[..start].to_owned(),
+                None => value,
+            }
+        },
+        ExpansionOperator::Replace { pattern, replacement, all } => {
+            let value = value.unwrap_or_default();
+            let pattern = glob_pattern(&shellstring_to_string(pattern, env, registry)?, env);
+            let replacement = shellstring_to_string(replacement, env, registry)?;
+            replace(&value, &pattern, &replacement, *all)
+        },
+        _ => value.unwrap_or_default(),
+    };
+    Ok(value)
+}
+
+/// Creates a pattern for matching within parameter expansions.
+///
+/// Invalid patterns, like `[abc`, match themselves. Pattern groups are allowed with the
+/// `extglob` option, but matching is always case sensitive.
+///
+fn glob_pattern(pattern: &str, env: &Environment) -> pattern::Pattern {
+    let options = Options { extglob: env.options().extglob, ..Options::default() };
+    pattern::Pattern::new(pattern, &options)
+}
+
+/// Returns the byte offsets of every character boundary in `value`, including both ends.
+///
+fn boundaries(value: &str) -> Vec<usize> {
+    value.char_indices().map(|(index, _)| index).chain(Some(value.len())).collect()
+}
+
+/// Replaces the first, or every, longest non-empty match of `pattern` in `value`.
+///
+fn replace(value: &str, pattern: &pattern::Pattern, replacement: &str, all: bool) -> String {
+    let boundaries = boundaries(value);
+    let mut result = String::new();
+    let mut start = 0;
+    let mut replaced = false;
+    while start < value.len() {
+        let end = if replaced && !all {
+            None
+        } else {
+            boundaries.iter().rev().take_while(|&&end| end > start).find(|&&end| pattern.matches(&value[start..end]))
+        };
+
+        match end {
+            Some(&end) => {
+                result.push_str(replacement);
+                start = end;
+                replaced = true;
+            },
+            None => {
+                let c = value[start..].chars().next().expect("start is a char boundary");
+                result.push(c);
+                start += c.len_utf8();
+            },
+        }
     }
+    result
 }
 
 #[cfg(test)]
@@ -85,7 +431,12 @@ mod tests {
         collections::HashMap,
         env,
         ffi::OsStr,
+        fs::{
+            self,
+            File,
+        },
         path::PathBuf,
+        process,
     };
 
     use super::*;
@@ -94,10 +445,10 @@ mod tests {
     fn test_has_glob_false_with_no_globs() {
         let string = ShellString::from(vec![
             Piece::from(" is a "),
-            Piece::Variable("WHAT".to_owned()),
+            Piece::Variable { name: "WHAT".to_owned(), quoted: true },
         ]);
 
-        assert_eq!(false, has_glob(&string));
+        assert!(!has_glob(&string));
     }
 
     #[test]
@@ -105,37 +456,39 @@ mod tests {
         let string = ShellString::from(vec![
             Piece::from(" is a "),
             Piece::Glob("*".to_owned()),
-            Piece::Variable("WHAT".to_owned()),
+            Piece::Variable { name: "WHAT".to_owned(), quoted: true },
         ]);
 
-        assert_eq!(true, has_glob(&string));
+        assert!(has_glob(&string));
     }
 
     #[test]
     fn test_to_string_returns_string_when_var_exists() {
         let shell_string = ShellString::from(vec![
             Piece::from("this is a "),
-            Piece::Variable("WHAT".to_owned()),
+            Piece::Variable { name: "WHAT".to_owned(), quoted: true },
         ]);
 
         let mut vars = HashMap::new();
         vars.insert("WHAT".to_owned(), "test".to_owned());
 
-        let env = Environment::new(vars);
+        let mut env = Environment::new(vars);
+        let registry = Registry::new();
 
-        assert_eq!("this is a test".to_owned(), shellstring_to_string(&shell_string, &env));
+        assert_eq!(Ok("this is a test".to_owned()), shellstring_to_string(&shell_string, &mut env, &registry));
     }
 
     #[test]
     fn test_to_string_returns_empty_string_when_var_doesnt_exist() {
         let shell_string = ShellString::from(vec![
             Piece::from("this is a "),
-            Piece::Variable("WHAT".to_owned()),
+            Piece::Variable { name: "WHAT".to_owned(), quoted: true },
         ]);
 
-        let env = Environment::new(HashMap::new());
+        let mut env = Environment::new(HashMap::new());
+        let registry = Registry::new();
 
-        assert_eq!("this is a ".to_owned(), shellstring_to_string(&shell_string, &env));
+        assert_eq!(Ok("this is a ".to_owned()), shellstring_to_string(&shell_string, &mut env, &registry));
     }
 
     #[test]
@@ -143,7 +496,7 @@ mod tests {
         let shell_strings = vec![
             ShellString::from(vec![
                 Piece::from("this is a "),
-                Piece::Variable("WHAT".to_owned()),
+                Piece::Variable { name: "WHAT".to_owned(), quoted: true },
             ]),
             ShellString::from("another"),
         ];
@@ -151,11 +504,12 @@ mod tests {
         let mut vars = HashMap::new();
         vars.insert("WHAT".to_owned(), "test".to_owned());
 
-        let env = Environment::new(vars);
+        let mut env = Environment::new(vars);
+        let registry = Registry::new();
 
         assert_eq!(
-            vec!["this is a test".to_owned(), "another".to_owned()],
-            to_string_vec(shell_strings.into_iter(), &env)
+            Ok(vec!["this is a test".to_owned(), "another".to_owned()]),
+            to_string_vec(shell_strings.into_iter(), &mut env, &registry)
         );
     }
 
@@ -167,8 +521,9 @@ mod tests {
 
         let mut env = Environment::from_existing_env();
         env.set_working_directory(project_root());
+        let registry = Registry::new();
 
-        let mut actual = to_string_vec(shell_strings.into_iter(), &env);
+        let mut actual = to_string_vec(shell_strings.into_iter(), &mut env, &registry).expect("expand");
         actual.sort_unstable();
 
         assert_eq!(
@@ -177,23 +532,243 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_to_string_vec_handles_globs_without_matches() {
+        let shell_strings = || vec![
+            ShellString::from("before"),
+            ShellString::from(vec![Piece::from("none"), Piece::Glob("*".to_owned())]),
+            ShellString::from(vec![Piece::Glob("[".to_owned()), Piece::from("x")]),
+        ].into_iter();
+
+        let directory = env::temp_dir().join(format!("gesh-strings-empty-{}", process::id()));
+        fs::create_dir_all(&directory).expect("create directory");
+        let mut env = Environment::from_existing_env();
+        env.set_working_directory(directory.clone());
+        let registry = Registry::new();
+
+        assert_eq!(
+            Ok(vec!["before".to_owned(), "none*".to_owned(), "[x".to_owned()]),
+            to_string_vec(shell_strings(), &mut env, &registry)
+        );
+
+        env.options_mut().nullglob = true;
+        assert_eq!(Ok(vec!["before".to_owned()]), to_string_vec(shell_strings(), &mut env, &registry));
+
+        env.options_mut().failglob = true;
+        assert_eq!(Err(Error::NoMatch("none*".to_owned())), to_string_vec(shell_strings(), &mut env, &registry));
+
+        fs::remove_dir(&directory).expect("remove directory");
+    }
+
+    #[test]
+    fn test_to_string_vec_keeps_quoted_text_literal_in_globs() {
+        let directory = env::temp_dir().join(format!("gesh-strings-literal-{}", process::id()));
+        fs::create_dir_all(&directory).expect("create directory");
+        for name in &["a*b", "axxb", "[x]1", "x1"] {
+            File::create(directory.join(name)).expect("create file");
+        }
+
+        let shell_strings = vec![
+            ShellString::from(vec![Piece::from("a*"), Piece::Glob("?".to_owned())]),
+            ShellString::from(vec![Piece::from("[x]"), Piece::Glob("*".to_owned())]),
+        ];
+
+        let mut env = Environment::new(HashMap::new());
+        env.set_working_directory(directory.clone());
+        let registry = Registry::new();
+        let actual = to_string_vec(shell_strings.into_iter(), &mut env, &registry);
+        fs::remove_dir_all(&directory).expect("remove directory");
+
+        assert_eq!(Ok(vec!["a*b".to_owned(), "[x]1".to_owned()]), actual);
+    }
+
+    #[test]
+    fn test_to_string_expands_tilde_prefixes() {
+        let mut env = Environment::new(HashMap::new());
+        env.set("HOME".to_owned(), "/home/me".to_owned());
+        env.set_working_directory(PathBuf::from("/old"));
+        env.set_working_directory(PathBuf::from("/cwd"));
+        env.push_directory(PathBuf::from("/first"));
+        env.push_directory(PathBuf::from("/second"));
+        let registry = Registry::new();
+
+        let mut expand = |prefix: &str| {
+            let string = ShellString::from(vec![Piece::Tilde(prefix.to_owned()), Piece::from("/x")]);
+            shellstring_to_string(&string, &mut env, &registry).expect("expand")
+        };
+
+        assert_eq!("/home/me/x", expand(""));
+        assert_eq!("/cwd/x", expand("+"));
+        assert_eq!("/old/x", expand("-"));
+        assert_eq!("/second/x", expand("1"));
+        assert_eq!("/first/x", expand("-0"));
+        assert_eq!("~+3/x", expand("+3"));
+        if let Some(home) = environment::home_directory_of("root") {
+            assert_eq!(format!("{}/x", home.display()), expand("root"));
+        }
+        assert_eq!("~no-such-user/x", expand("no-such-user"));
+    }
+
     #[test]
     fn test_adding_shellstrings_concatenates() {
         let string1 = ShellString::from("this");
         let string2 = ShellString::from(vec![
             Piece::from(" is a "),
-            Piece::Variable("WHAT".to_owned()),
+            Piece::Variable { name: "WHAT".to_owned(), quoted: true },
         ]);
 
         let expected = ShellString::from(vec![
             Piece::from("this"),
             Piece::from(" is a "),
-            Piece::Variable("WHAT".to_owned()),
+            Piece::Variable { name: "WHAT".to_owned(), quoted: true },
         ]);
 
         assert_eq!(expected, string1 + string2);
     }
 
+    #[test]
+    fn test_to_string_vec_splits_unquoted_command_substitutions() {
+        let substitution = |quoted| Piece::CommandSubstitution {
+            program: geshl::Parser::new().parse("printf \" a  b\\nc\\n\\n\"").expect("should parse"),
+            quoted,
+        };
+        let shell_strings = vec![
+            ShellString::from(vec![Piece::from("x"), substitution(false), Piece::from("y")]),
+            ShellString::from(vec![Piece::from("x"), substitution(true), Piece::from("y")]),
+            ShellString::from(Piece::CommandSubstitution { program: Vec::new(), quoted: false }),
+        ];
+
+        let mut env = Environment::from_existing_env();
+        let registry = Registry::new();
+
+        assert_eq!(
+            Ok(vec!["x".to_owned(), "a".to_owned(), "b".to_owned(), "cy".to_owned(), "x a  b\ncy".to_owned()]),
+            to_string_vec(shell_strings.into_iter(), &mut env, &registry)
+        );
+    }
+
+    #[test]
+    fn test_to_string_vec_splits_unquoted_variables_on_ifs() {
+        let words = |source: &str, env: &mut Environment| {
+            let shell_strings = geshl::Parser::new()
+                .parse(&format!("echo {}\n", source))
+                .expect("should parse")
+                .into_iter()
+                .flat_map(|line| match line {
+                    geshl::ParsedLine::Command(command) => command.args,
+                    _ => Vec::new(),
+                });
+            let registry = Registry::new();
+            to_string_vec(shell_strings, env, &registry).expect("expand")
+        };
+
+        let mut env = Environment::new(HashMap::new());
+        env.set("x".to_owned(), " a  b ".to_owned());
+        env.set("y".to_owned(), "c::d:".to_owned());
+
+        assert_eq!(vec!["echo", "a", "b", " a  b ", "c::d:"], words("$x \"$x\" $y", &mut env));
+        assert_eq!(vec!["echo", "", "a-b"], words("\"\" $empty a-${x/ a  b /b}", &mut env));
+
+        env.set("IFS".to_owned(), ": ".to_owned());
+        assert_eq!(vec!["echo", "c", "", "d", "a", "b"], words("$y$x", &mut env));
+
+        env.set("IFS".to_owned(), "".to_owned());
+        assert_eq!(vec!["echo", " a  b "], words("$x", &mut env));
+    }
+
+    #[test]
+    fn test_to_string_vec_keeps_quoted_arguments_separate() {
+        let mut env = Environment::new(HashMap::new());
+        env.replace_arguments(vec!["a b".to_owned(), "c".to_owned()]);
+        let registry = Registry::new();
+
+        let all = |name: &str, quoted| ShellString::from(vec![
+            Piece::from("<"),
+            Piece::Variable { name: name.to_owned(), quoted },
+            Piece::from(">"),
+        ]);
+        let shell_strings = vec![all("@", true), all("*", true), all("@", false)];
+
+        assert_eq!(
+            Ok(vec!["<a b", "c>", "<a b c>", "<a", "b", "c>"].into_iter().map(String::from).collect()),
+            to_string_vec(shell_strings.into_iter(), &mut env, &registry)
+        );
+
+        env.replace_arguments(Vec::new());
+        assert_eq!(
+            Ok(Vec::new()),
+            to_string_vec(vec![ShellString::from(Piece::Variable { name: "@".to_owned(), quoted: true })].into_iter(), &mut env, &registry)
+        );
+    }
+
+    #[test]
+    fn test_to_string_vec_expands_braces() {
+        let shell_strings = geshl::Parser::new()
+            .parse("echo file.{rs,b{ak,in}} {1..3}x\n")
+            .expect("should parse")
+            .into_iter()
+            .flat_map(|line| match line {
+                geshl::ParsedLine::Command(command) => command.args,
+                _ => Vec::new(),
+            });
+
+        let mut env = Environment::new(HashMap::new());
+        let registry = Registry::new();
+
+        assert_eq!(
+            Ok(vec!["echo", "file.rs", "file.bak", "file.bin", "1x", "2x", "3x"].into_iter().map(String::from).collect()),
+            to_string_vec(shell_strings, &mut env, &registry)
+        );
+    }
+
+    #[test]
+    fn test_sequence_counts_in_either_direction() {
+        assert_eq!(vec!["1", "4", "7", "10"], sequence("1", "10", Some(-3)));
+        assert_eq!(vec!["2", "1", "0", "-1"], sequence("2", "-1", None));
+        assert_eq!(vec!["08", "09", "10"], sequence("08", "10", None));
+        assert_eq!(vec!["-05", "-03", "-01"], sequence("-5", "-01", Some(2)));
+        assert_eq!(vec!["e", "c", "a"], sequence("e", "a", Some(2)));
+    }
+
+    #[test]
+    fn test_to_string_applies_expansion_operators() {
+        let mut env = Environment::new(HashMap::new());
+        env.set("FILE".to_owned(), "/tmp/archive.tar.gz".to_owned());
+        env.set("EMPTY".to_owned(), "".to_owned());
+        let registry = Registry::new();
+
+        let mut expand = |name: &str, operator| {
+            let string = ShellString::from(Piece::Expansion {
+                expansion: Expansion { name: name.to_owned(), operator },
+                quoted: true,
+            });
+            shellstring_to_string(&string, &mut env, &registry)
+        };
+        let word = |word: &str| ShellString::from(word);
+
+        assert_eq!(Ok("19".to_owned()), expand("FILE", ExpansionOperator::Length));
+        assert_eq!(Ok("".to_owned()), expand("EMPTY", ExpansionOperator::Default { word: word("x"), if_null: false }));
+        assert_eq!(Ok("x".to_owned()), expand("EMPTY", ExpansionOperator::Default { word: word("x"), if_null: true }));
+        assert_eq!(Ok("tmp/archive.tar.gz".to_owned()), expand("FILE", ExpansionOperator::RemovePrefix { pattern: word("*/"), longest: false }));
+        assert_eq!(Ok("archive.tar.gz".to_owned()), expand("FILE", ExpansionOperator::RemovePrefix { pattern: word("*/"), longest: true }));
+        assert_eq!(Ok("/tmp/archive.tar".to_owned()), expand("FILE", ExpansionOperator::RemoveSuffix { pattern: word(".*"), longest: false }));
+        assert_eq!(Ok("/tmp/archive".to_owned()), expand("FILE", ExpansionOperator::RemoveSuffix { pattern: word(".*"), longest: true }));
+        assert_eq!(
+            Ok("_tmp/archive.tar.gz".to_owned()),
+            expand("FILE", ExpansionOperator::Replace { pattern: word("/"), replacement: word("_"), all: false })
+        );
+        assert_eq!(
+            Ok("/tmp/archive-tar-gz".to_owned()),
+            expand("FILE", ExpansionOperator::Replace { pattern: word("."), replacement: word("-"), all: true })
+        );
+        assert_eq!(
+            Err(Error::Unset { name: "NOPE".to_owned(), message: "".to_owned() }),
+            expand("NOPE", ExpansionOperator::Error { word: ShellString::from(Vec::new()), if_null: false })
+        );
+        assert_eq!(Ok("new".to_owned()), expand("NOPE", ExpansionOperator::Assign { word: word("new"), if_null: true }));
+        assert_eq!(Ok("new".to_owned()), expand("NOPE", ExpansionOperator::Error { word: word("unset"), if_null: true }));
+    }
+
     fn project_root() -> PathBuf {
         let bin = env::current_exe().expect("exe path");
         let mut target_dir = PathBuf::from(bin.parent().expect("bin parent"));
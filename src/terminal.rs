@@ -0,0 +1,140 @@
+//! Control of the shell's terminal.
+//!
+//! An interactive shell owns its controlling terminal, and hands it to whichever job is running
+//! in the foreground. Only the foreground process group can read from the terminal, and it's the
+//! group that receives signals from keys like Ctrl-C and Ctrl-Z.
+//!
+use command::{
+    self,
+    Pid,
+};
+
+use libc;
+
+use std::{
+    io,
+    mem,
+    os::unix::io::RawFd,
+};
+
+/// The settings of a terminal, such as whether or not input is echoed.
+///
+pub type Modes = libc::termios;
+
+/// The controlling terminal of an interactive shell.
+///
+pub struct Terminal {
+    fd: RawFd,
+    shell_pid: Pid,
+    shell_pgid: Pid,
+    shell_modes: Modes,
+}
+
+impl Terminal {
+    /// Takes control of the terminal attached to stdin, if there is one.
+    ///
+    /// If the shell was started in the background, this waits until it's brought into the
+    /// foreground. The shell is then moved into its own process group, and stops reacting to the
+    /// job control signals that are meant for foreground jobs.
+    ///
+    pub fn acquire() -> io::Result<Option<Terminal>> {
+        if unsafe { libc::isatty(0) } == 0 {
+            return Ok(None);
+        }
+
+        // Keep a descriptor for the terminal that won't be affected by redirections, and won't
+        // be inherited by commands
+        let fd = unsafe { libc::fcntl(0, libc::F_DUPFD_CLOEXEC, 10) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        loop {
+            let pgid = unsafe { libc::getpgrp() };
+            if unsafe { libc::tcgetpgrp(fd) } == pgid {
+                break;
+            }
+            unsafe { libc::kill(-pgid, libc::SIGTTIN) };
+        }
+
+        command::ignore_job_control_signals();
+
+        let shell_pid = unsafe { libc::getpid() };
+        if unsafe { libc::getpgrp() } != shell_pid && unsafe { libc::setpgid(0, 0) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let terminal = Terminal {
+            fd,
+            shell_pid,
+            shell_pgid: shell_pid,
+            shell_modes: modes(fd)?,
+        };
+        terminal.set_foreground_group(shell_pid)?;
+        Ok(Some(terminal))
+    }
+
+    /// Returns the file descriptor of the terminal.
+    ///
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Returns whether or not this process is the shell that acquired the terminal.
+    ///
+    /// Copies of the shell made by forking share the terminal, but shouldn't take it from the
+    /// shell that started them.
+    ///
+    pub fn is_owner(&self) -> bool {
+        unsafe { libc::getpid() == self.shell_pid }
+    }
+
+    /// Gives the terminal to the given process group, using the job's own `modes` if it had any.
+    ///
+    pub fn give_to(&self, pgid: Pid, modes: Option<&Modes>) -> io::Result<()> {
+        if let Some(modes) = modes {
+            set_modes(self.fd, modes)?;
+        }
+        self.set_foreground_group(pgid)
+    }
+
+    /// Takes the terminal back for the shell and restores the shell's modes.
+    ///
+    /// Returns the modes that the foreground job left the terminal in, so that they can be
+    /// restored if the job is continued later.
+    ///
+    pub fn reclaim(&self) -> io::Result<Modes> {
+        self.set_foreground_group(self.shell_pgid)?;
+        let job_modes = modes(self.fd)?;
+        set_modes(self.fd, &self.shell_modes)?;
+        Ok(job_modes)
+    }
+
+    /// Makes the given process group the foreground group of this terminal.
+    ///
+    fn set_foreground_group(&self, pgid: Pid) -> io::Result<()> {
+        if unsafe { libc::tcsetpgrp(self.fd, pgid) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Returns the current modes of the terminal `fd`.
+///
+fn modes(fd: RawFd) -> io::Result<Modes> {
+    let mut modes: Modes = unsafe { mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut modes) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(modes)
+}
+
+/// Changes the modes of the terminal `fd`, once any pending output has been written.
+///
+fn set_modes(fd: RawFd, modes: &Modes) -> io::Result<()> {
+    if unsafe { libc::tcsetattr(fd, libc::TCSADRAIN, modes) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}